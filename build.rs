@@ -0,0 +1,96 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{write::GzEncoder, Compression};
+use sha2::{Digest, Sha256, Sha384};
+
+// Embed every file of `public/` into the binary. Each file
+// gets a content-hashed name, a subresource integrity and
+// gzip/brotli variants compressed once at build time.
+fn main() {
+    println!("cargo:rerun-if-changed=public");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let mut files = Vec::new();
+    collect_files(Path::new("public"), &mut files);
+    files.sort();
+
+    let mut entries = String::new();
+
+    for (index, file) in files.iter().enumerate() {
+        let content = fs::read(file).unwrap();
+        let path = file
+            .strip_prefix("public")
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        let hash: String = Sha256::digest(&content)
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let integrity = format!("sha384-{}", STANDARD.encode(Sha384::digest(&content)));
+        let content_type = mime_guess::from_path(file)
+            .first_or_octet_stream()
+            .to_string();
+
+        let gzip_file = out_dir.join(format!("asset_{}.gz", index));
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::best());
+        gzip.write_all(&content).unwrap();
+        fs::write(&gzip_file, gzip.finish().unwrap()).unwrap();
+
+        let brotli_file = out_dir.join(format!("asset_{}.br", index));
+        let mut brotli = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+        brotli.write_all(&content).unwrap();
+        fs::write(&brotli_file, brotli.into_inner()).unwrap();
+
+        entries.push_str(&format!(
+            "Asset {{ path: {:?}, hashed_path: {:?}, hash: {:?}, integrity: {:?}, content_type: {:?}, raw: include_bytes!({:?}), gzip: include_bytes!({:?}), brotli: include_bytes!({:?}) }},\n",
+            path,
+            hashed_path(&path, &hash),
+            hash,
+            integrity,
+            content_type,
+            fs::canonicalize(file).unwrap(),
+            gzip_file,
+            brotli_file,
+        ));
+    }
+
+    fs::write(
+        out_dir.join("assets.rs"),
+        format!("static ASSETS: &[Asset] = &[\n{}];\n", entries),
+    )
+    .unwrap();
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if !path.ends_with(".gitignore") {
+            files.push(path);
+        }
+    }
+}
+
+/// `css/app.css` becomes `css/app.<hash>.css`.
+fn hashed_path(path: &str, hash: &str) -> String {
+    return match path.rsplit_once('.') {
+        Some((stem, extension)) if !stem.ends_with('/') => {
+            format!("{}.{}.{}", stem, hash, extension)
+        }
+        _ => format!("{}.{}", path, hash),
+    };
+}
@@ -0,0 +1,13 @@
+-- Add migration script here
+create table personal_access_tokens (
+	id int unsigned auto_increment primary key,
+	user_id int unsigned not null,
+	name varchar(255) not null,
+	token char(64) not null unique,
+	scopes text not null,
+	expires_at timestamp null,
+	last_used_at timestamp null,
+	last_used_ip varchar(45) null,
+	created_at timestamp default current_timestamp,
+	foreign key (user_id) references users(id) on delete cascade
+);
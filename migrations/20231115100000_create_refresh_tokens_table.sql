@@ -0,0 +1,13 @@
+-- Add migration script here
+create table refresh_tokens (
+	id int unsigned auto_increment primary key,
+	user_id int unsigned not null,
+	family varchar(64) not null,
+	token char(64) not null unique,
+	replaced_by int unsigned null,
+	revoked_at timestamp null,
+	expires_at timestamp not null,
+	created_at timestamp default current_timestamp,
+	index (family),
+	foreign key (user_id) references users(id) on delete cascade
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+create table oauth_clients (
+	id varchar(64) primary key,
+	secret char(64) null,
+	name varchar(255) not null,
+	redirect_uris text not null,
+	grant_types varchar(255) not null,
+	scopes text not null,
+	created_at timestamp default current_timestamp
+);
@@ -0,0 +1,14 @@
+-- Add migration script here
+create table oauth_authorization_codes (
+	id char(64) primary key,
+	client_id varchar(64) not null,
+	user_id int unsigned not null,
+	redirect_uri text not null,
+	scopes text not null,
+	code_challenge varchar(128) null,
+	code_challenge_method varchar(10) null,
+	expires_at timestamp not null,
+	created_at timestamp default current_timestamp,
+	foreign key (client_id) references oauth_clients(id) on delete cascade,
+	foreign key (user_id) references users(id) on delete cascade
+);
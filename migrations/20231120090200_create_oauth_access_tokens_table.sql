@@ -0,0 +1,12 @@
+-- Add migration script here
+create table oauth_access_tokens (
+	id char(64) primary key,
+	client_id varchar(64) not null,
+	user_id int unsigned null,
+	scopes text not null,
+	revoked_at timestamp null,
+	expires_at timestamp not null,
+	created_at timestamp default current_timestamp,
+	foreign key (client_id) references oauth_clients(id) on delete cascade,
+	foreign key (user_id) references users(id) on delete cascade
+);
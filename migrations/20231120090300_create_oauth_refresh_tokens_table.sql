@@ -0,0 +1,13 @@
+-- Add migration script here
+create table oauth_refresh_tokens (
+	id char(64) primary key,
+	access_token_id char(64) not null,
+	client_id varchar(64) not null,
+	user_id int unsigned not null,
+	scopes text not null,
+	revoked_at timestamp null,
+	expires_at timestamp not null,
+	created_at timestamp default current_timestamp,
+	foreign key (client_id) references oauth_clients(id) on delete cascade,
+	foreign key (user_id) references users(id) on delete cascade
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+alter table oauth_clients
+	add column post_logout_redirect_uris text null after redirect_uris;
+
+alter table oauth_authorization_codes
+	add column nonce varchar(255) null after code_challenge_method;
@@ -0,0 +1,11 @@
+-- Add migration script here
+create table user_identities (
+	id int unsigned auto_increment primary key,
+	user_id int unsigned not null,
+	provider varchar(255) not null,
+	subject varchar(255) not null,
+	email varchar(255) null,
+	created_at timestamp default current_timestamp,
+	unique (provider, subject),
+	foreign key (user_id) references users(id) on delete cascade
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+create table saml_identity_providers (
+	id int unsigned auto_increment primary key,
+	tenant varchar(64) not null,
+	slug varchar(64) not null,
+	name varchar(255) not null,
+	metadata mediumtext not null,
+	email_attribute varchar(255) null,
+	username_attribute varchar(255) null,
+	created_at timestamp default current_timestamp,
+	unique (tenant, slug)
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+create table email_changes (
+	id int unsigned auto_increment primary key,
+	user_id int unsigned not null unique,
+	email varchar(255) not null,
+	token char(64) not null unique,
+	expires_at timestamp not null,
+	created_at timestamp default current_timestamp,
+	foreign key (user_id) references users(id) on delete cascade
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+alter table users
+	add column deleted_at timestamp null after updated_at;
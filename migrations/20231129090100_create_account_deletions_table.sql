@@ -0,0 +1,7 @@
+-- Add migration script here
+create table account_deletions (
+	id int unsigned auto_increment primary key,
+	user_id int unsigned not null,
+	requested_at timestamp not null,
+	purged_at timestamp default current_timestamp
+);
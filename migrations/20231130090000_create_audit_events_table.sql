@@ -0,0 +1,15 @@
+-- Add migration script here
+create table audit_events (
+	id bigint unsigned auto_increment primary key,
+	event varchar(64) not null,
+	outcome varchar(16) not null,
+	user_id int unsigned null,
+	username varchar(255) null,
+	ip varchar(45) null,
+	user_agent varchar(512) null,
+	metadata text null,
+	created_at timestamp not null default current_timestamp,
+	index (event),
+	index (user_id),
+	index (created_at)
+);
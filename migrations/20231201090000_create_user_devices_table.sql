@@ -0,0 +1,14 @@
+-- Add migration script here
+create table user_devices (
+	id int unsigned auto_increment primary key,
+	user_id int unsigned not null,
+	fingerprint char(64) not null,
+	device varchar(255) not null,
+	ip_range varchar(64) null,
+	alert_token char(64) null unique,
+	alert_expires_at timestamp null,
+	first_seen_at timestamp not null default current_timestamp,
+	last_seen_at timestamp not null default current_timestamp,
+	unique (user_id, fingerprint),
+	foreign key (user_id) references users(id) on delete cascade
+);
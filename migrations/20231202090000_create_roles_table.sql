@@ -0,0 +1,9 @@
+-- Add migration script here
+create table roles (
+	id int unsigned auto_increment primary key,
+	name varchar(64) not null unique,
+	description varchar(255) not null,
+	created_at timestamp default current_timestamp
+);
+
+insert into roles (name, description) values ('administrator', 'Manages the users and reviews the audit log');
@@ -0,0 +1,10 @@
+-- Add migration script here
+create table permissions (
+	id int unsigned auto_increment primary key,
+	name varchar(64) not null unique,
+	description varchar(255) not null
+);
+
+insert into permissions (name, description) values
+	('users.manage', 'Assign roles to the users'),
+	('audit.view', 'Browse and export the audit log');
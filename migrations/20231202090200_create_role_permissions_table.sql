@@ -0,0 +1,11 @@
+-- Add migration script here
+create table role_permissions (
+	role_id int unsigned not null,
+	permission_id int unsigned not null,
+	primary key (role_id, permission_id),
+	foreign key (role_id) references roles(id) on delete cascade,
+	foreign key (permission_id) references permissions(id) on delete cascade
+);
+
+insert into role_permissions (role_id, permission_id)
+	select roles.id, permissions.id from roles, permissions where roles.name = 'administrator';
@@ -0,0 +1,9 @@
+-- Add migration script here
+create table user_roles (
+	user_id int unsigned not null,
+	role_id int unsigned not null,
+	created_at timestamp default current_timestamp,
+	primary key (user_id, role_id),
+	foreign key (user_id) references users(id) on delete cascade,
+	foreign key (role_id) references roles(id) on delete cascade
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+alter table user_identities
+	add column username varchar(255) null after subject;
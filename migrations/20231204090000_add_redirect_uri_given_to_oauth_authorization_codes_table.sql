@@ -0,0 +1,3 @@
+-- Add migration script here
+alter table oauth_authorization_codes
+	add column redirect_uri_given boolean not null default false after redirect_uri;
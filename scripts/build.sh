@@ -1,4 +1,6 @@
 #!/usr/bin/env bash
 
+./scripts/copy_vendor_assets.sh
+
 cargo watch -w ./src/ -w ./resources/ -s "npx tailwindcss -i ./resources/css/app.css -o ./public/css/app.css && cargo run"
 
@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+
+# Serve htmx and morphdom from public/ instead of a CDN.
+mkdir -p ./public/js/vendor
+
+cp ./node_modules/htmx.org/dist/htmx.min.js ./public/js/vendor/htmx.min.js
+cp ./node_modules/htmx.org/dist/ext/morphdom-swap.js ./public/js/vendor/morphdom-swap.js
+cp ./node_modules/morphdom/dist/morphdom-umd.min.js ./public/js/vendor/morphdom-umd.min.js
@@ -0,0 +1,31 @@
+#!/usr/bin/env bash
+
+# Create a new key pair signing the JWT access tokens.
+# Usage: ./scripts/generate_jwt_key.sh [eddsa|rs256]
+#
+# The newest key signs the tokens. To retire a key, delete its
+# private half (<kid>.<alg>.pem) and keep the public one until
+# the tokens it signed have expired.
+
+ALGORITHM=${1:-eddsa}
+KEYS_DIR=${JWT_KEYS_DIR:-./keys/jwt}
+KID=$(date -u +"%Y%m%d%H%M%S")
+
+mkdir -p "$KEYS_DIR"
+
+case "$ALGORITHM" in
+	eddsa)
+		openssl genpkey -algorithm ed25519 -out "$KEYS_DIR/$KID.eddsa.pem"
+		;;
+	rs256)
+		openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048 -out "$KEYS_DIR/$KID.rs256.pem"
+		;;
+	*)
+		echo >&2 "Unsupported algorithm: $ALGORITHM"
+		exit 1
+		;;
+esac
+
+openssl pkey -in "$KEYS_DIR/$KID.$ALGORITHM.pem" -pubout -out "$KEYS_DIR/$KID.$ALGORITHM.pub.pem"
+
+echo "Created key $KID in $KEYS_DIR"
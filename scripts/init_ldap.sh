@@ -0,0 +1,46 @@
+#!/usr/bin/env bash
+
+# Run a directory to log in with `CREDENTIAL_PROVIDER=ldap`,
+# also used by the LDAP tests (`cargo test -- --ignored`).
+#
+# LDAP_URL=ldap://localhost:1389
+# LDAP_BIND_DN=cn=admin,dc=example,dc=org
+# LDAP_BIND_PASSWORD=adminpassword
+# LDAP_BASE_DN=ou=people,dc=example,dc=org
+
+RUNNING_CONTAINER=$(docker ps --filter "name=openldap" --format "{{.ID}}")
+if [ -n "$RUNNING_CONTAINER" ]; then
+	echo >&2 "OpenLDAP is already running."
+	echo >&2 "To kill it, run: docker kill $RUNNING_CONTAINER"
+	exit 1
+fi
+
+docker run -p "1389:1389" --name openldap \
+	-e LDAP_ROOT=dc=example,dc=org \
+	-e LDAP_ADMIN_USERNAME=admin \
+	-e LDAP_ADMIN_PASSWORD=adminpassword \
+	-d bitnami/openldap:2.6
+
+# purpose: wait for the directory to be up and running.
+until docker exec openldap ldapsearch -x -H ldap://localhost:1389 -b dc=example,dc=org > /dev/null 2>&1; do
+	>&2 echo "OpenLDAP is still unavailable - sleeping"
+	sleep 1
+done
+
+# The user of the tests, whose uid is not a valid username.
+docker exec -i openldap ldapadd -x -H ldap://localhost:1389 -D cn=admin,dc=example,dc=org -w adminpassword <<LDIF
+dn: ou=people,dc=example,dc=org
+objectClass: organizationalUnit
+ou: people
+
+dn: uid=jane.doe,ou=people,dc=example,dc=org
+objectClass: inetOrgPerson
+uid: jane.doe
+cn: Jane Doe
+sn: Doe
+mail: jane@example.org
+userPassword: secret
+LDIF
+
+# Show success message.
+echo "OpenLDAP is up and running on port 1389"
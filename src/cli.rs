@@ -0,0 +1,367 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::http::{
+    add_role, build_data_export, create_oauth_client, create_saml_provider, export_audit_events,
+    parse_date, redis_pool, validate_saml_metadata, AuditFilter, NewOAuthClient, NewSamlProvider,
+    SessionLimits, ADMINISTRATOR, OAUTH_GRANT_TYPES, OAUTH_SCOPES,
+};
+use clap::{builder::PossibleValuesParser, Parser, Subcommand};
+use sqlx::MySqlPool;
+
+#[derive(Parser, Debug)]
+#[command(about = "AuthKit authentication server")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Start the web server. This is what runs without a command.
+    Serve,
+
+    /// Register an application allowed to obtain tokens from us.
+    #[command(name = "oauth:client")]
+    OAuthClient {
+        /// The name shown to the users on the consent screen.
+        #[arg(long)]
+        name: String,
+
+        /// Where the users are sent back to. Can be repeated.
+        #[arg(long = "redirect-uri")]
+        redirect_uris: Vec<String>,
+
+        /// Where the users may be sent back to after logging
+        /// out from the client. Can be repeated.
+        #[arg(long = "post-logout-redirect-uri")]
+        post_logout_redirect_uris: Vec<String>,
+
+        /// The grant types the client may use. Can be repeated.
+        #[arg(
+            long = "grant",
+            value_parser = PossibleValuesParser::new(OAUTH_GRANT_TYPES),
+            default_values = ["authorization_code", "refresh_token"],
+        )]
+        grant_types: Vec<String>,
+
+        /// The scopes the client may ask for. Can be repeated,
+        /// all of them by default.
+        #[arg(long = "scope", value_parser = PossibleValuesParser::new(OAUTH_SCOPES.map(|(scope, _)| scope)))]
+        scopes: Vec<String>,
+
+        /// Register a public client, e.g. a single page or
+        /// native app. It gets no secret and must use PKCE.
+        #[arg(long)]
+        public: bool,
+    },
+
+    /// Register a SAML identity provider users can sign in with.
+    #[command(name = "saml:provider")]
+    SamlProvider {
+        /// The customer the provider belongs to, by its short
+        /// name in our URLs, e.g. `acme`.
+        #[arg(long)]
+        tenant: String,
+
+        /// The short name of the provider in our URLs, unique
+        /// within the tenant, e.g. `okta`.
+        #[arg(long)]
+        slug: String,
+
+        /// The name shown to the users.
+        #[arg(long)]
+        name: String,
+
+        /// The file holding the metadata of the provider.
+        #[arg(long)]
+        metadata: PathBuf,
+
+        /// The attribute holding the email address, when it is
+        /// not one of the usual ones.
+        #[arg(long)]
+        email_attribute: Option<String>,
+
+        /// The attribute holding the username, when it is not
+        /// one of the usual ones.
+        #[arg(long)]
+        username_attribute: Option<String>,
+    },
+
+    /// Write everything stored about a user into a ZIP archive,
+    /// to answer a data subject access request. Accounts deleted
+    /// within the grace period are exported too, their data is
+    /// kept until they are purged.
+    #[command(name = "user:export")]
+    ExportUserData {
+        /// The username of the user.
+        #[arg(long)]
+        username: String,
+
+        /// Where to write the archive.
+        #[arg(long)]
+        output: PathBuf,
+    },
+
+    /// Print the audit events as JSON Lines, the most recent
+    /// first. The filters can be combined.
+    #[command(name = "audit:export")]
+    ExportAuditEvents {
+        /// Only the events of this kind, e.g. `login`.
+        #[arg(long)]
+        event: Option<String>,
+
+        /// Only the events with this outcome.
+        #[arg(long, value_parser = PossibleValuesParser::new(["success", "failure"]))]
+        outcome: Option<String>,
+
+        /// Only the events of the user with this username.
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Only the events of requests from this IP address.
+        #[arg(long)]
+        ip: Option<String>,
+
+        /// Only the events from this day on, e.g. `2023-11-30`.
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only the events until this day, included.
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Write the events to this file instead.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Give a role to a user, e.g. to make the first
+    /// administrator, who can then assign the roles from the
+    /// admin pages.
+    #[command(name = "user:assign-role")]
+    AssignRole {
+        /// The username of the user.
+        #[arg(long)]
+        username: String,
+
+        /// The name of the role.
+        #[arg(long, default_value = ADMINISTRATOR)]
+        role: String,
+    },
+}
+
+/// Run the command and print its outcome. Errors are
+/// returned, so they end up in the exit status.
+pub async fn run(db: &MySqlPool, command: Command) -> Result<(), String> {
+    return match command {
+        Command::Serve => Ok(()),
+        Command::OAuthClient {
+            name,
+            redirect_uris,
+            post_logout_redirect_uris,
+            grant_types,
+            scopes,
+            public,
+        } => {
+            create_client(
+                db,
+                NewOAuthClient {
+                    name,
+                    redirect_uris,
+                    post_logout_redirect_uris,
+                    grant_types,
+                    scopes,
+                    confidential: !public,
+                },
+            )
+            .await
+        }
+        Command::SamlProvider {
+            tenant,
+            slug,
+            name,
+            metadata,
+            email_attribute,
+            username_attribute,
+        } => {
+            let metadata = fs::read_to_string(&metadata)
+                .map_err(|e| format!("{}: {}", metadata.display(), e))?;
+
+            register_saml_provider(
+                db,
+                NewSamlProvider {
+                    tenant,
+                    slug,
+                    name,
+                    metadata,
+                    email_attribute,
+                    username_attribute,
+                },
+            )
+            .await
+        }
+        Command::ExportUserData { username, output } => {
+            export_user_data(db, &username, &output).await
+        }
+        Command::ExportAuditEvents {
+            event,
+            outcome,
+            user,
+            ip,
+            since,
+            until,
+            output,
+        } => {
+            let filter = AuditFilter {
+                event,
+                outcome,
+                username: user,
+                ip,
+                since: since.as_deref().map(parse_date).transpose()?,
+                until: until
+                    .as_deref()
+                    .map(parse_date)
+                    .transpose()?
+                    .map(|until| until + time::Duration::days(1)),
+                before_id: None,
+            };
+
+            export_audit(db, filter, output.as_deref()).await
+        }
+        Command::AssignRole { username, role } => assign_role(db, &username, &role).await,
+    };
+}
+
+async fn create_client(db: &MySqlPool, mut client: NewOAuthClient) -> Result<(), String> {
+    let uses_grant = |grant: &str| client.grant_types.iter().any(|g| g == grant);
+
+    if uses_grant("authorization_code") && client.redirect_uris.is_empty() {
+        return Err("The authorization_code grant needs at least one --redirect-uri.".to_string());
+    }
+
+    // The redirect URIs are compared as a whole, and the
+    // fragment would be lost by the browser anyway.
+    if let Some(uri) = client
+        .redirect_uris
+        .iter()
+        .chain(client.post_logout_redirect_uris.iter())
+        .find(|uri| !uri.contains("://") || uri.contains('#') || uri.contains(' '))
+    {
+        return Err(format!("Invalid redirect URI: {}", uri));
+    }
+
+    if !client.confidential && uses_grant("client_credentials") {
+        return Err("Public clients can not use the client_credentials grant.".to_string());
+    }
+
+    if client.scopes.is_empty() {
+        client.scopes = OAUTH_SCOPES.map(|(scope, _)| scope.to_string()).to_vec();
+    }
+
+    let (id, secret) = create_oauth_client(db, &client)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    println!("Client ID:     {}", id);
+    if let Some(secret) = secret {
+        println!("Client secret: {}", secret);
+        println!("Store the secret now, it can not be shown again.");
+    }
+
+    return Ok(());
+}
+
+async fn register_saml_provider(db: &MySqlPool, provider: NewSamlProvider) -> Result<(), String> {
+    let is_slug = |value: &str| {
+        !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    };
+
+    if !is_slug(&provider.tenant) || !is_slug(&provider.slug) {
+        return Err(
+            "The tenant and the slug may only contain lowercase letters, digits and dashes."
+                .to_string(),
+        );
+    }
+
+    validate_saml_metadata(&provider.metadata)?;
+
+    create_saml_provider(db, &provider)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    // The provider has to be set up with our side as well.
+    let app_url = env::var("APP_URL").unwrap_or("http://localhost:3000".to_string());
+    let path = format!("{}/saml/{}/{}", app_url, provider.tenant, provider.slug);
+    println!("Metadata: {}/metadata", path);
+    println!("Login:    {}/login", path);
+    println!("Tenant:   {}/saml/{}", app_url, provider.tenant);
+
+    return Ok(());
+}
+
+async fn export_user_data(db: &MySqlPool, username: &str, output: &Path) -> Result<(), String> {
+    let user = sqlx::query!("select id from users where username = ?", username)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No user is named {}.", username))?;
+
+    let limits = SessionLimits::from_env()?;
+    let archive = build_data_export(db, &redis_pool(), &limits, user.id)
+        .await
+        .map_err(|e| format!("{:?}", e))?
+        .ok_or_else(|| format!("No user is named {}.", username))?;
+
+    fs::write(output, archive).map_err(|e| format!("{}: {}", output.display(), e))?;
+    println!("Archive written to {}", output.display());
+
+    return Ok(());
+}
+
+async fn export_audit(
+    db: &MySqlPool,
+    filter: AuditFilter,
+    output: Option<&Path>,
+) -> Result<(), String> {
+    let lines = export_audit_events(db, filter)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    return match output {
+        Some(output) => {
+            fs::write(output, lines).map_err(|e| format!("{}: {}", output.display(), e))
+        }
+        None => {
+            print!("{}", lines);
+            Ok(())
+        }
+    };
+}
+
+async fn assign_role(db: &MySqlPool, username: &str, role: &str) -> Result<(), String> {
+    let user = sqlx::query!(
+        "select id from users where username = ? and deleted_at is null",
+        username
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("No user is named {}.", username))?;
+
+    if !add_role(db, user.id, role)
+        .await
+        .map_err(|e| format!("{:?}", e))?
+    {
+        return Err(format!("No role is named {}.", role));
+    }
+
+    println!("{} now has the {} role.", username, role);
+
+    return Ok(());
+}
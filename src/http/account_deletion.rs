@@ -0,0 +1,202 @@
+use std::{env, sync::OnceLock};
+
+use super::{
+    audit::{Audit, AuditEvent},
+    error::ApplicationError,
+    user_cache, user_session,
+};
+use redis_pool::SingleRedisPool;
+use sqlx::MySqlPool;
+use time::{Duration, OffsetDateTime};
+
+/// How often the server looks for accounts to purge.
+const PURGE_INTERVAL_SECONDS: u64 = 60 * 60;
+
+/// How long a deleted account can still be restored by
+/// logging in, from `ACCOUNT_DELETION_GRACE_DAYS` (30 days by
+/// default).
+pub fn grace_period() -> Duration {
+    static GRACE_PERIOD: OnceLock<Duration> = OnceLock::new();
+
+    return *GRACE_PERIOD.get_or_init(|| {
+        let days = env::var("ACCOUNT_DELETION_GRACE_DAYS")
+            .ok()
+            .and_then(|days| days.parse::<i64>().ok())
+            .unwrap_or(30);
+
+        return Duration::days(days);
+    });
+}
+
+/// Mark the account as deleted. It must not be used until it
+/// is restored, so the user is logged out everywhere and the
+/// tokens issued to them are revoked.
+pub async fn schedule(
+    db: &MySqlPool,
+    redis: &SingleRedisPool,
+    user_id: u32,
+) -> Result<(), ApplicationError> {
+    let mut transaction = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update users set deleted_at = current_timestamp where id = ? and deleted_at is null",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "delete from personal_access_tokens where user_id = ?",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update refresh_tokens set revoked_at = current_timestamp where user_id = ? and revoked_at is null",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "delete from oauth_authorization_codes where user_id = ?",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update oauth_access_tokens set revoked_at = current_timestamp where user_id = ? and revoked_at is null",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update oauth_refresh_tokens set revoked_at = current_timestamp where user_id = ? and revoked_at is null",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    user_cache::forget(redis, user_id).await?;
+    user_session::revoke_others(redis, user_id, None).await?;
+
+    return Ok(());
+}
+
+/// Cancel the deletion of the account, returning whether it
+/// was deleted. Called whenever the user logs in.
+pub async fn restore(db: &MySqlPool, user_id: u32) -> Result<bool, ApplicationError> {
+    let result = sqlx::query!(
+        "update users set deleted_at = null where id = ? and deleted_at is not null",
+        user_id
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(result.rows_affected() == 1);
+}
+
+/// Remove the accounts deleted longer ago than the grace
+/// period, along with everything that belongs to them. Only
+/// the id of the user and the dates are kept, as a record
+/// that the account was deleted on request.
+pub async fn purge(db: &MySqlPool, redis: &SingleRedisPool) -> Result<u64, ApplicationError> {
+    let deleted_before = OffsetDateTime::now_utc() - grace_period();
+
+    let records = sqlx::query!(
+        "select id, deleted_at as `deleted_at!` from users where deleted_at < ?",
+        deleted_before
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let audit = Audit::system(db.clone());
+    let mut purged = 0;
+    for record in records {
+        let mut transaction = db
+            .begin()
+            .await
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        // The tokens, identities and pending email changes go
+        // with the user, through their foreign keys.
+        let deleted = sqlx::query!(
+            "delete from users where id = ? and deleted_at < ?",
+            record.id,
+            deleted_before
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+        .rows_affected()
+            == 1;
+
+        // The user logged in again in the meantime.
+        if !deleted {
+            continue;
+        }
+
+        sqlx::query!(
+            "insert into account_deletions (user_id, requested_at) values (?, ?)",
+            record.id,
+            record.deleted_at
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        user_cache::forget(redis, record.id).await?;
+        user_session::revoke_others(redis, record.id, None).await?;
+
+        // The events of the user are kept, they have no foreign
+        // key to the users table.
+        audit
+            .record(AuditEvent::success("account.purged").user(record.id))
+            .await?;
+
+        purged += 1;
+    }
+
+    return Ok(purged);
+}
+
+/// Purge the deleted accounts every hour, for as long as the
+/// server runs.
+pub async fn purge_periodically(db: MySqlPool, redis: SingleRedisPool) {
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECONDS));
+
+    loop {
+        interval.tick().await;
+
+        match purge(&db, &redis).await {
+            Ok(0) => {}
+            Ok(purged) => println!("Purged {} deleted accounts", purged),
+            Err(error) => println!("Server error: {:?}", error),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::http::{
+    audit::{self, parse_date, AuditFilter},
+    error::ApplicationError,
+    utils::deserialize_empty_string_as_none,
+    AppContext,
+};
+use crate::view::admin::{audit_page, AuditFilterForm};
+use axum::{
+    extract::{Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use maud::Markup;
+use serde::{Deserialize, Serialize};
+
+/// The number of events per page.
+const PAGE_SIZE: u32 = 50;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/audit", get(index))
+        .route("/audit/export", get(export));
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+struct AuditQuery {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcome: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    since: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    until: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before: Option<u64>,
+}
+
+impl AuditQuery {
+    /// Dates that can not be read are left out of the filter,
+    /// the form shows them as they were typed.
+    fn filter(&self) -> AuditFilter {
+        return AuditFilter {
+            event: self.event.clone(),
+            outcome: self.outcome.clone(),
+            username: self.user.clone(),
+            ip: self.ip.clone(),
+            since: self.since.as_deref().and_then(|v| parse_date(v).ok()),
+            until: self
+                .until
+                .as_deref()
+                .and_then(|v| parse_date(v).ok())
+                .map(|until| until + time::Duration::days(1)),
+            before_id: self.before,
+        };
+    }
+
+    fn to_query_string(&self) -> String {
+        return serde_urlencoded::to_string(self).unwrap_or_default();
+    }
+}
+
+async fn index(
+    State(AppContext { db, .. }): State<AppContext>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Markup, ApplicationError> {
+    let records = audit::search(&db, &query.filter(), PAGE_SIZE).await?;
+
+    let older = records
+        .last()
+        .filter(|_| records.len() == PAGE_SIZE as usize)
+        .map(|last| {
+            let older = AuditQuery {
+                before: Some(last.id),
+                ..query.clone()
+            };
+            format!("/admin/audit?{}", older.to_query_string())
+        });
+    let export = format!(
+        "/admin/audit/export?{}",
+        AuditQuery {
+            before: None,
+            ..query.clone()
+        }
+        .to_query_string()
+    );
+
+    let form = AuditFilterForm {
+        event: query.event.as_deref().unwrap_or(""),
+        outcome: query.outcome.as_deref().unwrap_or(""),
+        user: query.user.as_deref().unwrap_or(""),
+        ip: query.ip.as_deref().unwrap_or(""),
+        since: query.since.as_deref().unwrap_or(""),
+        until: query.until.as_deref().unwrap_or(""),
+    };
+
+    return Ok(audit_page(&records, &form, older.as_deref(), &export));
+}
+
+/// Every matching event as JSON Lines, e.g. to feed them to
+/// another tool.
+async fn export(
+    State(AppContext { db, .. }): State<AppContext>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Response, ApplicationError> {
+    let lines = audit::export(&db, query.filter()).await?;
+
+    return Ok((
+        [
+            (CONTENT_TYPE, "application/x-ndjson"),
+            (
+                CONTENT_DISPOSITION,
+                r#"attachment; filename="audit-events.jsonl""#,
+            ),
+        ],
+        lines,
+    )
+        .into_response());
+}
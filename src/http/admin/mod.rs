@@ -0,0 +1,17 @@
+use super::{
+    middleware::RequirePermission,
+    role::{MANAGE_USERS, VIEW_AUDIT_LOG},
+    AppContext,
+};
+use axum::Router;
+
+mod audit;
+mod users;
+
+/// The pages of the administrators. Each one requires the
+/// permission it is about.
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .merge(audit::router().route_layer(RequirePermission(VIEW_AUDIT_LOG)))
+        .merge(users::router().route_layer(RequirePermission(MANAGE_USERS)));
+}
@@ -0,0 +1,98 @@
+use crate::http::{
+    audit::{Audit, AuditEvent},
+    error::ApplicationError,
+    extractor::{HxRequest, RequiredUser},
+    flash::Flash,
+    middleware::RequireSudo,
+    response::redirect,
+    role, user_cache,
+    utils::deserialize_empty_string_as_none,
+    AppContext,
+};
+use crate::view::admin::users_page;
+use axum::{
+    extract::{Path, Query, State},
+    response::Response,
+    routing::{get, post},
+    Form, Router,
+};
+use maud::Markup;
+use serde::Deserialize;
+
+/// The number of users shown at once, the others are found
+/// by searching.
+const PAGE_SIZE: u32 = 50;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/users", get(index)).route(
+        "/users/:id/roles",
+        post(update_roles).route_layer(RequireSudo::new()),
+    );
+}
+
+#[derive(Deserialize, Debug)]
+struct UsersQuery {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    search: Option<String>,
+}
+
+async fn index(
+    State(AppContext { db, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    Query(query): Query<UsersQuery>,
+) -> Result<Markup, ApplicationError> {
+    let users = role::users(&db, query.search.as_deref(), PAGE_SIZE).await?;
+    let roles = role::all(&db).await?;
+
+    return Ok(users_page(
+        &users,
+        &roles,
+        query.search.as_deref().unwrap_or(""),
+        user.id,
+    ));
+}
+
+/// The checked roles replace the roles of the user. The
+/// administrators can not change their own roles, so they
+/// do not lock themselves out by mistake.
+async fn update_roles(
+    State(AppContext { db, redis, .. }): State<AppContext>,
+    RequiredUser(admin): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    flash: Flash,
+    audit: Audit,
+    Path(id): Path<u32>,
+    Form(pairs): Form<Vec<(String, String)>>,
+) -> Result<Response, ApplicationError> {
+    if id == admin.id {
+        flash.error("You cannot change your own roles.");
+        return Ok(redirect(is_htmx, "/admin/users"));
+    }
+
+    let Some(user) = user_cache::find(&redis, &db, id).await? else {
+        flash.error("The user no longer exists.");
+        return Ok(redirect(is_htmx, "/admin/users"));
+    };
+
+    let role_ids = pairs
+        .iter()
+        .filter(|(key, _)| key == "roles")
+        .filter_map(|(_, value)| value.parse::<u32>().ok())
+        .collect::<Vec<_>>();
+
+    role::assign(&db, user.id, &role_ids).await?;
+
+    let roles = role::grants(&db, user.id).await?.roles;
+    audit
+        .record(
+            AuditEvent::success("roles.assign")
+                .user(user.id)
+                .with("roles", roles)
+                .with("by", admin.id),
+        )
+        .await?;
+
+    flash.success(format!("The roles of {} have been saved.", user.username));
+
+    return Ok(redirect(is_htmx, "/admin/users"));
+}
@@ -0,0 +1,154 @@
+use crate::http::{
+    account_deletion,
+    audit::{Audit, AuditEvent},
+    authentication::{
+        login::{attempt, invalid_credentials, log_in},
+        register::create_user,
+    },
+    check_email::email_errors,
+    check_username::username_errors,
+    error::{ErrorBag, ProblemDetails},
+    extractor::{JwtUser, ValidatedJson},
+    middleware::{csrf_token, Auth, User},
+    user_cache, AppContext, LoginAttempRequest, RegisterRequest,
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use serde::Serialize;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/register", post(register))
+        .route("/login", post(login))
+        .route("/logout", post(logout))
+        .route("/me", get(me))
+        .route("/csrf-token", get(csrf));
+}
+
+#[derive(Serialize)]
+struct CsrfTokenResponse {
+    csrf_token: String,
+}
+
+/// The CSRF token of the session, for the clients logged in
+/// with a cookie to send in the `X-CSRF-Token` header.
+async fn csrf() -> Json<CsrfTokenResponse> {
+    return Json(CsrfTokenResponse {
+        csrf_token: csrf_token(),
+    });
+}
+
+async fn register(
+    State(AppContext { db, redis, .. }): State<AppContext>,
+    audit: Audit,
+    ValidatedJson(request): ValidatedJson<RegisterRequest>,
+) -> Result<(StatusCode, Json<User>), ProblemDetails> {
+    // The HTML form checks the availability while typing,
+    // API clients get the same checks on submission.
+    let mut errors = ErrorBag::new();
+
+    let username = username_errors(&db, request.username.as_deref()).await?;
+    if !username.is_empty() {
+        errors.insert("username".to_string(), username);
+    }
+
+    let email = email_errors(&db, request.email.as_deref()).await?;
+    if !email.is_empty() {
+        errors.insert("email".to_string(), email);
+    }
+
+    if !errors.is_empty() {
+        return Err(ProblemDetails::validation(errors));
+    }
+
+    let user_id = create_user(&db, &request).await?;
+    audit
+        .record(
+            AuditEvent::success("register")
+                .user(user_id)
+                .with("via", "api"),
+        )
+        .await?;
+    let user = user_cache::find(&redis, &db, user_id)
+        .await?
+        .ok_or_else(|| ProblemDetails::new(StatusCode::INTERNAL_SERVER_ERROR, "User not found"))?;
+
+    return Ok((StatusCode::CREATED, Json(user)));
+}
+
+async fn login(
+    session: Session<SessionRedisPool>,
+    State(AppContext {
+        db,
+        redis,
+        credentials,
+        ..
+    }): State<AppContext>,
+    audit: Audit,
+    ValidatedJson(request): ValidatedJson<LoginAttempRequest>,
+) -> Result<Json<User>, ProblemDetails> {
+    let Some(authenticated) = attempt(credentials.as_ref(), &request).await? else {
+        audit
+            .record(
+                AuditEvent::failure("login")
+                    .username(request.username.as_deref().unwrap_or_default())
+                    .with("via", "api"),
+            )
+            .await?;
+        return Err(
+            ProblemDetails::new(StatusCode::UNAUTHORIZED, "Invalid credentials")
+                .kind("/problems/invalid-credentials")
+                .errors(invalid_credentials()),
+        );
+    };
+
+    audit
+        .record(
+            AuditEvent::success("login")
+                .user(authenticated.id)
+                .with("via", "api"),
+        )
+        .await?;
+
+    if account_deletion::restore(&db, authenticated.id).await? {
+        audit
+            .record(AuditEvent::success("account.restored").user(authenticated.id))
+            .await?;
+    }
+
+    log_in(&session, &authenticated);
+
+    let user = user_cache::find(&redis, &db, authenticated.id)
+        .await?
+        .ok_or_else(|| ProblemDetails::new(StatusCode::INTERNAL_SERVER_ERROR, "User not found"))?;
+
+    return Ok(Json(user));
+}
+
+async fn logout(session: Session<SessionRedisPool>) -> StatusCode {
+    session.clear();
+    session.renew();
+
+    return StatusCode::NO_CONTENT;
+}
+
+async fn me(auth: Auth, jwt: Option<JwtUser>) -> Result<Json<User>, ProblemDetails> {
+    if let Some(JwtUser(user)) = jwt {
+        return Ok(Json(user));
+    }
+
+    if auth.check() && !auth.can("profile:read") {
+        return Err(ProblemDetails::new(StatusCode::FORBIDDEN, "Forbidden")
+            .detail("The access token is missing the profile:read scope."));
+    }
+
+    return match auth.get_user().await? {
+        Some(user) => Ok(Json(user.clone())),
+        None => Err(ProblemDetails::unauthenticated()),
+    };
+}
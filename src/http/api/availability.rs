@@ -0,0 +1,51 @@
+use crate::http::{
+    check_email::{email_errors, CheckEmailRequest},
+    check_username::{username_errors, CheckUsernameRequest},
+    error::ProblemDetails,
+    AppContext,
+};
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/check-username", get(check_username))
+        .route("/check-email", get(check_email));
+}
+
+#[derive(Serialize)]
+struct Availability {
+    available: bool,
+    errors: Vec<String>,
+}
+
+impl Availability {
+    fn new(errors: Vec<String>) -> Self {
+        return Self {
+            available: errors.is_empty(),
+            errors,
+        };
+    }
+}
+
+async fn check_username(
+    State(AppContext { db, .. }): State<AppContext>,
+    Query(request): Query<CheckUsernameRequest>,
+) -> Result<Json<Availability>, ProblemDetails> {
+    let errors = username_errors(&db, request.username.as_deref()).await?;
+
+    return Ok(Json(Availability::new(errors)));
+}
+
+async fn check_email(
+    State(AppContext { db, .. }): State<AppContext>,
+    Query(request): Query<CheckEmailRequest>,
+) -> Result<Json<Availability>, ProblemDetails> {
+    let errors = email_errors(&db, request.email.as_deref()).await?;
+
+    return Ok(Json(Availability::new(errors)));
+}
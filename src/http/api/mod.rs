@@ -0,0 +1,99 @@
+use super::AppContext;
+use axum::Router;
+
+mod authentication;
+mod availability;
+mod token;
+
+/// JSON versions of the authentication endpoints, for the
+/// clients that can not use the HTML fragments.
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .merge(authentication::router())
+        .merge(availability::router())
+        .merge(token::router());
+}
+
+/// The JSON API driven through the whole middleware stack,
+/// as its clients use it.
+#[cfg(test)]
+mod tests {
+    use crate::http::testing::{app_with, body_json, context, create_user, Browser};
+    use axum::{
+        body::Body,
+        http::{header::CONTENT_TYPE, Request, StatusCode},
+    };
+    use serde_json::json;
+    use sqlx::MySqlPool;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    async fn browser(db: MySqlPool) -> Browser {
+        create_user(&db, "janedoe", PASSWORD).await;
+
+        return Browser::new(app_with(context(db)).await);
+    }
+
+    fn post_json(uri: &str) -> axum::http::request::Builder {
+        return Request::post(uri).header(CONTENT_TYPE, "application/json");
+    }
+
+    fn credentials() -> Body {
+        return Body::from(json!({ "username": "janedoe", "password": PASSWORD }).to_string());
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_logs_in_with_the_csrf_token_of_the_session(db: MySqlPool) {
+        let mut browser = browser(db).await;
+
+        let refused = browser
+            .send(post_json("/api/v1/login"), credentials())
+            .await;
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+        assert_eq!(refused.headers()[CONTENT_TYPE], "application/problem+json");
+
+        let token = browser.csrf_token().await;
+        let request = post_json("/api/v1/login").header("X-CSRF-Token", token);
+        let response = browser.send(request, credentials()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_json(response).await["username"], "janedoe");
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_does_not_read_the_csrf_token_of_a_json_body(db: MySqlPool) {
+        let mut browser = browser(db).await;
+
+        let token = browser.csrf_token().await;
+        let body = json!({ "username": "janedoe", "password": PASSWORD, "_token": token });
+        let response = browser
+            .send(post_json("/api/v1/login"), Body::from(body.to_string()))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_issues_and_refreshes_tokens_without_csrf_token(db: MySqlPool) {
+        let mut browser = browser(db).await;
+
+        let response = browser
+            .send(post_json("/api/v1/token"), credentials())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let tokens = body_json(response).await;
+        assert_eq!(tokens["token_type"], "Bearer");
+
+        let body = json!({ "refresh_token": tokens["refresh_token"] });
+        let response = browser
+            .send(
+                post_json("/api/v1/token/refresh"),
+                Body::from(body.to_string()),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::http::{
+    account_deletion,
+    audit::{Audit, AuditEvent},
+    authentication::login::attempt,
+    error::{ApplicationError, ProblemDetails},
+    extractor::ValidatedJson,
+    refresh_token::{self, Rotation},
+    utils::deserialize_empty_string_as_none,
+    AppContext, LoginAttempRequest,
+};
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/token", post(store))
+        .route("/token/refresh", post(refresh))
+        .route("/token/revoke", post(revoke));
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+    refresh_token: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct RefreshTokenRequest {
+    #[validate(required(message = "This field is required."))]
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    refresh_token: Option<String>,
+}
+
+fn invalid_grant(detail: &str) -> ProblemDetails {
+    return ProblemDetails::new(StatusCode::UNAUTHORIZED, "Invalid grant")
+        .kind("/problems/invalid-grant")
+        .detail(detail);
+}
+
+fn token_response(
+    context: &AppContext,
+    user_id: u32,
+    refresh_token: String,
+) -> Result<Json<TokenResponse>, ProblemDetails> {
+    let (access_token, expires_in) = context
+        .jwt
+        .issue_access_token(user_id)
+        .map_err(|e| ProblemDetails::from(ApplicationError::ServerError(e)))?;
+
+    return Ok(Json(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in,
+        refresh_token,
+    }));
+}
+
+async fn store(
+    State(context): State<AppContext>,
+    audit: Audit,
+    ValidatedJson(request): ValidatedJson<LoginAttempRequest>,
+) -> Result<Json<TokenResponse>, ProblemDetails> {
+    let Some(user) = attempt(context.credentials.as_ref(), &request).await? else {
+        audit
+            .record(
+                AuditEvent::failure("login")
+                    .username(request.username.as_deref().unwrap_or_default())
+                    .with("via", "token"),
+            )
+            .await?;
+        return Err(invalid_grant("Invalid username or password."));
+    };
+
+    audit
+        .record(
+            AuditEvent::success("login")
+                .user(user.id)
+                .with("via", "token"),
+        )
+        .await?;
+
+    if account_deletion::restore(&context.db, user.id).await? {
+        audit
+            .record(AuditEvent::success("account.restored").user(user.id))
+            .await?;
+    }
+
+    let refresh_token = refresh_token::issue(&context.db, user.id).await?;
+
+    return token_response(&context, user.id, refresh_token);
+}
+
+async fn refresh(
+    State(context): State<AppContext>,
+    ValidatedJson(request): ValidatedJson<RefreshTokenRequest>,
+) -> Result<Json<TokenResponse>, ProblemDetails> {
+    let token = request.refresh_token.unwrap();
+
+    return match refresh_token::rotate(&context.db, &token).await? {
+        Rotation::Rotated { user_id, token } => token_response(&context, user_id, token),
+        Rotation::Invalid => Err(invalid_grant(
+            "The refresh token is invalid or has expired.",
+        )),
+        Rotation::Reused => Err(invalid_grant(
+            "The refresh token has already been used. All tokens issued with it have been revoked.",
+        )),
+    };
+}
+
+async fn revoke(
+    State(AppContext { db, .. }): State<AppContext>,
+    ValidatedJson(request): ValidatedJson<RefreshTokenRequest>,
+) -> Result<StatusCode, ProblemDetails> {
+    refresh_token::revoke(&db, &request.refresh_token.unwrap()).await?;
+
+    return Ok(StatusCode::NO_CONTENT);
+}
@@ -0,0 +1,241 @@
+use super::AppContext;
+use axum::{
+    extract::Path,
+    http::{
+        header::{
+            ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH,
+            VARY,
+        },
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+
+/// The encodings the assets are compressed in, from the
+/// most preferred.
+const ENCODINGS: [&str; 2] = ["br", "gzip"];
+
+/// A file of `public/` embedded into the binary by `build.rs`.
+pub struct Asset {
+    pub path: &'static str,
+    pub hashed_path: &'static str,
+    pub hash: &'static str,
+    pub integrity: &'static str,
+    pub content_type: &'static str,
+    pub raw: &'static [u8],
+    pub gzip: &'static [u8],
+    pub brotli: &'static [u8],
+}
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/public/*path", get(serve));
+}
+
+/// Find the embedded asset by its original path,
+/// e.g. `css/app.css`.
+pub fn find(path: &str) -> Option<&'static Asset> {
+    return ASSETS.iter().find(|asset| asset.path == path);
+}
+
+/// The public url of the asset, with the content hash
+/// in the filename so it can be cached forever.
+pub fn asset(path: &str) -> String {
+    return match find(path) {
+        Some(asset) => format!("/public/{}", asset.hashed_path),
+        None => format!("/public/{}", path),
+    };
+}
+
+async fn serve(Path(path): Path<String>, headers: HeaderMap) -> Response {
+    // Only the hashed filenames are immutable. The original
+    // names are still served, but must be revalidated.
+    let (asset, cache_control) = match ASSETS.iter().find(|asset| asset.hashed_path == path) {
+        Some(asset) => (asset, "public, max-age=31536000, immutable"),
+        None => match find(&path) {
+            Some(asset) => (asset, "public, no-cache"),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+    };
+
+    return respond(asset, cache_control, &headers);
+}
+
+/// Answer with the encoding of the asset the client prefers,
+/// or with "not modified" when it has it already.
+fn respond(asset: &Asset, cache_control: &str, headers: &HeaderMap) -> Response {
+    let accept_encoding = headers
+        .get(ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    let mut encoding = None;
+    let mut best = 0.0;
+    for candidate in ENCODINGS {
+        let quality = quality(accept_encoding, candidate);
+        if quality > best {
+            encoding = Some(candidate);
+            best = quality;
+        }
+    }
+
+    // Each encoding is a different representation, so it
+    // gets its own ETag.
+    let (content, etag) = match encoding {
+        Some("br") => (asset.brotli, format!("\"{}-br\"", asset.hash)),
+        Some("gzip") => (asset.gzip, format!("\"{}-gzip\"", asset.hash)),
+        _ => (asset.raw, format!("\"{}\"", asset.hash)),
+    };
+
+    let is_fresh = headers
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag || tag == "*")
+        })
+        .unwrap_or(false);
+
+    if is_fresh {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [
+                (ETAG, etag),
+                (CACHE_CONTROL, cache_control.to_string()),
+                (VARY, "Accept-Encoding".to_string()),
+            ],
+        )
+            .into_response();
+    }
+
+    let mut response = (
+        [
+            (CONTENT_TYPE, asset.content_type.to_string()),
+            (CACHE_CONTROL, cache_control.to_string()),
+            (ETAG, etag),
+            (VARY, "Accept-Encoding".to_string()),
+        ],
+        content,
+    )
+        .into_response();
+
+    if let Some(encoding) = encoding {
+        response
+            .headers_mut()
+            .insert(CONTENT_ENCODING, encoding.parse().unwrap());
+    }
+
+    return response;
+}
+
+/// The quality the `Accept-Encoding` header gives to the
+/// encoding, or else to `*`. A quality of 0 refuses it.
+fn quality(accept_encoding: &str, encoding: &str) -> f32 {
+    let mut wildcard = 0.0;
+
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or("").trim();
+        let quality = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+            .unwrap_or(1.0);
+
+        if coding.eq_ignore_ascii_case(encoding) {
+            return quality;
+        }
+
+        if coding == "*" {
+            wildcard = quality;
+        }
+    }
+
+    return wildcard;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ASSET: Asset = Asset {
+        path: "css/app.css",
+        hashed_path: "css/app.0123456789abcdef.css",
+        hash: "0123456789abcdef",
+        integrity: "sha384-",
+        content_type: "text/css",
+        raw: b"raw",
+        gzip: b"gzip",
+        brotli: b"brotli",
+    };
+
+    fn get(headers: &[(&'static str, &'static str)]) -> Response {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(*name, value.parse().unwrap());
+        }
+
+        return respond(&ASSET, "public, no-cache", &header_map);
+    }
+
+    fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+        return response.headers().get(name).and_then(|v| v.to_str().ok());
+    }
+
+    #[test]
+    fn it_serves_the_preferred_encoding() {
+        let response = get(&[("accept-encoding", "gzip, deflate, br")]);
+
+        assert_eq!(header(&response, "content-encoding"), Some("br"));
+        assert_eq!(header(&response, "etag"), Some("\"0123456789abcdef-br\""));
+    }
+
+    #[test]
+    fn it_gives_each_encoding_its_own_etag() {
+        let br = get(&[("accept-encoding", "br")]);
+        let gzip = get(&[("accept-encoding", "gzip")]);
+        let identity = get(&[]);
+
+        assert_eq!(header(&gzip, "etag"), Some("\"0123456789abcdef-gzip\""));
+        assert_eq!(header(&identity, "etag"), Some("\"0123456789abcdef\""));
+        assert_eq!(header(&identity, "content-encoding"), None);
+        assert_ne!(header(&br, "etag"), header(&gzip, "etag"));
+    }
+
+    #[test]
+    fn it_skips_the_encodings_refused_with_a_zero_quality() {
+        let gzip = get(&[("accept-encoding", "br;q=0, gzip")]);
+        let identity = get(&[("accept-encoding", "br;q=0, gzip;q=0.0")]);
+        let wildcard = get(&[("accept-encoding", "*;q=0, identity")]);
+
+        assert_eq!(header(&gzip, "content-encoding"), Some("gzip"));
+        assert_eq!(header(&identity, "content-encoding"), None);
+        assert_eq!(header(&wildcard, "content-encoding"), None);
+    }
+
+    #[test]
+    fn it_prefers_the_encoding_of_higher_quality() {
+        let response = get(&[("accept-encoding", "br;q=0.5, gzip;q=0.8")]);
+
+        assert_eq!(header(&response, "content-encoding"), Some("gzip"));
+    }
+
+    #[test]
+    fn it_answers_not_modified_for_the_etag_of_the_same_encoding() {
+        let br = get(&[
+            ("accept-encoding", "br"),
+            ("if-none-match", "\"0123456789abcdef-br\""),
+        ]);
+        let gzip = get(&[
+            ("accept-encoding", "gzip"),
+            ("if-none-match", "\"0123456789abcdef-br\""),
+        ]);
+
+        assert_eq!(br.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(gzip.status(), StatusCode::OK);
+        assert_eq!(header(&gzip, "content-encoding"), Some("gzip"));
+    }
+}
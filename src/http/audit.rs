@@ -0,0 +1,274 @@
+use std::{convert::Infallible, net::IpAddr};
+
+use super::{error::ApplicationError, extractor::client_ip, AppContext};
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header::USER_AGENT, request::Parts},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::MySqlPool;
+use time::{macros::format_description, Date, OffsetDateTime};
+
+/// The number of characters of the user agents we keep.
+const USER_AGENT_LENGTH: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+        };
+    }
+}
+
+/// Something that happened to an account, such as a login
+/// or a password change, to be kept in the audit log.
+#[derive(Debug)]
+pub struct AuditEvent {
+    event: &'static str,
+    outcome: Outcome,
+    user_id: Option<u32>,
+    username: Option<String>,
+    metadata: Map<String, Value>,
+}
+
+impl AuditEvent {
+    pub fn success(event: &'static str) -> Self {
+        return Self::new(event, Outcome::Success);
+    }
+
+    pub fn failure(event: &'static str) -> Self {
+        return Self::new(event, Outcome::Failure);
+    }
+
+    fn new(event: &'static str, outcome: Outcome) -> Self {
+        return Self {
+            event,
+            outcome,
+            user_id: None,
+            username: None,
+            metadata: Map::new(),
+        };
+    }
+
+    /// The user who acted, or whose account was acted upon.
+    pub fn user(mut self, user_id: u32) -> Self {
+        self.user_id = Some(user_id);
+        return self;
+    }
+
+    /// The username that was given when no user is known,
+    /// e.g. for a failed login.
+    pub fn username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        return self;
+    }
+
+    /// Add the detail to the metadata of the event.
+    pub fn with(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.metadata.insert(key.to_string(), value.into());
+        return self;
+    }
+}
+
+/// Records audit events along with where the request they
+/// happened in came from.
+#[derive(Clone)]
+pub struct Audit {
+    db: MySqlPool,
+    ip: Option<IpAddr>,
+    user_agent: Option<String>,
+}
+
+impl Audit {
+    /// Record events that do not come from a request, e.g.
+    /// the ones of the background tasks.
+    pub fn system(db: MySqlPool) -> Self {
+        return Self {
+            db,
+            ip: None,
+            user_agent: None,
+        };
+    }
+
+    pub async fn record(&self, event: AuditEvent) -> Result<(), ApplicationError> {
+        let metadata = if event.metadata.is_empty() {
+            None
+        } else {
+            Some(Value::Object(event.metadata).to_string())
+        };
+
+        sqlx::query!(
+            "insert into audit_events (event, outcome, user_id, username, ip, user_agent, metadata)
+            values (?, ?, ?, ?, ?, ?, ?)",
+            event.event,
+            event.outcome.as_str(),
+            event.user_id,
+            event.username,
+            self.ip.map(|ip| ip.to_string()),
+            self.user_agent,
+            metadata
+        )
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppContext> for Audit {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        context: &AppContext,
+    ) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.chars().take(USER_AGENT_LENGTH).collect());
+
+        return Ok(Self {
+            db: context.db.clone(),
+            ip: client_ip(&parts.extensions),
+            user_agent,
+        });
+    }
+}
+
+/// A recorded event, as shown to the administrators and
+/// exported.
+#[derive(Serialize, Debug)]
+pub struct AuditRecord {
+    pub id: u64,
+    pub event: String,
+    pub outcome: String,
+    pub user_id: Option<u32>,
+    /// The current username of the user, or the username
+    /// that was given when no user is known.
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: Option<Value>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+/// Which events to look for. Unset fields match any event.
+#[derive(Default, Debug)]
+pub struct AuditFilter {
+    pub event: Option<String>,
+    pub outcome: Option<String>,
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+    /// Only the events older than this one, to page through
+    /// the results.
+    pub before_id: Option<u64>,
+}
+
+/// The latest events matching the filter, the most recent
+/// first.
+pub async fn search(
+    db: &MySqlPool,
+    filter: &AuditFilter,
+    limit: u32,
+) -> Result<Vec<AuditRecord>, ApplicationError> {
+    let records = sqlx::query!(
+        "select audit_events.id, audit_events.event, audit_events.outcome, audit_events.user_id,
+            coalesce(users.username, audit_events.username) as username, audit_events.ip,
+            audit_events.user_agent, audit_events.metadata, audit_events.created_at
+        from audit_events
+        left join users on users.id = audit_events.user_id
+        where (? is null or audit_events.event = ?)
+            and (? is null or audit_events.outcome = ?)
+            and (? is null or users.username = ? or audit_events.username = ?)
+            and (? is null or audit_events.ip = ?)
+            and (? is null or audit_events.created_at >= ?)
+            and (? is null or audit_events.created_at < ?)
+            and (? is null or audit_events.id < ?)
+        order by audit_events.id desc
+        limit ?",
+        filter.event,
+        filter.event,
+        filter.outcome,
+        filter.outcome,
+        filter.username,
+        filter.username,
+        filter.username,
+        filter.ip,
+        filter.ip,
+        filter.since,
+        filter.since,
+        filter.until,
+        filter.until,
+        filter.before_id,
+        filter.before_id,
+        limit
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(records
+        .into_iter()
+        .map(|record| AuditRecord {
+            id: record.id,
+            event: record.event,
+            outcome: record.outcome,
+            user_id: record.user_id,
+            username: record.username,
+            ip: record.ip,
+            user_agent: record.user_agent,
+            metadata: record
+                .metadata
+                .and_then(|metadata| serde_json::from_str(&metadata).ok()),
+            created_at: record.created_at,
+        })
+        .collect());
+}
+
+/// Every event matching the filter as JSON Lines, one event
+/// per line, the most recent first.
+pub async fn export(db: &MySqlPool, filter: AuditFilter) -> Result<String, ApplicationError> {
+    const PAGE_SIZE: u32 = 500;
+
+    let mut filter = filter;
+    let mut lines = String::new();
+    loop {
+        let records = search(db, &filter, PAGE_SIZE).await?;
+
+        for record in &records {
+            let line = serde_json::to_string(record)
+                .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+
+        match records.last() {
+            Some(last) if records.len() == PAGE_SIZE as usize => filter.before_id = Some(last.id),
+            _ => return Ok(lines),
+        }
+    }
+}
+
+/// Read a day such as `2023-11-30`, as its first instant in
+/// UTC.
+pub fn parse_date(value: &str) -> Result<OffsetDateTime, String> {
+    let date = Date::parse(value, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| format!("{} is not a date such as 2023-11-30", value))?;
+
+    return Ok(date.midnight().assume_utc());
+}
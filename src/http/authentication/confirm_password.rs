@@ -0,0 +1,86 @@
+use super::login::{attempt, intended_url, LoginAttempRequest};
+use crate::http::{
+    audit::{Audit, AuditEvent},
+    error::ApplicationError,
+    extractor::RequiredUser,
+    utils::deserialize_empty_string_as_none,
+    AppContext,
+};
+use crate::view::authentication::confirm_password_page;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Form, Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use maud::Markup;
+use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
+
+/// The key of the session holding when the user last entered
+/// their password, as a unix timestamp.
+const CONFIRMED_AT: &str = "_sudo_at";
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/confirm-password", get(show).post(store));
+}
+
+#[derive(Deserialize, Debug)]
+struct ConfirmPasswordRequest {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    password: Option<String>,
+}
+
+/// Record that the user has just entered their password.
+pub fn confirm(session: &Session<SessionRedisPool>) {
+    session.set(CONFIRMED_AT, OffsetDateTime::now_utc().unix_timestamp());
+}
+
+/// Whether the user entered their password within the window.
+pub fn is_fresh(session: &Session<SessionRedisPool>, window: Duration) -> bool {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    return session
+        .get::<i64>(CONFIRMED_AT)
+        .is_some_and(|confirmed_at| now - confirmed_at < window.whole_seconds());
+}
+
+async fn show(RequiredUser(_): RequiredUser) -> Markup {
+    return confirm_password_page(None);
+}
+
+async fn store(
+    State(AppContext { credentials, .. }): State<AppContext>,
+    session: Session<SessionRedisPool>,
+    audit: Audit,
+    RequiredUser(user): RequiredUser,
+    Form(request): Form<ConfirmPasswordRequest>,
+) -> Result<Response, ApplicationError> {
+    // The password is checked the same way as on the login
+    // page, against the account of the logged-in user. The
+    // directory may know them by another username.
+    let user_id = user.id;
+    let attempt_request = LoginAttempRequest {
+        username: Some(credentials.login_name(user_id, &user.username).await?),
+        password: request.password,
+    };
+
+    if attempt(credentials.as_ref(), &attempt_request)
+        .await?
+        .filter(|user| user.id == user_id)
+        .is_none()
+    {
+        audit
+            .record(AuditEvent::failure("password.confirm").user(user_id))
+            .await?;
+        return Ok(confirm_password_page(Some("The password is incorrect.")).into_response());
+    }
+
+    audit
+        .record(AuditEvent::success("password.confirm").user(user_id))
+        .await?;
+    confirm(&session);
+
+    return Ok(Redirect::to(&intended_url(&session)).into_response());
+}
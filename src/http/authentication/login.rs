@@ -1,82 +1,50 @@
-use super::AppContext;
+use super::{confirm_password, login_alert, AppContext};
 use crate::{
     http::{
-        error::{ApplicationError, ErrorBag, RenderErrorsAsHtml},
-        extractor::ValidatedForm,
+        account_deletion,
+        audit::{Audit, AuditEvent},
+        credentials::CredentialProvider,
+        error::{ApplicationError, ErrorBag, ProblemDetails, RenderErrorsAsHtml},
+        extractor::{ClientIp, HxRequest, Validated},
+        flash::Flash,
+        login_device,
+        response::redirect,
+        user_cache,
+        user_session::SESSION_KEY,
         utils::deserialize_empty_string_as_none,
     },
-    view::authentication::{login_form, login_page},
+    view::authentication::{login_form, login_page, login_page_with_errors},
 };
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use axum::{
-    body::BoxBody,
     extract::State,
-    http::{HeaderMap, StatusCode},
+    http::{header::USER_AGENT, HeaderMap},
     response::IntoResponse,
     routing::get,
     Router,
 };
 use axum_session::{Session, SessionRedisPool};
-use cookie::Cookie;
 use maud::Markup;
 use serde::Deserialize;
-use time::{self, Duration};
 use validator::Validate;
 
-pub fn router() -> Router<AppContext> {
-    return Router::new().route("/login", get(get_login_page).post(store));
-}
-
-struct LoginResponse<'a> {
-    deleting_cookie: Cookie<'a>,
-    content: Markup,
-}
-
-impl<'a> IntoResponse for LoginResponse<'a> {
-    fn into_response(self) -> axum::http::Response<BoxBody> {
-        let mut response_header = HeaderMap::new();
-
-        response_header.insert(
-            "Set-Cookie",
-            self.deleting_cookie.encoded().to_string().parse().unwrap(),
-        );
-
-        return (response_header, self.content).into_response();
-    }
-}
+const INTENDED_URL: &str = "intended_url";
 
-impl<'a> LoginResponse<'a> {
-    fn new(content: Markup) -> Self {
-        return Self {
-            deleting_cookie: Cookie::build("successfully_registered")
-                .max_age(Duration::ZERO)
-                .build(),
-            content,
-        };
-    }
-}
+/// Shown to the users who log in while their account is
+/// waiting to be purged.
+pub const ACCOUNT_RESTORED: &str = "Welcome back! Your account is no longer going to be deleted.";
 
-async fn get_login_page(headers: HeaderMap) -> impl IntoResponse {
-    let successfully_registered = headers
-        .get_all("cookie")
-        .into_iter()
-        .filter_map(|v| v.to_str().ok())
-        .flat_map(|v| v.split("; "))
-        .filter_map(|v| Cookie::parse_encoded(v).ok())
-        .find(|v| v.name() == "successfully_registered")
-        .map(|v| v.value().parse::<bool>().unwrap());
-
-    return LoginResponse::new(login_page(successfully_registered.unwrap_or(false)));
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/login", get(login_page).post(store));
 }
 
 #[derive(Deserialize, Validate, Debug)]
 pub struct LoginAttempRequest {
     #[validate(required(message = "This field is required."))]
-    #[serde(deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
     pub username: Option<String>,
 
     #[validate(required(message = "This field is required."))]
-    #[serde(deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
     pub password: Option<String>,
 }
 
@@ -84,53 +52,163 @@ impl RenderErrorsAsHtml for LoginAttempRequest {
     fn render(&self, errors: &ErrorBag) -> Markup {
         return login_form(Some(self), Some(errors));
     }
+
+    fn render_page(&self, errors: &ErrorBag) -> Markup {
+        return login_page_with_errors(Some(self), Some(errors));
+    }
 }
 
 async fn store(
     session: Session<SessionRedisPool>,
-    State(AppContext { db }): State<AppContext>,
-    ValidatedForm(request): ValidatedForm<LoginAttempRequest>,
+    flash: Flash,
+    audit: Audit,
+    HxRequest(is_htmx): HxRequest,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    State(AppContext {
+        db,
+        redis,
+        credentials,
+        mailer,
+        ..
+    }): State<AppContext>,
+    Validated(request): Validated<LoginAttempRequest>,
 ) -> Result<impl IntoResponse, ApplicationError> {
-    // Find the user by username.
-    let result = sqlx::query!(
-        "select id, username, password from users where username = ?",
-        request.username
-    )
-    .fetch_optional(&db)
-    .await
-    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
-
-    // If the user exists, then we will verify
-    // the password from the request with hashed
-    // one stored in the database.
-    if let Some(record) = result {
-        let password_hash = PasswordHash::new(&record.password)
-            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
-
-        let result = Argon2::default().verify_password(
-            request.password.as_ref().unwrap().as_bytes(),
-            &password_hash,
-        );
-
-        // If everything ok, then we will create
-        // a logged-in session for the user. And
-        // redirect the user to the home page.
-        if result.is_ok() {
-            session.renew();
-            session.set("user_id", record.id);
-            session.set("username", record.username);
-            return Ok((StatusCode::SEE_OTHER, [("HX-Location", "/home")]).into_response());
+    // The provider may refuse the user with the reason,
+    // e.g. when the account it would create already exists.
+    let user = match attempt(credentials.as_ref(), &request).await {
+        Err(ApplicationError::Problem(ProblemDetails {
+            errors: Some(errors),
+            ..
+        })) => {
+            audit
+                .record(
+                    AuditEvent::failure("login")
+                        .username(request.username.as_deref().unwrap_or("")),
+                )
+                .await?;
+
+            return Ok(if is_htmx {
+                login_form(Some(&request), Some(&errors)).into_response()
+            } else {
+                login_page_with_errors(Some(&request), Some(&errors)).into_response()
+            });
         }
+        result => result?,
     };
 
+    // If everything ok, then we will create
+    // a logged-in session for the user. And
+    // redirect the user to the page they were
+    // heading to, or else to the home page.
+    if let Some(user) = user {
+        audit
+            .record(AuditEvent::success("login").user(user.id))
+            .await?;
+
+        if account_deletion::restore(&db, user.id).await? {
+            audit
+                .record(AuditEvent::success("account.restored").user(user.id))
+                .await?;
+            flash.success(ACCOUNT_RESTORED);
+        }
+
+        // The user is told about the logins from devices they
+        // have not used before, in case it was not them.
+        let user_agent = headers
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        if let Some(device) = login_device::remember(&db, user.id, user_agent, ip).await? {
+            audit
+                .record(
+                    AuditEvent::success("login.new_device")
+                        .user(user.id)
+                        .with("device", device.device.as_str())
+                        .with("ip_range", device.ip_range.as_deref()),
+                )
+                .await?;
+
+            if let Some(account) = user_cache::find(&redis, &db, user.id).await? {
+                login_alert::notify(&mailer, &account.email, &account.username, device);
+            }
+        }
+
+        let to = intended_url(&session);
+        log_in(&session, &user);
+        return Ok(redirect(is_htmx, &to));
+    }
+
     // In case the user does not exist or the verification
     // of the password failed, then we will return the
     // form with the errors.
+    audit
+        .record(AuditEvent::failure("login").username(request.username.as_deref().unwrap_or("")))
+        .await?;
+
+    let errors = invalid_credentials();
+
+    return Ok(if is_htmx {
+        login_form(Some(&request), Some(&errors)).into_response()
+    } else {
+        login_page_with_errors(Some(&request), Some(&errors)).into_response()
+    });
+}
+
+pub struct AuthenticatedUser {
+    pub id: u32,
+    pub username: String,
+}
+
+/// Check the credentials of the request with the
+/// configured provider, returning the user they belong to.
+pub async fn attempt(
+    credentials: &dyn CredentialProvider,
+    request: &LoginAttempRequest,
+) -> Result<Option<AuthenticatedUser>, ApplicationError> {
+    let (Some(username), Some(password)) = (&request.username, &request.password) else {
+        return Ok(None);
+    };
+
+    return credentials.verify(username, password).await;
+}
+
+/// Turn the session into a logged-in session of the user.
+/// The session id is renewed to prevent session fixation,
+/// and the session gets a new entry in the index of the
+/// user's sessions once the request is done. Having just
+/// entered the password, the user is not asked for it again
+/// before sensitive actions for a while.
+pub fn log_in(session: &Session<SessionRedisPool>, user: &AuthenticatedUser) {
+    session.renew();
+    session.remove(SESSION_KEY);
+    session.set("user_id", user.id);
+    session.set("username", &user.username);
+    confirm_password::confirm(session);
+}
+
+/// Remember the page a guest was sent to the login page from,
+/// to go back to it once logged in.
+pub fn intend(session: &Session<SessionRedisPool>, url: &str) {
+    session.set(INTENDED_URL, url);
+}
+
+/// The remembered page, unless it is not one of ours, which
+/// would make the login page an open redirect.
+pub fn intended_url(session: &Session<SessionRedisPool>) -> String {
+    return session
+        .get_remove::<String>(INTENDED_URL)
+        .filter(|url| url.starts_with('/') && !url.starts_with("//") && !url.starts_with("/\\"))
+        .unwrap_or("/home".to_string());
+}
+
+pub fn invalid_credentials() -> ErrorBag {
     let mut errors = ErrorBag::new();
     errors.insert(
         "invalid_credentials".to_string(),
         vec!["Invalid username or password".to_string()],
     );
 
-    return Ok(login_form(Some(&request), Some(&errors)).into_response());
+    return errors;
 }
@@ -0,0 +1,126 @@
+use super::{logout::log_out, register::hash_password};
+use crate::http::{
+    audit::{Audit, AuditEvent},
+    error::ApplicationError,
+    extractor::error_bag,
+    flash::Flash,
+    login_device::{self, LoginAlert, NewDevice, ALERT_TTL_DAYS},
+    mailer::Mailer,
+    utils::deserialize_empty_string_as_none,
+    AppContext,
+};
+use crate::view::authentication::login_alert_page;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Form, Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use serde::Deserialize;
+use std::sync::Arc;
+use validator::Validate;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/login-alerts/:token", get(show).post(store));
+}
+
+/// Tell the user about a login from a new device, with a link
+/// to disown it. The email is sent in the background, so it
+/// does not hold up the login.
+pub fn notify(mailer: &Arc<Mailer>, email: &str, username: &str, device: NewDevice) {
+    let body = format!(
+        "Hello {},\n\nYour account was just logged in to from a new device:\n\n{}\n{}\n\nIf it was you, you can ignore this email. If it was not, follow this link within {} days to log out everywhere and choose a new password:\n\n{}\n",
+        username,
+        device.device,
+        device.ip_range.as_deref().unwrap_or("Unknown network"),
+        ALERT_TTL_DAYS,
+        mailer.url(&format!("/login-alerts/{}", device.token))
+    );
+    let (mailer, email) = (mailer.clone(), email.to_string());
+
+    tokio::spawn(async move {
+        if let Err(error) = mailer.send(&email, "New login to your account", body).await {
+            println!("Server error: {:?}", error);
+        }
+    });
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct ResetPasswordRequest {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[validate(required(message = "This field is required."))]
+    password: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[validate(
+        required(message = "This field is required."),
+        must_match(other = "password", message = "Does not match with password field.")
+    )]
+    password_confirmation: Option<String>,
+}
+
+fn invalid_link(flash: &Flash) -> Response {
+    flash.error("The link is invalid or has expired.");
+    return Redirect::to("/login").into_response();
+}
+
+/// The link can be followed from any device, the token alone
+/// tells whose login it was.
+async fn show(
+    State(AppContext { db, .. }): State<AppContext>,
+    flash: Flash,
+    Path(token): Path<String>,
+) -> Result<Response, ApplicationError> {
+    let Some(alert) = login_device::find_alert(&db, &token).await? else {
+        return Ok(invalid_link(&flash));
+    };
+
+    return Ok(login_alert_page(&token, &alert, None).into_response());
+}
+
+/// Nothing happens until the new password is submitted, so
+/// that mail scanners following the link do not lock the
+/// user out.
+async fn store(
+    State(AppContext { db, redis, .. }): State<AppContext>,
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    audit: Audit,
+    Path(token): Path<String>,
+    Form(request): Form<ResetPasswordRequest>,
+) -> Result<Response, ApplicationError> {
+    if let Err(err) = request.validate() {
+        let Some(alert) = login_device::find_alert(&db, &token).await? else {
+            return Ok(invalid_link(&flash));
+        };
+
+        return Ok(login_alert_page(&token, &alert, Some(&error_bag(&err))).into_response());
+    }
+
+    let password_hash = hash_password(request.password.as_deref().unwrap())?;
+
+    let Some(LoginAlert {
+        user_id,
+        device,
+        ip_range,
+        ..
+    }) = login_device::disown(&db, &redis, &token, &password_hash).await?
+    else {
+        return Ok(invalid_link(&flash));
+    };
+
+    audit
+        .record(
+            AuditEvent::success("login.disowned")
+                .user(user_id)
+                .with("device", device)
+                .with("ip_range", ip_range),
+        )
+        .await?;
+
+    log_out(&session);
+    flash.success("Your password has been changed and you have been logged out everywhere. You can now log in with your new password.");
+
+    return Ok(Redirect::to("/login").into_response());
+}
@@ -0,0 +1,40 @@
+use super::AppContext;
+use crate::http::{
+    audit::{Audit, AuditEvent},
+    error::ApplicationError,
+    extractor::HxRequest,
+    flash::Flash,
+    response::redirect,
+};
+use axum::{response::IntoResponse, routing::post, Router};
+use axum_session::{Session, SessionRedisPool};
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/logout", post(logout));
+}
+
+async fn logout(
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    audit: Audit,
+    HxRequest(is_htmx): HxRequest,
+) -> Result<impl IntoResponse, ApplicationError> {
+    if let Some(user_id) = session.get::<u32>("user_id") {
+        audit
+            .record(AuditEvent::success("logout").user(user_id))
+            .await?;
+    }
+
+    log_out(&session);
+    flash.info("You have been logged out.");
+
+    return Ok(redirect(is_htmx, "/login"));
+}
+
+/// Drop everything the session knows about the user and
+/// give it a new id, but keep the session itself so a flash
+/// message survives the redirect.
+pub fn log_out(session: &Session<SessionRedisPool>) {
+    session.clear();
+    session.renew();
+}
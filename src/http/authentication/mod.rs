@@ -1,8 +1,11 @@
 use super::AppContext;
 use axum::Router;
 
-mod login;
-mod register;
+pub(super) mod confirm_password;
+pub(super) mod login;
+pub(super) mod login_alert;
+pub(super) mod logout;
+pub(super) mod register;
 
 pub use login::LoginAttempRequest;
 pub use register::RegisterRequest;
@@ -10,5 +13,8 @@ pub use register::RegisterRequest;
 pub fn router() -> Router<AppContext> {
     return Router::new()
         .merge(register::router())
-        .merge(login::router());
+        .merge(login::router())
+        .merge(login_alert::router())
+        .merge(confirm_password::router())
+        .merge(logout::router());
 }
@@ -1,17 +1,18 @@
+use crate::http::audit::{Audit, AuditEvent};
 use crate::http::error::{ApplicationError, ErrorBag, RenderErrorsAsHtml};
-use crate::http::extractor::ValidatedForm;
+use crate::http::extractor::{HxRequest, Validated};
+use crate::http::flash::Flash;
+use crate::http::response::redirect;
 use crate::http::{utils::deserialize_empty_string_as_none, AppContext};
-use crate::view::authentication::{register_form, register_page};
+use crate::view::authentication::{register_form, register_page, register_page_with_errors};
 use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Argon2, PasswordHasher,
 };
-use axum::http::HeaderMap;
-use axum::response::Response;
 use axum::{extract::State, response::IntoResponse, routing::get, Router};
-use cookie::Cookie;
 use maud::Markup;
 use serde::Deserialize;
+use sqlx::MySqlPool;
 use validator::Validate;
 
 pub fn router() -> Router<AppContext> {
@@ -20,7 +21,7 @@ pub fn router() -> Router<AppContext> {
 
 #[derive(Deserialize, Debug, Validate)]
 pub struct RegisterRequest {
-    #[serde(deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
     #[validate(
         required(message = "This field is required and the length must be in range 5-12"),
         length(
@@ -31,18 +32,18 @@ pub struct RegisterRequest {
     )]
     pub username: Option<String>,
 
-    #[serde(deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
     #[validate(
         required(message = "This field is required."),
         email(message = "Invalid email.")
     )]
     pub email: Option<String>,
 
-    #[serde(deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
     #[validate(required(message = "This field is required."))]
     pub password: Option<String>,
 
-    #[serde(deserialize_with = "deserialize_empty_string_as_none")]
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
     #[validate(
         required(message = "This field is required."),
         must_match(other = "password", message = "Does not match with password field.")
@@ -54,44 +55,30 @@ impl RenderErrorsAsHtml for RegisterRequest {
     fn render(&self, errors: &ErrorBag) -> Markup {
         return register_form(Some(self), Some(errors));
     }
-}
-
-struct SuccessfulRegistrationResponse {
-    redirect_to: String,
-}
-
-impl SuccessfulRegistrationResponse {
-    fn new(redirect_to: String) -> Self {
-        return Self { redirect_to };
-    }
-}
 
-impl IntoResponse for SuccessfulRegistrationResponse {
-    fn into_response(self) -> Response {
-        let mut headers = HeaderMap::new();
-
-        headers.insert("HX-Location", self.redirect_to.parse().unwrap());
-        headers.insert(
-            "Set-Cookie",
-            Cookie::build(("successfully_registered", "true"))
-                .secure(true)
-                .same_site(cookie::SameSite::Strict)
-                .http_only(true)
-                .build()
-                .encoded()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-
-        return (headers).into_response();
+    fn render_page(&self, errors: &ErrorBag) -> Markup {
+        return register_page_with_errors(Some(self), Some(errors));
     }
 }
 
 async fn store(
-    State(AppContext { db }): State<AppContext>,
-    ValidatedForm(request): ValidatedForm<RegisterRequest>,
+    flash: Flash,
+    audit: Audit,
+    HxRequest(is_htmx): HxRequest,
+    State(AppContext { db, .. }): State<AppContext>,
+    Validated(request): Validated<RegisterRequest>,
 ) -> Result<impl IntoResponse, ApplicationError> {
+    let user_id = create_user(&db, &request).await?;
+    audit.record(AuditEvent::success("register").user(user_id)).await?;
+
+    flash.success("Your account has been created! Now try to login with the registered information.");
+
+    return Ok(redirect(is_htmx, "/login"));
+}
+
+/// Store the user of a validated registration request,
+/// returning the id of the new user.
+pub async fn create_user(db: &MySqlPool, request: &RegisterRequest) -> Result<u32, ApplicationError> {
     let password_hash = hash_password(request.password.as_ref().unwrap())?;
 
     let result = sqlx::query!(
@@ -100,16 +87,16 @@ async fn store(
         request.email,
         password_hash
     )
-    .execute(&db)
+    .execute(db)
     .await;
 
     return match result {
-        Ok(_) => Ok(SuccessfulRegistrationResponse::new("/login".to_string())),
+        Ok(result) => Ok(result.last_insert_id() as u32),
         Err(err) => Err(ApplicationError::ServerError(err.to_string())),
     };
 }
 
-fn hash_password(password: &str) -> Result<String, ApplicationError> {
+pub fn hash_password(password: &str) -> Result<String, ApplicationError> {
     let salt = SaltString::generate(&mut OsRng);
 
     let argon2 = Argon2::default();
@@ -1,4 +1,4 @@
-use super::{utils::deserialize_empty_string_as_none, AppContext};
+use super::{error::ApplicationError, utils::deserialize_empty_string_as_none, AppContext};
 use crate::view::input::{Input, InputKind, OnChangeValidation};
 use axum::{
     extract::{Form, State},
@@ -7,6 +7,7 @@ use axum::{
 };
 use maud::{html, Markup};
 use serde::Deserialize;
+use sqlx::MySqlPool;
 use validator::validate_email;
 
 pub fn router() -> Router<AppContext> {
@@ -14,45 +15,52 @@ pub fn router() -> Router<AppContext> {
 }
 
 #[derive(Deserialize)]
-struct CheckEmailRequest {
-    #[serde(deserialize_with = "deserialize_empty_string_as_none")]
-    email: Option<String>,
+pub(super) struct CheckEmailRequest {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    pub(super) email: Option<String>,
 }
 
 async fn check_email(
-    State(AppContext { db }): State<AppContext>,
+    State(AppContext { db, .. }): State<AppContext>,
     Form(request): Form<CheckEmailRequest>,
-) -> Markup {
-    let mut email_input = Input::new("Email", "email")
+) -> Result<Markup, ApplicationError> {
+    let email_input = Input::new("Email", "email")
         .kind(InputKind::Email)
-        .validate_on_change(OnChangeValidation::Email);
+        .validate_on_change(OnChangeValidation::Email)
+        .value(request.email.as_deref().unwrap_or(""));
 
-    if request.email.is_none() {
-        return html! {
-            (email_input.errors(Some(&vec!["This field is required.".to_string()])))
-        };
-    }
+    let errors = email_errors(&db, request.email.as_deref()).await?;
 
-    let email = request.email.unwrap();
+    return Ok(if errors.is_empty() {
+        html! { (email_input) }
+    } else {
+        html! { (email_input.errors(Some(&errors))) }
+    });
+}
 
-    email_input = email_input.value(&email);
+/// Check that the email can be registered, returning the
+/// reasons why it can not. An empty list means the email
+/// is available.
+pub(super) async fn email_errors(
+    db: &MySqlPool,
+    email: Option<&str>,
+) -> Result<Vec<String>, ApplicationError> {
+    let Some(email) = email else {
+        return Ok(vec!["This field is required.".to_string()]);
+    };
 
-    if !validate_email(&email) {
-        return html! {
-            (email_input.errors(Some(&vec!["Invalid email.".to_string()])))
-        };
+    if !validate_email(email) {
+        return Ok(vec!["Invalid email.".to_string()]);
     }
 
     let result = sqlx::query!("select count(*) as count from users where email = ?", email)
-        .fetch_one(&db)
+        .fetch_one(db)
         .await
-        .unwrap();
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
 
-    return if result.count >= 1 {
-        html! {
-            (email_input.errors(Some(&vec!["Email already exists.".to_string()])))
-        }
-    } else {
-        html! { (email_input) }
-    };
+    if result.count >= 1 {
+        return Ok(vec!["Email already exists.".to_string()]);
+    }
+
+    return Ok(vec![]);
 }
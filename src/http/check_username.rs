@@ -1,4 +1,4 @@
-use super::{utils::deserialize_empty_string_as_none, AppContext};
+use super::{error::ApplicationError, utils::deserialize_empty_string_as_none, AppContext};
 use crate::view::input::Input;
 use crate::view::input::OnChangeValidation;
 use axum::{
@@ -8,6 +8,7 @@ use axum::{
 };
 use maud::{html, Markup};
 use serde::Deserialize;
+use sqlx::MySqlPool;
 use validator::validate_length;
 
 pub fn router() -> Router<AppContext> {
@@ -15,41 +16,54 @@ pub fn router() -> Router<AppContext> {
 }
 
 #[derive(Deserialize)]
-struct CheckUsernameRequest {
-    #[serde(deserialize_with = "deserialize_empty_string_as_none")]
-    username: Option<String>,
+pub(super) struct CheckUsernameRequest {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    pub(super) username: Option<String>,
 }
 
 async fn check_username(
-    State(AppContext { db }): State<AppContext>,
+    State(AppContext { db, .. }): State<AppContext>,
     Form(request): Form<CheckUsernameRequest>,
-) -> Markup {
-    let mut username_input =
-        Input::new("Username", "username").validate_on_change(OnChangeValidation::Username);
+) -> Result<Markup, ApplicationError> {
+    let username_input = Input::new("Username", "username")
+        .validate_on_change(OnChangeValidation::Username)
+        .value(request.username.as_deref().unwrap_or(""));
 
-    if request.username.is_none() {
-        return html! { (username_input.errors(Some(&vec!["This field is required.".to_string()]))) };
-    }
+    let errors = username_errors(&db, request.username.as_deref()).await?;
 
-    let username = request.username.unwrap();
+    return Ok(if errors.is_empty() {
+        html! { (username_input) }
+    } else {
+        html! { (username_input.errors(Some(&errors))) }
+    });
+}
 
-    username_input = username_input.value(&username);
+/// Check that the username can be registered, returning
+/// the reasons why it can not. An empty list means the
+/// username is available.
+pub(super) async fn username_errors(
+    db: &MySqlPool,
+    username: Option<&str>,
+) -> Result<Vec<String>, ApplicationError> {
+    let Some(username) = username else {
+        return Ok(vec!["This field is required.".to_string()]);
+    };
 
-    if !validate_length(&username, Some(5), Some(12), None) {
-        return html! { (username_input.errors(Some(&vec!["The length must be in range 5-12".to_string()]))) };
+    if !validate_length(username, Some(5), Some(12), None) {
+        return Ok(vec!["The length must be in range 5-12".to_string()]);
     }
 
     let result = sqlx::query!(
         "select count(*) as count from users where username = ?",
         username
     )
-    .fetch_one(&db)
+    .fetch_one(db)
     .await
-    .unwrap();
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
 
-    return if result.count >= 1 {
-        html! { (username_input.errors(Some(&vec!["Already exists.".to_string()]))) }
-    } else {
-        html! { (username_input) }
-    };
+    if result.count >= 1 {
+        return Ok(vec!["Already exists.".to_string()]);
+    }
+
+    return Ok(vec![]);
 }
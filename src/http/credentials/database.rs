@@ -0,0 +1,52 @@
+use super::CredentialProvider;
+use crate::http::{authentication::login::AuthenticatedUser, error::ApplicationError};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use async_trait::async_trait;
+use sqlx::MySqlPool;
+
+/// Check the password against the Argon2 hash stored in
+/// the `users` table.
+pub struct DatabaseCredentials {
+    db: MySqlPool,
+}
+
+impl DatabaseCredentials {
+    pub fn new(db: MySqlPool) -> Self {
+        return Self { db };
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for DatabaseCredentials {
+    async fn verify(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<AuthenticatedUser>, ApplicationError> {
+        // Find the user by username.
+        let result = sqlx::query!(
+            "select id, username, password from users where username = ?",
+            username
+        )
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        // If the user exists, then we will verify
+        // the password from the request with hashed
+        // one stored in the database.
+        let Some(record) = result else {
+            return Ok(None);
+        };
+
+        let password_hash = PasswordHash::new(&record.password)
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        let result = Argon2::default().verify_password(password.as_bytes(), &password_hash);
+
+        return Ok(result.ok().map(|_| AuthenticatedUser {
+            id: record.id,
+            username: record.username,
+        }));
+    }
+}
@@ -0,0 +1,326 @@
+use std::{env, time::Duration};
+
+use super::CredentialProvider;
+use crate::http::{
+    authentication::login::AuthenticatedUser,
+    error::{ApplicationError, ErrorBag, ProblemDetails},
+    sso::{identity, provider::ExternalIdentity},
+};
+use async_trait::async_trait;
+use ldap3::{drive, ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use sqlx::MySqlPool;
+
+/// The result code of a bind with a wrong password.
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Shown when the email address of the directory belongs to
+/// an account of ours which is not linked to the directory.
+const EMAIL_TAKEN: &str = "Your email address is already used by another account. Ask an administrator to link it to the directory.";
+
+/// Check the password by binding to the directory as the
+/// user. The user is first looked up with the service
+/// account, since the DN can not be derived from the
+/// username in most directories, e.g. Active Directory.
+///
+/// Users are added to the `users` table the first time they
+/// log in, and found again through `user_identities` by their
+/// DN, so their username here may differ from the one in the
+/// directory.
+pub struct LdapCredentials {
+    url: String,
+    starttls: bool,
+    bind_dn: Option<String>,
+    bind_password: String,
+    base_dn: String,
+    user_filter: String,
+    email_attribute: String,
+    db: MySqlPool,
+}
+
+impl LdapCredentials {
+    /// Read the directory from the `LDAP_*` variables: `URL`,
+    /// `STARTTLS`, `BIND_DN` and `BIND_PASSWORD` of the service
+    /// account (anonymous when unset), `BASE_DN`, `USER_FILTER`
+    /// where `{username}` is replaced, and `EMAIL_ATTRIBUTE`.
+    pub fn from_env(db: MySqlPool) -> Result<Self, String> {
+        let var = |name: &str| env::var(format!("LDAP_{}", name)).ok();
+        let required = |name: &str| var(name).ok_or_else(|| format!("LDAP_{} is not set", name));
+
+        let user_filter = var("USER_FILTER").unwrap_or("(uid={username})".to_string());
+        if !user_filter.contains("{username}") {
+            return Err("LDAP_USER_FILTER must contain {username}".to_string());
+        }
+
+        return Ok(Self {
+            url: required("URL")?,
+            starttls: var("STARTTLS").is_some_and(|value| value == "true"),
+            bind_dn: var("BIND_DN"),
+            bind_password: var("BIND_PASSWORD").unwrap_or_default(),
+            base_dn: required("BASE_DN")?,
+            user_filter,
+            email_attribute: var("EMAIL_ATTRIBUTE").unwrap_or("mail".to_string()),
+            db,
+        });
+    }
+
+    async fn connect(&self) -> Result<Ldap, ApplicationError> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(5))
+            .set_starttls(self.starttls);
+
+        let (connection, ldap) = LdapConnAsync::with_settings(settings, &self.url)
+            .await
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+        drive!(connection);
+
+        return Ok(ldap);
+    }
+
+    /// The DN and email address of the user, if the directory
+    /// knows exactly one entry for the username.
+    async fn search(
+        &self,
+        ldap: &mut Ldap,
+        username: &str,
+    ) -> Result<Option<(String, Option<String>)>, ApplicationError> {
+        if let Some(bind_dn) = &self.bind_dn {
+            ldap.simple_bind(bind_dn, &self.bind_password)
+                .await
+                .and_then(|result| result.success())
+                .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+        }
+
+        let filter = self
+            .user_filter
+            .replace("{username}", &ldap_escape(username));
+
+        let (entries, _) = ldap
+            .search(
+                &self.base_dn,
+                Scope::Subtree,
+                &filter,
+                vec![self.email_attribute.as_str()],
+            )
+            .await
+            .and_then(|result| result.success())
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        let mut entries = entries.into_iter().map(SearchEntry::construct);
+
+        return Ok(match (entries.next(), entries.next()) {
+            (Some(entry), None) => {
+                let email = entry
+                    .attrs
+                    .get(&self.email_attribute)
+                    .and_then(|values| values.first())
+                    .cloned();
+                Some((entry.dn, email))
+            }
+            _ => None,
+        });
+    }
+
+    /// The account of the directory user, created on their
+    /// first login.
+    async fn provision(
+        &self,
+        dn: String,
+        username: &str,
+        email: Option<String>,
+    ) -> Result<AuthenticatedUser, ApplicationError> {
+        let identity = ExternalIdentity {
+            provider: "ldap".to_string(),
+            subject: dn.to_lowercase(),
+            username: Some(username.to_string()),
+            email,
+        };
+
+        if let Some((id, username)) = identity::find_user(&self.db, &identity).await? {
+            identity::update_username(&self.db, &identity).await?;
+            return Ok(AuthenticatedUser { id, username });
+        }
+
+        let Some(email) = identity.email.clone() else {
+            return Err(ApplicationError::ServerError(format!(
+                "The directory has no {} for {}",
+                self.email_attribute, identity.subject
+            )));
+        };
+
+        // The account may be someone else's, so it is not
+        // taken over by whoever controls the directory entry.
+        if identity::find_user_by_email(&self.db, &email)
+            .await?
+            .is_some()
+        {
+            let mut errors = ErrorBag::new();
+            errors.insert(
+                "invalid_credentials".to_string(),
+                vec![EMAIL_TAKEN.to_string()],
+            );
+
+            return Err(ApplicationError::Problem(ProblemDetails::validation(
+                errors,
+            )));
+        }
+
+        let (id, username) = identity::create_user(&self.db, &identity, &email).await?;
+
+        return Ok(AuthenticatedUser { id, username });
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for LdapCredentials {
+    async fn verify(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<AuthenticatedUser>, ApplicationError> {
+        // A bind without password is an anonymous bind, which
+        // most directories accept whoever the DN is.
+        if password.is_empty() {
+            return Ok(None);
+        }
+
+        let mut ldap = self.connect().await?;
+
+        let Some((dn, email)) = self.search(&mut ldap, username).await? else {
+            let _ = ldap.unbind().await;
+            return Ok(None);
+        };
+
+        let result = ldap
+            .simple_bind(&dn, password)
+            .await
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+        let _ = ldap.unbind().await;
+
+        if result.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        result
+            .success()
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        return Ok(Some(self.provision(dn, username, email).await?));
+    }
+
+    /// The username of the directory, since the one here was
+    /// made up on the first login. Accounts that were never
+    /// linked to the directory keep their own.
+    async fn login_name(&self, user_id: u32, username: &str) -> Result<String, ApplicationError> {
+        let login_name = identity::find_username(&self.db, user_id, "ldap").await?;
+
+        return Ok(login_name.unwrap_or(username.to_string()));
+    }
+
+    fn manages_passwords(&self) -> bool {
+        return false;
+    }
+}
+
+/// Run against the directory of `scripts/init_ldap.sh`, with
+/// `cargo test -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(db: MySqlPool) -> LdapCredentials {
+        return LdapCredentials {
+            url: "ldap://localhost:1389".to_string(),
+            starttls: false,
+            bind_dn: Some("cn=admin,dc=example,dc=org".to_string()),
+            bind_password: "adminpassword".to_string(),
+            base_dn: "ou=people,dc=example,dc=org".to_string(),
+            user_filter: "(uid={username})".to_string(),
+            email_attribute: "mail".to_string(),
+            db,
+        };
+    }
+
+    async fn create_local_user(db: &MySqlPool, username: &str, email: &str) {
+        sqlx::query!(
+            "insert into users (username, email, password) values (?, ?, '')",
+            username,
+            email
+        )
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the directory of scripts/init_ldap.sh"]
+    async fn it_provisions_the_user_on_the_first_login(db: MySqlPool) {
+        let credentials = credentials(db);
+
+        let user = credentials.verify("jane.doe", "secret").await.unwrap();
+        let again = credentials.verify("jane.doe", "secret").await.unwrap();
+
+        let (user, again) = (user.unwrap(), again.unwrap());
+        assert_eq!(user.username, "janedoe");
+        assert_eq!(user.id, again.id);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the directory of scripts/init_ldap.sh"]
+    async fn it_refuses_invalid_credentials(db: MySqlPool) {
+        let credentials = credentials(db);
+
+        assert!(credentials
+            .verify("jane.doe", "wrong")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(credentials.verify("jane.doe", "").await.unwrap().is_none());
+        assert!(credentials
+            .verify("john.doe", "secret")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(credentials.verify("*", "secret").await.unwrap().is_none());
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the directory of scripts/init_ldap.sh"]
+    async fn it_logs_in_with_the_directory_username(db: MySqlPool) {
+        create_local_user(&db, "janedoe", "someone@example.org").await;
+        let credentials = credentials(db);
+
+        let user = credentials
+            .verify("jane.doe", "secret")
+            .await
+            .unwrap()
+            .unwrap();
+        let login_name = credentials
+            .login_name(user.id, &user.username)
+            .await
+            .unwrap();
+
+        assert_ne!(user.username, "janedoe");
+        assert_eq!(login_name, "jane.doe");
+        assert!(credentials
+            .verify(&login_name, "secret")
+            .await
+            .unwrap()
+            .is_some_and(|again| again.id == user.id));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the directory of scripts/init_ldap.sh"]
+    async fn it_refuses_to_take_over_an_account_with_the_same_email(db: MySqlPool) {
+        create_local_user(&db, "jane_local", "jane@example.org").await;
+        let credentials = credentials(db);
+
+        let Err(ApplicationError::Problem(problem)) =
+            credentials.verify("jane.doe", "secret").await
+        else {
+            panic!("The login should be refused");
+        };
+
+        assert!(problem
+            .errors
+            .is_some_and(|errors| errors.contains_key("invalid_credentials")));
+    }
+}
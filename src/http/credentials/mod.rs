@@ -0,0 +1,48 @@
+use std::{env, sync::Arc};
+
+use super::{authentication::login::AuthenticatedUser, error::ApplicationError};
+use async_trait::async_trait;
+use sqlx::MySqlPool;
+
+mod database;
+mod ldap;
+
+pub use database::DatabaseCredentials;
+pub use ldap::LdapCredentials;
+
+/// Where the passwords of the users are checked, e.g. our
+/// own `users` table or the directory of the company.
+#[async_trait]
+pub trait CredentialProvider: Send + Sync {
+    /// The user the credentials belong to, or `None` when
+    /// they are invalid.
+    async fn verify(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<AuthenticatedUser>, ApplicationError>;
+
+    /// The username the user logs in with, to check the
+    /// password of a logged-in user again. It is their
+    /// username here unless the provider knows them by
+    /// another one.
+    async fn login_name(&self, _user_id: u32, username: &str) -> Result<String, ApplicationError> {
+        return Ok(username.to_string());
+    }
+
+    /// Whether the users change their password here. The
+    /// directory of a company keeps its own passwords.
+    fn manages_passwords(&self) -> bool {
+        return true;
+    }
+}
+
+/// The provider chosen by `CREDENTIAL_PROVIDER`, either
+/// `database` (the default) or `ldap`.
+pub fn from_env(db: MySqlPool) -> Result<Arc<dyn CredentialProvider>, String> {
+    return match env::var("CREDENTIAL_PROVIDER").as_deref() {
+        Err(_) | Ok("database") => Ok(Arc::new(DatabaseCredentials::new(db))),
+        Ok("ldap") => Ok(Arc::new(LdapCredentials::from_env(db)?)),
+        Ok(other) => Err(format!("Unknown credential provider {}", other)),
+    };
+}
@@ -0,0 +1,480 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::{Cursor, Write},
+    sync::{Arc, OnceLock},
+};
+
+use super::{
+    error::ApplicationError,
+    login_device,
+    mailer::Mailer,
+    middleware::User,
+    personal_access_token, role,
+    user_session::{self, SessionLimits},
+    utils::{constant_time_eq, random_token},
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use redis::AsyncCommands;
+use redis_pool::SingleRedisPool;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::MySqlPool;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use zip::{write::FileOptions, ZipWriter};
+
+/// How long the archives are kept, and their links valid.
+const TTL_SECONDS: usize = 24 * 60 * 60;
+
+fn key(id: &str) -> String {
+    return format!("data_export:{}", id);
+}
+
+fn archive_key(id: &str) -> String {
+    return format!("data_export_archive:{}", id);
+}
+
+fn user_key(user_id: u32) -> String {
+    return format!("data_exports:{}", user_id);
+}
+
+/// The latest export the user asked for.
+pub enum DataExport {
+    Building,
+    Ready {
+        path: String,
+        expires_at: OffsetDateTime,
+    },
+    Failed,
+}
+
+/// Everything we store about the user, as the files of a ZIP
+/// archive, or `None` if the user does not exist.
+pub async fn build(
+    db: &MySqlPool,
+    redis: &SingleRedisPool,
+    limits: &SessionLimits,
+    user_id: u32,
+) -> Result<Option<Vec<u8>>, ApplicationError> {
+    let Some(user) = sqlx::query!(
+        "select id, username, email, created_at, updated_at, deleted_at from users where id = ?",
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    else {
+        return Ok(None);
+    };
+
+    let roles = role::grants(db, user_id).await?.roles;
+
+    let account = json!({
+        "id": user.id,
+        "username": user.username,
+        "email": user.email,
+        "roles": roles,
+        "created_at": timestamp(user.created_at),
+        "updated_at": timestamp(user.updated_at),
+        "deleted_at": timestamp(user.deleted_at),
+    });
+
+    let pending_email_changes = sqlx::query!(
+        "select email, expires_at, created_at from email_changes where user_id = ?",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    .into_iter()
+    .map(|record| {
+        json!({
+            "email": record.email,
+            "expires_at": timestamp(Some(record.expires_at)),
+            "created_at": timestamp(record.created_at),
+        })
+    })
+    .collect::<Vec<_>>();
+
+    let sessions = user_session::list(redis, limits, user_id)
+        .await?
+        .into_iter()
+        .map(|session| {
+            json!({
+                "device": session.device,
+                "ip": session.ip,
+                "created_at": timestamp(Some(session.created_at)),
+                "last_seen_at": timestamp(Some(session.last_seen_at)),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let personal_access_tokens = personal_access_token::list_for_user(db, user_id)
+        .await?
+        .into_iter()
+        .map(|token| {
+            json!({
+                "name": token.name,
+                "scopes": token.scopes,
+                "expires_at": timestamp(token.expires_at),
+                "last_used_at": timestamp(token.last_used_at),
+                "last_used_ip": token.last_used_ip,
+                "created_at": timestamp(token.created_at),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let refresh_tokens = sqlx::query!(
+        "select expires_at, revoked_at, created_at from refresh_tokens where user_id = ? order by id",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    .into_iter()
+    .map(|record| {
+        json!({
+            "expires_at": timestamp(Some(record.expires_at)),
+            "revoked_at": timestamp(record.revoked_at),
+            "created_at": timestamp(record.created_at),
+        })
+    })
+    .collect::<Vec<_>>();
+
+    let oauth_access_tokens = sqlx::query!(
+        "select oauth_clients.name as client, oauth_access_tokens.scopes, oauth_access_tokens.expires_at,
+            oauth_access_tokens.revoked_at, oauth_access_tokens.created_at
+        from oauth_access_tokens
+        join oauth_clients on oauth_clients.id = oauth_access_tokens.client_id
+        where oauth_access_tokens.user_id = ?
+        order by oauth_access_tokens.created_at",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    .into_iter()
+    .map(|record| {
+        json!({
+            "client": record.client,
+            "scopes": record.scopes.split_whitespace().collect::<Vec<_>>(),
+            "expires_at": timestamp(Some(record.expires_at)),
+            "revoked_at": timestamp(record.revoked_at),
+            "created_at": timestamp(record.created_at),
+        })
+    })
+    .collect::<Vec<_>>();
+
+    let oauth_refresh_tokens = sqlx::query!(
+        "select oauth_clients.name as client, oauth_refresh_tokens.scopes, oauth_refresh_tokens.expires_at,
+            oauth_refresh_tokens.revoked_at, oauth_refresh_tokens.created_at
+        from oauth_refresh_tokens
+        join oauth_clients on oauth_clients.id = oauth_refresh_tokens.client_id
+        where oauth_refresh_tokens.user_id = ?
+        order by oauth_refresh_tokens.created_at",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    .into_iter()
+    .map(|record| {
+        json!({
+            "client": record.client,
+            "scopes": record.scopes.split_whitespace().collect::<Vec<_>>(),
+            "expires_at": timestamp(Some(record.expires_at)),
+            "revoked_at": timestamp(record.revoked_at),
+            "created_at": timestamp(record.created_at),
+        })
+    })
+    .collect::<Vec<_>>();
+
+    let identities = sqlx::query!(
+        "select provider, subject, username, email, created_at from user_identities where user_id = ? order by id",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    .into_iter()
+    .map(|record| {
+        json!({
+            "provider": record.provider,
+            "subject": record.subject,
+            "username": record.username,
+            "email": record.email,
+            "created_at": timestamp(record.created_at),
+        })
+    })
+    .collect::<Vec<_>>();
+
+    let devices = login_device::list(db, user_id)
+        .await?
+        .into_iter()
+        .map(|device| {
+            json!({
+                "device": device.device,
+                "ip_range": device.ip_range,
+                "first_seen_at": timestamp(Some(device.first_seen_at)),
+                "last_seen_at": timestamp(Some(device.last_seen_at)),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let audit_events = sqlx::query!(
+        "select event, outcome, ip, user_agent, metadata, created_at
+        from audit_events where user_id = ? order by id",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    .into_iter()
+    .map(|record| {
+        json!({
+            "event": record.event,
+            "outcome": record.outcome,
+            "ip": record.ip,
+            "user_agent": record.user_agent,
+            "metadata": record
+                .metadata
+                .and_then(|metadata| serde_json::from_str::<Value>(&metadata).ok()),
+            "created_at": timestamp(Some(record.created_at)),
+        })
+    })
+    .collect::<Vec<_>>();
+
+    let files = [
+        ("account.json", account),
+        ("email_changes.json", Value::from(pending_email_changes)),
+        ("sessions.json", Value::from(sessions)),
+        (
+            "personal_access_tokens.json",
+            Value::from(personal_access_tokens),
+        ),
+        ("refresh_tokens.json", Value::from(refresh_tokens)),
+        ("oauth_access_tokens.json", Value::from(oauth_access_tokens)),
+        (
+            "oauth_refresh_tokens.json",
+            Value::from(oauth_refresh_tokens),
+        ),
+        ("identities.json", Value::from(identities)),
+        ("devices.json", Value::from(devices)),
+        ("audit_events.json", Value::from(audit_events)),
+    ];
+
+    return archive(&files).map(Some);
+}
+
+fn timestamp(value: Option<OffsetDateTime>) -> Option<String> {
+    return value.and_then(|value| value.format(&Rfc3339).ok());
+}
+
+fn archive(files: &[(&str, Value)]) -> Result<Vec<u8>, ApplicationError> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for (name, content) in files {
+        let content = serde_json::to_vec_pretty(content)
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        zip.start_file(*name, FileOptions::default())
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+        zip.write_all(&content)
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+    }
+
+    let cursor = zip
+        .finish()
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(cursor.into_inner());
+}
+
+/// Start building the archive of the user in the background.
+/// They are emailed a link to download it once it is ready.
+pub async fn request(
+    db: &MySqlPool,
+    redis: &SingleRedisPool,
+    limits: &SessionLimits,
+    mailer: &Arc<Mailer>,
+    user: &User,
+) -> Result<(), ApplicationError> {
+    let id = random_token(16);
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    redis::pipe()
+        .hset(key(&id), "status", "building")
+        .expire(key(&id), TTL_SECONDS)
+        .set_ex(user_key(user.id), &id, TTL_SECONDS)
+        .query_async::<_, ()>(&mut *connection)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let (db, redis, limits, mailer, user) = (
+        db.clone(),
+        redis.clone(),
+        *limits,
+        mailer.clone(),
+        user.clone(),
+    );
+
+    tokio::spawn(async move {
+        if let Err(error) = finish(&db, &redis, &limits, &mailer, &user, &id).await {
+            println!("Server error: {:?}", error);
+
+            if let Ok(mut connection) = redis.aquire().await {
+                let _ = connection
+                    .hset::<_, _, _, ()>(key(&id), "status", "failed")
+                    .await;
+            }
+        }
+    });
+
+    return Ok(());
+}
+
+async fn finish(
+    db: &MySqlPool,
+    redis: &SingleRedisPool,
+    limits: &SessionLimits,
+    mailer: &Mailer,
+    user: &User,
+    id: &str,
+) -> Result<(), ApplicationError> {
+    let archive = build(db, redis, limits, user.id)
+        .await?
+        .ok_or_else(|| ApplicationError::ServerError("The user no longer exists".to_string()))?;
+    let expires_at = OffsetDateTime::now_utc().unix_timestamp() + TTL_SECONDS as i64;
+
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    redis::pipe()
+        .set_ex(archive_key(id), archive, TTL_SECONDS)
+        .hset_multiple(
+            key(id),
+            &[
+                ("status", "ready".to_string()),
+                ("expires_at", expires_at.to_string()),
+            ],
+        )
+        .query_async::<_, ()>(&mut *connection)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    mailer
+        .send(
+            &user.email,
+            "Your data is ready to download",
+            format!(
+                "Hello {},\n\nThe copy of your data you asked for is ready. Download it within 24 hours from:\n\n{}\n\nIf you did not ask for it, change your password right away.\n",
+                user.username,
+                mailer.url(&download_path(id, expires_at))
+            ),
+        )
+        .await?;
+
+    return Ok(());
+}
+
+/// The latest export of the user, while it is kept.
+pub async fn latest(
+    redis: &SingleRedisPool,
+    user_id: u32,
+) -> Result<Option<DataExport>, ApplicationError> {
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let Some(id) = connection
+        .get::<_, Option<String>>(user_key(user_id))
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    else {
+        return Ok(None);
+    };
+
+    let entry: HashMap<String, String> = connection
+        .hgetall(key(&id))
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let expires_at = entry.get("expires_at").and_then(|v| v.parse::<i64>().ok());
+
+    return Ok(
+        match (entry.get("status").map(String::as_str), expires_at) {
+            (Some("building"), _) => Some(DataExport::Building),
+            (Some("failed"), _) => Some(DataExport::Failed),
+            (Some("ready"), Some(expires_at)) => Some(DataExport::Ready {
+                path: download_path(&id, expires_at),
+                expires_at: OffsetDateTime::from_unix_timestamp(expires_at)
+                    .map_err(|e| ApplicationError::ServerError(e.to_string()))?,
+            }),
+            _ => None,
+        },
+    );
+}
+
+/// The archive behind a download link, unless the link has
+/// expired or was not signed by us.
+pub async fn download(
+    redis: &SingleRedisPool,
+    id: &str,
+    expires_at: i64,
+    signature: &str,
+) -> Result<Option<Vec<u8>>, ApplicationError> {
+    if expires_at < OffsetDateTime::now_utc().unix_timestamp()
+        || !constant_time_eq(signature, &sign(id, expires_at))
+    {
+        return Ok(None);
+    }
+
+    return redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+        .get::<_, Option<Vec<u8>>>(archive_key(id))
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()));
+}
+
+fn download_path(id: &str, expires_at: i64) -> String {
+    return format!(
+        "/settings/export/{}/download?expires={}&signature={}",
+        id,
+        expires_at,
+        sign(id, expires_at)
+    );
+}
+
+/// The key signing the download links, from `APP_KEY`.
+/// Without it a key is generated on start, and the links
+/// stop working when the server restarts.
+fn signing_key() -> &'static [u8] {
+    static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+    return KEY.get_or_init(|| match env::var("APP_KEY") {
+        Ok(key) => key.into_bytes(),
+        Err(_) => {
+            let mut key = vec![0u8; 32];
+            OsRng.fill_bytes(&mut key);
+            key
+        }
+    });
+}
+
+fn sign(id: &str, expires_at: i64) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(signing_key()).expect("HMAC takes keys of any size");
+    mac.update(format!("{}:{}", id, expires_at).as_bytes());
+
+    return URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+}
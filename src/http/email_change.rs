@@ -0,0 +1,134 @@
+use super::{
+    error::ApplicationError,
+    utils::{random_token, sha256_hex},
+};
+use sqlx::MySqlPool;
+use time::{Duration, OffsetDateTime};
+
+/// How long the confirmation link sent to the new address
+/// stays valid.
+const TTL_HOURS: i64 = 24;
+
+/// Start changing the email address of the user, replacing
+/// the change they may have started before. Returns the
+/// token of the confirmation link, only its hash is stored.
+pub async fn create(db: &MySqlPool, user_id: u32, email: &str) -> Result<String, ApplicationError> {
+    let token = random_token(32);
+    let expires_at = OffsetDateTime::now_utc() + Duration::hours(TTL_HOURS);
+
+    sqlx::query!(
+        "insert into email_changes (user_id, email, token, expires_at) values (?, ?, ?, ?)
+        on duplicate key update email = values(email), token = values(token), expires_at = values(expires_at), created_at = current_timestamp",
+        user_id,
+        email,
+        sha256_hex(&token),
+        expires_at
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(token);
+}
+
+/// The address the user is changing to, until confirmed.
+pub async fn pending(db: &MySqlPool, user_id: u32) -> Result<Option<String>, ApplicationError> {
+    let record = sqlx::query!(
+        "select email from email_changes where user_id = ? and expires_at > current_timestamp",
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| record.email));
+}
+
+/// The address the token of the confirmation link was sent
+/// to, while it is valid.
+pub async fn find(db: &MySqlPool, token: &str) -> Result<Option<String>, ApplicationError> {
+    let record = sqlx::query!(
+        "select email from email_changes where token = ? and expires_at > current_timestamp",
+        sha256_hex(token)
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| record.email));
+}
+
+pub enum Confirmation {
+    Changed {
+        user_id: u32,
+        email: String,
+    },
+    Invalid,
+    /// Someone registered with the address in the meantime.
+    Taken,
+}
+
+/// Apply the change the token was sent for. The token can
+/// only be used once.
+pub async fn confirm(db: &MySqlPool, token: &str) -> Result<Confirmation, ApplicationError> {
+    let mut transaction = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let record = sqlx::query!(
+        "select user_id, email from email_changes where token = ? and expires_at > current_timestamp for update",
+        sha256_hex(token)
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let Some(record) = record else {
+        return Ok(Confirmation::Invalid);
+    };
+
+    sqlx::query!(
+        "delete from email_changes where user_id = ?",
+        record.user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let taken = sqlx::query!(
+        "select count(*) as count from users where email = ? and id != ?",
+        record.email,
+        record.user_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    .count
+        >= 1;
+
+    if !taken {
+        sqlx::query!(
+            "update users set email = ? where id = ?",
+            record.email,
+            record.user_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(if taken {
+        Confirmation::Taken
+    } else {
+        Confirmation::Changed {
+            user_id: record.user_id,
+            email: record.email,
+        }
+    });
+}
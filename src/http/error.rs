@@ -1,7 +1,12 @@
 use std::collections::HashMap;
 
-use axum::{extract::rejection::FormRejection, response::IntoResponse};
+use axum::{
+    extract::rejection::FormRejection,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Redirect},
+};
 use maud::{Markup, PreEscaped};
+use serde::Serialize;
 
 pub type ErrorBag = HashMap<String, Vec<String>>;
 
@@ -9,11 +14,17 @@ pub type ErrorBag = HashMap<String, Vec<String>>;
 pub enum ApplicationError {
     ValidationError(Option<Markup>),
     AxumFormRejection(FormRejection),
+    Unauthenticated,
+    Problem(ProblemDetails),
     ServerError(String),
 }
 
 pub(super) trait RenderErrorsAsHtml {
+    /// The form with its errors, swapped in by htmx.
     fn render(&self, errs: &ErrorBag) -> Markup;
+
+    /// The whole page around the form, for clients without htmx.
+    fn render_page(&self, errs: &ErrorBag) -> Markup;
 }
 
 impl IntoResponse for ApplicationError {
@@ -23,6 +34,10 @@ impl IntoResponse for ApplicationError {
                 html.or(Some(PreEscaped("".to_string()))).unwrap()
             }
             ApplicationError::AxumFormRejection(_) => PreEscaped("".to_string()),
+            ApplicationError::Unauthenticated => {
+                return Redirect::to("/login").into_response();
+            }
+            ApplicationError::Problem(problem) => return problem.into_response(),
             ApplicationError::ServerError(_) => {
                 println!("Server error: {:?}", self);
                 PreEscaped("".to_string())
@@ -31,3 +46,91 @@ impl IntoResponse for ApplicationError {
         .into_response();
     }
 }
+
+/// An error of the JSON API, serialized as problem details
+/// (RFC 7807).
+#[derive(Serialize, Debug)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<ErrorBag>,
+}
+
+impl ProblemDetails {
+    pub fn new(status: StatusCode, title: &str) -> Self {
+        return Self {
+            kind: "about:blank".to_string(),
+            title: title.to_string(),
+            status: status.as_u16(),
+            detail: None,
+            errors: None,
+        };
+    }
+
+    pub fn kind(mut self, kind: &str) -> Self {
+        self.kind = kind.to_string();
+        return self;
+    }
+
+    pub fn detail(mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_string());
+        return self;
+    }
+
+    pub fn errors(mut self, errors: ErrorBag) -> Self {
+        self.errors = Some(errors);
+        return self;
+    }
+
+    pub fn validation(errors: ErrorBag) -> Self {
+        return Self::new(StatusCode::UNPROCESSABLE_ENTITY, "Validation failed")
+            .kind("/problems/validation")
+            .detail("The request contains invalid fields.")
+            .errors(errors);
+    }
+
+    pub fn unauthenticated() -> Self {
+        return Self::new(StatusCode::UNAUTHORIZED, "Unauthenticated")
+            .detail("You must be logged in to access this resource.");
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> axum::response::Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_REQUEST);
+
+        return match serde_json::to_vec(&self) {
+            Ok(body) => (
+                status,
+                [(CONTENT_TYPE, "application/problem+json")],
+                body,
+            )
+                .into_response(),
+            Err(_) => status.into_response(),
+        };
+    }
+}
+
+impl From<ApplicationError> for ProblemDetails {
+    fn from(error: ApplicationError) -> Self {
+        return match error {
+            ApplicationError::ValidationError(_) => {
+                ProblemDetails::new(StatusCode::UNPROCESSABLE_ENTITY, "Validation failed")
+            }
+            ApplicationError::AxumFormRejection(rejection) => {
+                ProblemDetails::new(rejection.status(), "Bad request").detail(&rejection.body_text())
+            }
+            ApplicationError::Unauthenticated => ProblemDetails::unauthenticated(),
+            ApplicationError::Problem(problem) => problem,
+            ApplicationError::ServerError(_) => {
+                println!("Server error: {:?}", error);
+                ProblemDetails::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+        };
+    }
+}
@@ -1,54 +1,626 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
 
-use super::error::{ApplicationError, ErrorBag, RenderErrorsAsHtml};
+use super::error::{ApplicationError, ErrorBag, ProblemDetails, RenderErrorsAsHtml};
+use super::jwt::Claims;
+use super::middleware::{Auth, User};
+use super::{user_cache, AppContext};
 use async_trait::async_trait;
 use axum::{
-    extract::{rejection::FormRejection, FromRequest},
-    http::Request,
-    Form,
+    body::Body,
+    extract::{
+        rejection::JsonRejection, ConnectInfo, FromRequest, FromRequestParts, Multipart,
+    },
+    http::{
+        header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+        request::Parts,
+        Extensions, HeaderMap, Request, StatusCode,
+    },
+    Form, Json,
 };
 use serde::de::DeserializeOwned;
-use validator::{Validate, ValidationErrorsKind};
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+/// Collect the messages of the failed validations by field.
+/// Errors of nested structures are keyed by their dotted path
+/// (`address.city`), errors of collection items by their index
+/// (`phones[1]`).
+pub(super) fn error_bag(err: &ValidationErrors) -> ErrorBag {
+    let mut errors: ErrorBag = HashMap::new();
+    flatten_errors(err, None, &mut errors);
+
+    return errors;
+}
+
+fn flatten_errors(err: &ValidationErrors, prefix: Option<&str>, errors: &mut ErrorBag) {
+    for (name, error) in err.errors() {
+        let key = match prefix {
+            Some(prefix) => format!("{}.{}", prefix, name),
+            None => name.to_string(),
+        };
 
+        match error {
+            ValidationErrorsKind::Field(error) => errors
+                .entry(key)
+                .or_default()
+                .extend(error.iter().map(message)),
+            ValidationErrorsKind::Struct(nested) => flatten_errors(nested, Some(&key), errors),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    flatten_errors(nested, Some(&format!("{}[{}]", key, index)), errors);
+                }
+            }
+        };
+    }
+}
+
+/// The message of the validator, or a default one based on
+/// the error code when the validator does not define any.
+fn message(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+
+    let param = |name: &str| error.params.get(name).map(|v| v.to_string().replace('"', ""));
+
+    return match error.code.as_ref() {
+        "required" => "This field is required.".to_string(),
+        "email" => "Invalid email.".to_string(),
+        "url" => "Invalid URL.".to_string(),
+        "must_match" => match param("other") {
+            Some(other) => format!("Does not match with {} field.", other),
+            None => "Does not match.".to_string(),
+        },
+        "length" => match (param("min"), param("max"), param("equal")) {
+            (_, _, Some(equal)) => format!("The length must be {}.", equal),
+            (Some(min), Some(max), _) => format!("The length must be in range {}-{}.", min, max),
+            (Some(min), None, _) => format!("The length must be at least {}.", min),
+            (None, Some(max), _) => format!("The length must be at most {}.", max),
+            _ => "Invalid length.".to_string(),
+        },
+        "range" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("The value must be between {} and {}.", min, max),
+            (Some(min), None) => format!("The value must be at least {}.", min),
+            (None, Some(max)) => format!("The value must be at most {}.", max),
+            _ => "The value is out of range.".to_string(),
+        },
+        "contains" => match param("needle") {
+            Some(needle) => format!("The value must contain \"{}\".", needle),
+            None => "Invalid value.".to_string(),
+        },
+        "regex" => "Invalid format.".to_string(),
+        "credit_card" => "Invalid credit card number.".to_string(),
+        "phone" => "Invalid phone number.".to_string(),
+        "non_control_character" => "The value contains invalid characters.".to_string(),
+        _ => "This field is invalid.".to_string(),
+    };
+}
+
+/// A request body that passed its validation. The body is
+/// read as an urlencoded form, JSON or multipart form based on
+/// its `Content-Type`. Failures are rendered following the
+/// `Accept` header: problem details for API clients, the form
+/// with its errors for browsers.
 #[derive(Debug)]
-pub(super) struct ValidatedForm<T>(pub(super) T);
+pub(super) struct Validated<T>(pub(super) T);
 
 #[async_trait]
-impl<T, S, B> FromRequest<S, B> for ValidatedForm<T>
+impl<T, S> FromRequest<S, Body> for Validated<T>
 where
     S: Send + Sync,
-    B: Send + 'static,
-    T: DeserializeOwned + Validate + RenderErrorsAsHtml,
-    Form<T>: FromRequest<S, B, Rejection = FormRejection>,
+    T: DeserializeOwned + Validate + RenderErrorsAsHtml + Send,
 {
     type Rejection = ApplicationError;
 
-    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
-        return match Form::<T>::from_request(request, state).await {
-            Err(err) => Err(ApplicationError::AxumFormRejection(err)),
-            Ok(Form(value)) => match value.validate() {
-                Err(err) => {
-                    let mut errors: ErrorBag = HashMap::new();
-
-                    for (name, error) in err.errors() {
-                        match &error {
-                            &ValidationErrorsKind::Field(error) => errors.insert(
-                                name.to_string(),
-                                error
-                                    .iter()
-                                    .map(|e| e.message.as_ref().unwrap().clone().into_owned())
-                                    .collect(),
-                            ),
-                            _ => unimplemented!(),
-                        };
-                    }
-
-                    Err(ApplicationError::ValidationError(Some(
-                        value.render(&errors),
-                    )))
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let is_htmx = request.headers().contains_key("HX-Request");
+        let wants_json = wants_json(request.headers());
+        let content_type = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+
+        let bad_request = |status: StatusCode, detail: String| {
+            ApplicationError::Problem(ProblemDetails::new(status, "Bad request").detail(&detail))
+        };
+
+        let value: T = if content_type.starts_with("application/json") {
+            Json::<T>::from_request(request, state)
+                .await
+                .map(|Json(value)| value)
+                .map_err(|e| bad_request(e.status(), e.body_text()))?
+        } else if content_type.starts_with("multipart/form-data") {
+            let mut multipart = Multipart::from_request(request, state)
+                .await
+                .map_err(|e| bad_request(e.status(), e.body_text()))?;
+
+            // Only the text fields are validated, uploaded
+            // files are left to the handlers. The fields are
+            // read like an urlencoded form, so numbers, booleans
+            // and repeated fields work the same way.
+            let mut fields: Vec<(String, String)> = Vec::new();
+
+            while let Some(field) = multipart
+                .next_field()
+                .await
+                .map_err(|e| bad_request(StatusCode::BAD_REQUEST, e.to_string()))?
+            {
+                let Some(name) = field.name().map(String::from) else {
+                    continue;
+                };
+
+                if field.file_name().is_some() {
+                    continue;
+                }
+
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|e| bad_request(StatusCode::BAD_REQUEST, e.to_string()))?;
+                fields.push((name, text));
+            }
+
+            from_fields(&fields).map_err(|e| bad_request(StatusCode::UNPROCESSABLE_ENTITY, e))?
+        } else {
+            match Form::<T>::from_request(request, state).await {
+                Ok(Form(value)) => value,
+                Err(err) if wants_json => return Err(bad_request(err.status(), err.body_text())),
+                Err(err) => return Err(ApplicationError::AxumFormRejection(err)),
+            }
+        };
+
+        return match value.validate() {
+            Err(err) => {
+                let errors = error_bag(&err);
+
+                if wants_json {
+                    return Err(ApplicationError::Problem(ProblemDetails::validation(errors)));
                 }
-                Ok(_) => Ok(ValidatedForm(value)),
+
+                // Clients without htmx replace the whole page
+                // with the response, so they need the full page.
+                Err(ApplicationError::ValidationError(Some(if is_htmx {
+                    value.render(&errors)
+                } else {
+                    value.render_page(&errors)
+                })))
+            }
+            Ok(_) => Ok(Validated(value)),
+        };
+    }
+}
+
+/// Read the text fields of a multipart form the way `Form`
+/// reads an urlencoded body.
+fn from_fields<T: DeserializeOwned>(fields: &[(String, String)]) -> Result<T, String> {
+    let encoded = serde_urlencoded::to_string(fields).map_err(|e| e.to_string())?;
+
+    return serde_urlencoded::from_str(&encoded).map_err(|e| e.to_string());
+}
+
+/// Whether the client prefers JSON over HTML, going by the
+/// order of the media types in its `Accept` header.
+pub(super) fn wants_json(headers: &HeaderMap) -> bool {
+    let accept = headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    for media_type in accept.split(',') {
+        let media_type = media_type.split(';').next().unwrap_or("").trim();
+
+        if media_type == "text/html" {
+            return false;
+        }
+
+        if media_type == "application/json" || media_type.ends_with("+json") {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+/// A JSON body that passed its validation. Failures are
+/// answered with problem details listing the errors.
+#[derive(Debug)]
+pub(super) struct ValidatedJson<T>(pub(super) T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ValidatedJson<T>
+where
+    S: Send + Sync,
+    B: Send + 'static,
+    T: DeserializeOwned + Validate,
+    Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+{
+    type Rejection = ProblemDetails;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        return match Json::<T>::from_request(request, state).await {
+            Err(err) => Err(ProblemDetails::new(err.status(), "Bad request").detail(&err.body_text())),
+            Ok(Json(value)) => match value.validate() {
+                Err(err) => Err(ProblemDetails::validation(error_bag(&err))),
+                Ok(_) => Ok(ValidatedJson(value)),
             },
         };
     }
 }
+
+/// The logged-in user, if there is one.
+#[derive(Debug)]
+pub(super) struct OptionalUser(pub(super) Option<User>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for OptionalUser
+where
+    S: Send + Sync,
+{
+    type Rejection = ApplicationError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let auth = Auth::from_request_parts(parts, state).await?;
+
+        return Ok(OptionalUser(auth.get_user().await?.cloned()));
+    }
+}
+
+/// The logged-in user. Guests are sent to the login page.
+#[derive(Debug)]
+pub(super) struct RequiredUser(pub(super) User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequiredUser
+where
+    S: Send + Sync,
+{
+    type Rejection = ApplicationError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let OptionalUser(user) = OptionalUser::from_request_parts(parts, state).await?;
+
+        return user
+            .map(RequiredUser)
+            .ok_or(ApplicationError::Unauthenticated);
+    }
+}
+
+/// Whether the request was made by htmx. Requests without
+/// it come from a browser without JavaScript and must get
+/// full pages and real redirects.
+#[derive(Debug)]
+pub(super) struct HxRequest(pub(super) bool);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for HxRequest
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        return Ok(HxRequest(parts.headers.contains_key("HX-Request")));
+    }
+}
+
+/// The address of the client, as seen by the server.
+pub(super) fn client_ip(extensions: &Extensions) -> Option<IpAddr> {
+    return extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip());
+}
+
+#[derive(Debug)]
+pub(super) struct ClientIp(pub(super) Option<IpAddr>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        return Ok(ClientIp(client_ip(&parts.extensions)));
+    }
+}
+
+/// The user of the JWT access token in the `Authorization:
+/// Bearer` header. The token must be signed by one of our
+/// keys and must not have expired.
+#[derive(Debug)]
+pub(super) struct JwtUser(pub(super) User);
+
+#[async_trait]
+impl FromRequestParts<AppContext> for JwtUser {
+    type Rejection = ProblemDetails;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        context: &AppContext,
+    ) -> Result<Self, Self::Rejection> {
+        let invalid = |detail: &str| ProblemDetails::unauthenticated().detail(detail);
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| invalid("The request has no bearer token."))?;
+
+        let claims: Claims = context
+            .jwt
+            .verify(token.trim(), context.jwt.issuer())
+            .map_err(|e| invalid(&e))?;
+
+        let user_id = claims
+            .sub
+            .parse::<u32>()
+            .map_err(|_| invalid("The token subject is invalid."))?;
+
+        return match user_cache::find(&context.redis, &context.db, user_id).await? {
+            Some(user) => Ok(JwtUser(user)),
+            None => Err(invalid("The user of the token no longer exists.")),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::testing::{app_with, body_text, context, create_user, Browser};
+    use axum::{response::Response, Router};
+    use serde::Deserialize;
+    use sqlx::MySqlPool;
+
+    const PASSWORD: &str = "correct-horse-battery-staple";
+
+    async fn app(db: MySqlPool) -> Router {
+        create_user(&db, "janedoe", PASSWORD).await;
+
+        return app_with(context(db)).await;
+    }
+
+    /// Submit a form of the authentication pages with the
+    /// CSRF token of the session, with or without htmx.
+    async fn submit(browser: &mut Browser, uri: &str, fields: &str, is_htmx: bool) -> Response {
+        let token = browser.csrf_token().await;
+        let mut request =
+            Request::post(uri).header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if is_htmx {
+            request = request.header("HX-Request", "true");
+        }
+
+        let body = format!("{}&_token={}", fields, token);
+
+        return browser.send(request, body).await;
+    }
+
+    fn assert_redirects(response: &Response, to: &str, is_htmx: bool) {
+        if is_htmx {
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["HX-Redirect"], to);
+            assert!(!response.headers().contains_key("Location"));
+        } else {
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+            assert_eq!(response.headers()["Location"], to);
+            assert!(!response.headers().contains_key("HX-Redirect"));
+        }
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_answers_an_invalid_login_with_the_whole_page_without_htmx(db: MySqlPool) {
+        let mut browser = Browser::new(app(db).await);
+
+        let response = submit(&mut browser, "/login", "username=janedoe", false).await;
+        let body = body_text(response).await;
+
+        assert!(body.starts_with("<!DOCTYPE html>"));
+        assert!(body.contains("This field is required."));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_answers_an_invalid_login_with_the_form_to_htmx(db: MySqlPool) {
+        let mut browser = Browser::new(app(db).await);
+
+        let response = submit(&mut browser, "/login", "username=janedoe", true).await;
+        let body = body_text(response).await;
+
+        assert!(!body.contains("<!DOCTYPE html>"));
+        assert!(body.contains("<form"));
+        assert!(body.contains("This field is required."));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_answers_wrong_credentials_with_the_whole_page_without_htmx(db: MySqlPool) {
+        let mut browser = Browser::new(app(db).await);
+
+        let fields = "username=janedoe&password=wrong";
+        let response = submit(&mut browser, "/login", fields, false).await;
+        let body = body_text(response).await;
+
+        assert!(body.starts_with("<!DOCTYPE html>"));
+        assert!(body.contains("Invalid username or password"));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_answers_an_invalid_form_with_problem_details_to_api_clients(db: MySqlPool) {
+        let mut browser = Browser::new(app(db).await);
+
+        let token = browser.csrf_token().await;
+        let request = Request::post("/login")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(ACCEPT, "application/json");
+        let body = format!("username=janedoe&_token={}", token);
+        let response = browser.send(request, body).await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body_text(response).await.contains("\"password\""));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_redirects_after_logging_in(db: MySqlPool) {
+        let app = app(db).await;
+
+        for is_htmx in [false, true] {
+            let mut browser = Browser::new(app.clone());
+
+            let fields = format!("username=janedoe&password={}", PASSWORD);
+            let response = submit(&mut browser, "/login", &fields, is_htmx).await;
+
+            assert_redirects(&response, "/home", is_htmx);
+        }
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_redirects_to_the_login_page_after_registering(db: MySqlPool) {
+        let app = app_with(context(db)).await;
+
+        for (username, is_htmx) in [("alice", false), ("bobby", true)] {
+            let mut browser = Browser::new(app.clone());
+
+            let fields = format!(
+                "username={}&email={}%40example.org&password={}&password_confirmation={}",
+                username, username, PASSWORD, PASSWORD
+            );
+            let response = submit(&mut browser, "/register", &fields, is_htmx).await;
+
+            assert_redirects(&response, "/login", is_htmx);
+        }
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_redirects_to_the_login_page_after_logging_out(db: MySqlPool) {
+        let app = app(db).await;
+
+        for is_htmx in [false, true] {
+            let mut browser = Browser::new(app.clone());
+            let fields = format!("username=janedoe&password={}", PASSWORD);
+            submit(&mut browser, "/login", &fields, is_htmx).await;
+
+            let response = submit(&mut browser, "/logout", "", is_htmx).await;
+
+            assert_redirects(&response, "/login", is_htmx);
+        }
+    }
+
+    #[derive(Validate)]
+    struct Address {
+        #[validate(length(min = 1))]
+        city: String,
+    }
+
+    #[derive(Validate)]
+    struct Phone {
+        #[validate(length(min = 5, message = "Too short."))]
+        number: String,
+    }
+
+    #[derive(Validate)]
+    struct Person {
+        #[validate(required)]
+        name: Option<String>,
+        #[validate]
+        address: Address,
+        #[validate]
+        phones: Vec<Phone>,
+    }
+
+    fn person(city: &str, phones: &[&str]) -> Person {
+        return Person {
+            name: None,
+            address: Address {
+                city: city.to_string(),
+            },
+            phones: phones
+                .iter()
+                .map(|number| Phone {
+                    number: number.to_string(),
+                })
+                .collect(),
+        };
+    }
+
+    #[test]
+    fn it_keys_the_errors_of_nested_structures_by_their_path() {
+        let person = person("", &["0123456789", "012"]);
+        let errors = error_bag(&person.validate().unwrap_err());
+
+        assert_eq!(errors["name"], vec!["This field is required."]);
+        assert_eq!(
+            errors["address.city"],
+            vec!["The length must be at least 1."]
+        );
+        assert_eq!(errors["phones[1].number"], vec!["Too short."]);
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn it_leaves_out_the_valid_items() {
+        let person = person("Paris", &["012", "0123456789"]);
+        let errors = error_bag(&person.validate().unwrap_err());
+
+        assert!(errors.contains_key("phones[0].number"));
+        assert!(!errors.contains_key("phones[1].number"));
+        assert!(!errors.contains_key("address.city"));
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Fields {
+        name: String,
+        age: u32,
+        admin: bool,
+        #[serde(default)]
+        nickname: Option<String>,
+    }
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        return pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+    }
+
+    #[test]
+    fn it_reads_the_multipart_fields_like_a_form() {
+        let value: Fields = from_fields(&fields(&[
+            ("name", "jane"),
+            ("age", "42"),
+            ("admin", "true"),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            value,
+            Fields {
+                name: "jane".to_string(),
+                age: 42,
+                admin: true,
+                nickname: None,
+            }
+        );
+    }
+
+    #[test]
+    fn it_refuses_invalid_multipart_fields() {
+        let value = from_fields::<Fields>(&fields(&[
+            ("name", "jane"),
+            ("age", "old"),
+            ("admin", "true"),
+        ]));
+
+        assert!(value.is_err());
+    }
+}
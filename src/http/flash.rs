@@ -0,0 +1,145 @@
+use super::error::ApplicationError;
+use crate::view::flash::flash_messages_oob;
+use async_trait::async_trait;
+use axum::{
+    body::{boxed, Body, Empty},
+    extract::FromRequestParts,
+    http::{header::CONTENT_TYPE, request::Parts, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_session::{Session, SessionRedisPool};
+use serde::{Deserialize, Serialize};
+
+const SESSION_KEY: &str = "_flash";
+
+tokio::task_local! {
+    static CURRENT: Flash;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Success,
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FlashMessage {
+    pub level: Level,
+    pub text: String,
+}
+
+/// Messages kept in the session until the next page is
+/// rendered, e.g. to confirm an action that redirects.
+#[derive(Clone)]
+pub struct Flash {
+    session: Session<SessionRedisPool>,
+}
+
+impl Flash {
+    pub fn new(session: Session<SessionRedisPool>) -> Self {
+        return Self { session };
+    }
+
+    pub fn success(&self, text: impl Into<String>) {
+        self.push(Level::Success, text.into());
+    }
+
+    pub fn info(&self, text: impl Into<String>) {
+        self.push(Level::Info, text.into());
+    }
+
+    pub fn warning(&self, text: impl Into<String>) {
+        self.push(Level::Warning, text.into());
+    }
+
+    pub fn error(&self, text: impl Into<String>) {
+        self.push(Level::Error, text.into());
+    }
+
+    fn push(&self, level: Level, text: String) {
+        let mut messages = self.pending();
+        messages.push(FlashMessage { level, text });
+        self.session.set(SESSION_KEY, messages);
+    }
+
+    fn pending(&self) -> Vec<FlashMessage> {
+        return self
+            .session
+            .get::<Vec<FlashMessage>>(SESSION_KEY)
+            .unwrap_or_default();
+    }
+
+    /// Remove and return the pending messages.
+    pub fn take(&self) -> Vec<FlashMessage> {
+        return self
+            .session
+            .get_remove::<Vec<FlashMessage>>(SESSION_KEY)
+            .unwrap_or_default();
+    }
+}
+
+/// The pending flash messages of the current request. Layouts
+/// call this, so every rendered page shows them.
+pub fn take_flashes() -> Vec<FlashMessage> {
+    return CURRENT.try_with(|flash| flash.take()).unwrap_or_default();
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Flash
+where
+    S: Send + Sync,
+{
+    type Rejection = ApplicationError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        return Session::<SessionRedisPool>::from_request_parts(parts, state)
+            .await
+            .map(Flash::new)
+            .map_err(|(_, e)| ApplicationError::ServerError(e.to_string()));
+    }
+}
+
+/// Make the flash messages available to the views. Fragments
+/// returned to htmx get the messages that were not rendered
+/// appended as an out-of-band swap. Redirects keep them for
+/// the page that is loaded next.
+pub async fn flash(request: Request<Body>, next: Next<Body>) -> Response {
+    let session = request
+        .extensions()
+        .get::<Session<SessionRedisPool>>()
+        .expect("The session layer must run before the flash middleware")
+        .clone();
+    let is_htmx = request.headers().contains_key("HX-Request");
+    let flash = Flash::new(session);
+
+    let response = CURRENT.scope(flash.clone(), next.run(request)).await;
+
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("text/html"))
+        .unwrap_or(false);
+    let is_redirect =
+        response.status().is_redirection() || response.headers().contains_key("HX-Redirect");
+
+    if !is_htmx || !is_html || is_redirect || flash.pending().is_empty() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let Ok(bytes) = hyper::body::to_bytes(body).await else {
+        return Response::from_parts(parts, boxed(Empty::new()));
+    };
+
+    let mut content = String::from_utf8_lossy(&bytes).into_owned();
+    content.push_str(&flash_messages_oob(flash.take()).into_string());
+
+    let mut response = Response::from_parts(parts, content.into_response().into_body());
+    response.headers_mut().remove("content-length");
+
+    return response;
+}
@@ -0,0 +1,308 @@
+use std::{fs, path::Path};
+
+use super::utils::random_token;
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use rsa::{pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+/// Lifetime of the access tokens, in seconds.
+pub const ACCESS_TOKEN_TTL: i64 = 15 * 60;
+
+/// A key pair used to sign or verify tokens. Retired keys
+/// only have their public half left and can no longer sign,
+/// but tokens they signed keep verifying until they expire.
+pub struct SigningKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+    jwk: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Claims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+}
+
+pub struct JwtKeys {
+    issuer: String,
+    keys: Vec<SigningKey>,
+    active: Option<String>,
+}
+
+impl JwtKeys {
+    /// Load the keys from the directory. Each key is stored as
+    /// `<kid>.<alg>.pub.pem`, plus `<kid>.<alg>.pem` holding the
+    /// private half for keys that still sign. `alg` is `eddsa`
+    /// or `rs256`. The signing key is `active_kid`, or else the
+    /// last key (by kid) that has a private half.
+    pub fn load(
+        directory: impl AsRef<Path>,
+        issuer: &str,
+        active_kid: Option<&str>,
+    ) -> Result<Self, String> {
+        let mut keys = Vec::new();
+        let entries = fs::read_dir(directory.as_ref()).map_err(|e| {
+            format!(
+                "Cannot read the keys in {}: {}. Create a key with `scripts/generate_jwt_key.sh`, or set JWT_KEYS_DIR to the directory of the keys.",
+                directory.as_ref().display(),
+                e
+            )
+        })?;
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(stem) = file_name.strip_suffix(".pub.pem") else {
+                continue;
+            };
+            let Some((kid, algorithm)) = stem.rsplit_once('.') else {
+                continue;
+            };
+
+            let algorithm = match algorithm {
+                "eddsa" => Algorithm::EdDSA,
+                "rs256" => Algorithm::RS256,
+                other => return Err(format!("Unsupported key algorithm: {}", other)),
+            };
+
+            let public_pem = fs::read(entry.path()).map_err(|e| e.to_string())?;
+            let private_pem = fs::read(directory.as_ref().join(format!("{}.pem", stem))).ok();
+
+            keys.push(SigningKey::new(
+                kid,
+                algorithm,
+                &public_pem,
+                private_pem.as_deref(),
+            )?);
+        }
+
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+
+        let active = match active_kid {
+            Some(kid) => Some(kid.to_string()),
+            None => keys
+                .iter()
+                .rev()
+                .find(|key| key.encoding.is_some())
+                .map(|key| key.kid.clone()),
+        };
+
+        let jwt_keys = Self {
+            issuer: issuer.to_string(),
+            keys,
+            active,
+        };
+
+        if jwt_keys.signing_key().is_err() {
+            return Err(format!(
+                "No key in {} can sign the tokens. Create a key with `scripts/generate_jwt_key.sh`, or check JWT_ACTIVE_KID.",
+                directory.as_ref().display()
+            ));
+        }
+
+        return Ok(jwt_keys);
+    }
+
+    pub fn issuer(&self) -> &str {
+        return &self.issuer;
+    }
+
+    fn signing_key(&self) -> Result<&SigningKey, String> {
+        return self
+            .active
+            .as_ref()
+            .and_then(|kid| self.keys.iter().find(|key| &key.kid == kid))
+            .filter(|key| key.encoding.is_some())
+            .ok_or_else(|| "No signing key is configured".to_string());
+    }
+
+    /// Sign the claims with the active key.
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, String> {
+        let key = self.signing_key()?;
+        let header = Header {
+            kid: Some(key.kid.clone()),
+            ..Header::new(key.algorithm)
+        };
+
+        return encode(&header, claims, key.encoding.as_ref().unwrap()).map_err(|e| e.to_string());
+    }
+
+    /// Issue an access token for the user, returning the token
+    /// and its lifetime in seconds.
+    pub fn issue_access_token(&self, user_id: u32) -> Result<(String, i64), String> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let claims = Claims {
+            iss: self.issuer.clone(),
+            sub: user_id.to_string(),
+            aud: self.issuer.clone(),
+            iat: now,
+            exp: now + ACCESS_TOKEN_TTL,
+            jti: random_token(16),
+        };
+
+        return Ok((self.sign(&claims)?, ACCESS_TOKEN_TTL));
+    }
+
+    /// Verify the signature, issuer, audience and expiry of the
+    /// token with the key named by its `kid` header.
+    pub fn verify<T: for<'de> Deserialize<'de>>(
+        &self,
+        token: &str,
+        audience: &str,
+    ) -> Result<T, String> {
+        return self.decode(token, |validation| validation.set_audience(&[audience]));
+    }
+
+    /// Verify that we issued the token, whoever it was issued to
+    /// and even if it has expired, e.g. for an `id_token_hint`
+    /// telling which client a logout request comes from.
+    pub fn verify_issued<T: for<'de> Deserialize<'de>>(&self, token: &str) -> Result<T, String> {
+        return self.decode(token, |validation| {
+            validation.validate_aud = false;
+            validation.validate_exp = false;
+        });
+    }
+
+    fn decode<T: for<'de> Deserialize<'de>>(
+        &self,
+        token: &str,
+        configure: impl FnOnce(&mut Validation),
+    ) -> Result<T, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+        let kid = header.kid.ok_or("The token has no key id")?;
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.kid == kid)
+            .ok_or("The token was signed with an unknown key")?;
+
+        if header.alg != key.algorithm {
+            return Err("The token algorithm does not match its key".to_string());
+        }
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        configure(&mut validation);
+
+        return decode::<T>(token, &key.decoding, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| e.to_string());
+    }
+
+    /// The names of the algorithms of the keys, as listed in
+    /// the OpenID Connect discovery document.
+    pub fn algorithms(&self) -> Vec<&'static str> {
+        let mut algorithms: Vec<&'static str> = self
+            .keys
+            .iter()
+            .map(|key| algorithm_name(key.algorithm))
+            .collect();
+        algorithms.sort();
+        algorithms.dedup();
+
+        return algorithms;
+    }
+
+    /// The public keys as a JSON Web Key Set.
+    pub fn jwks(&self) -> Value {
+        return json!({
+            "keys": self.keys.iter().map(|key| key.jwk.clone()).collect::<Vec<_>>(),
+        });
+    }
+}
+
+impl SigningKey {
+    fn new(
+        kid: &str,
+        algorithm: Algorithm,
+        public_pem: &[u8],
+        private_pem: Option<&[u8]>,
+    ) -> Result<Self, String> {
+        let (decoding, encoding, jwk) = match algorithm {
+            Algorithm::EdDSA => {
+                // An Ed25519 SubjectPublicKeyInfo ends with
+                // the 32 bytes of the raw public key.
+                let der = pem_to_der(public_pem)?;
+                let raw = der
+                    .get(der.len().saturating_sub(32)..)
+                    .filter(|raw| raw.len() == 32)
+                    .ok_or("Invalid Ed25519 public key")?;
+
+                (
+                    DecodingKey::from_ed_pem(public_pem).map_err(|e| e.to_string())?,
+                    private_pem
+                        .map(EncodingKey::from_ed_pem)
+                        .transpose()
+                        .map_err(|e| e.to_string())?,
+                    json!({
+                        "kty": "OKP",
+                        "crv": "Ed25519",
+                        "x": URL_SAFE_NO_PAD.encode(raw),
+                    }),
+                )
+            }
+            Algorithm::RS256 => {
+                let pem = std::str::from_utf8(public_pem).map_err(|e| e.to_string())?;
+                let public_key =
+                    RsaPublicKey::from_public_key_pem(pem).map_err(|e| e.to_string())?;
+
+                (
+                    DecodingKey::from_rsa_pem(public_pem).map_err(|e| e.to_string())?,
+                    private_pem
+                        .map(EncodingKey::from_rsa_pem)
+                        .transpose()
+                        .map_err(|e| e.to_string())?,
+                    json!({
+                        "kty": "RSA",
+                        "n": URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+                        "e": URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+                    }),
+                )
+            }
+            _ => return Err("Unsupported key algorithm".to_string()),
+        };
+
+        let mut jwk = jwk;
+        jwk["kid"] = json!(kid);
+        jwk["use"] = json!("sig");
+        jwk["alg"] = json!(algorithm_name(algorithm));
+
+        return Ok(Self {
+            kid: kid.to_string(),
+            algorithm,
+            encoding,
+            decoding,
+            jwk,
+        });
+    }
+}
+
+fn algorithm_name(algorithm: Algorithm) -> &'static str {
+    return match algorithm {
+        Algorithm::EdDSA => "EdDSA",
+        _ => "RS256",
+    };
+}
+
+fn pem_to_der(pem: &[u8]) -> Result<Vec<u8>, String> {
+    let pem = std::str::from_utf8(pem).map_err(|e| e.to_string())?;
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+
+    return STANDARD.decode(body.trim()).map_err(|e| e.to_string());
+}
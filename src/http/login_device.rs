@@ -0,0 +1,306 @@
+use std::net::IpAddr;
+
+use super::{
+    error::ApplicationError,
+    user_cache,
+    user_session::{self, device},
+    utils::{random_token, sha256_hex},
+};
+use redis_pool::SingleRedisPool;
+use serde_json::Value;
+use sqlx::MySqlPool;
+use time::{Duration, OffsetDateTime};
+
+/// How long the "this wasn't me" link of the alert stays
+/// valid.
+pub const ALERT_TTL_DAYS: i64 = 7;
+
+/// A device the user has logged in from, as far as we can
+/// tell it apart: the browser and system of the user agent,
+/// and the network of the address.
+pub struct KnownDevice {
+    pub device: String,
+    pub ip_range: Option<String>,
+    pub first_seen_at: OffsetDateTime,
+    pub last_seen_at: OffsetDateTime,
+}
+
+/// A login that did not come from one of the known devices
+/// of the user, to alert them about.
+pub struct NewDevice {
+    pub device: String,
+    pub ip_range: Option<String>,
+    /// The token of the link to disown the login, only its
+    /// hash is stored.
+    pub token: String,
+}
+
+/// The network the address belongs to. Addresses change
+/// often within a network, e.g. when a phone reconnects, so
+/// the devices are told apart by the network instead.
+fn ip_range(ip: IpAddr) -> String {
+    return match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+        }
+    };
+}
+
+/// Record that the user logged in from the device. Returns
+/// the device when neither it nor its network has been seen
+/// before. The very first device of the user is not new, the
+/// user has just registered with it.
+pub async fn remember(
+    db: &MySqlPool,
+    user_id: u32,
+    user_agent: &str,
+    ip: Option<IpAddr>,
+) -> Result<Option<NewDevice>, ApplicationError> {
+    let device = device(user_agent);
+    let ip_range = ip.map(ip_range);
+    let fingerprint = sha256_hex(&format!(
+        "{}|{}",
+        device,
+        ip_range.as_deref().unwrap_or_default()
+    ));
+
+    let known = sqlx::query!(
+        "select device, ip_range from user_devices where user_id = ?",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let is_new = !known.is_empty()
+        && (!known.iter().any(|record| record.device == device)
+            || !known.iter().any(|record| record.ip_range == ip_range));
+
+    let token = is_new.then(|| random_token(32));
+    let alert_expires_at =
+        is_new.then(|| OffsetDateTime::now_utc() + Duration::days(ALERT_TTL_DAYS));
+
+    sqlx::query!(
+        "insert into user_devices (user_id, fingerprint, device, ip_range, alert_token, alert_expires_at)
+        values (?, ?, ?, ?, ?, ?)
+        on duplicate key update last_seen_at = current_timestamp",
+        user_id,
+        fingerprint,
+        device,
+        ip_range,
+        token.as_deref().map(sha256_hex),
+        alert_expires_at
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(token.map(|token| NewDevice {
+        device,
+        ip_range,
+        token,
+    }));
+}
+
+/// The devices of the user, the most recently seen first.
+pub async fn list(db: &MySqlPool, user_id: u32) -> Result<Vec<KnownDevice>, ApplicationError> {
+    let records = sqlx::query!(
+        "select device, ip_range, first_seen_at, last_seen_at from user_devices
+        where user_id = ? order by last_seen_at desc",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(records
+        .into_iter()
+        .map(|record| KnownDevice {
+            device: record.device,
+            ip_range: record.ip_range,
+            first_seen_at: record.first_seen_at,
+            last_seen_at: record.last_seen_at,
+        })
+        .collect());
+}
+
+/// A login attempt of the user, read from the audit log.
+pub struct SignIn {
+    pub succeeded: bool,
+    pub device: String,
+    pub ip: Option<String>,
+    /// The identity provider or the API the user logged in
+    /// with, if not the login page.
+    pub method: Option<String>,
+    pub created_at: OffsetDateTime,
+}
+
+/// The latest logins of the user, the most recent first. The
+/// failed attempts are only known by the username they were
+/// made with.
+pub async fn history(
+    db: &MySqlPool,
+    user_id: u32,
+    limit: u32,
+) -> Result<Vec<SignIn>, ApplicationError> {
+    let records = sqlx::query!(
+        "select outcome, user_agent, ip, metadata, created_at from audit_events
+        where event = 'login'
+            and (user_id = ? or (user_id is null and username = (select username from users where id = ?)))
+        order by id desc
+        limit ?",
+        user_id,
+        user_id,
+        limit
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(records
+        .into_iter()
+        .map(|record| {
+            let metadata = record
+                .metadata
+                .and_then(|metadata| serde_json::from_str::<Value>(&metadata).ok());
+            let method = metadata.and_then(|metadata| {
+                ["provider", "via"]
+                    .iter()
+                    .find_map(|key| metadata.get(key)?.as_str().map(str::to_string))
+            });
+
+            SignIn {
+                succeeded: record.outcome == "success",
+                device: device(record.user_agent.as_deref().unwrap_or_default()),
+                ip: record.ip,
+                method,
+                created_at: record.created_at,
+            }
+        })
+        .collect());
+}
+
+/// The login an alert was sent for.
+pub struct LoginAlert {
+    pub user_id: u32,
+    pub device: String,
+    pub ip_range: Option<String>,
+    pub first_seen_at: OffsetDateTime,
+}
+
+pub async fn find_alert(
+    db: &MySqlPool,
+    token: &str,
+) -> Result<Option<LoginAlert>, ApplicationError> {
+    let record = sqlx::query!(
+        "select user_id, device, ip_range, first_seen_at from user_devices
+        where alert_token = ? and alert_expires_at > current_timestamp",
+        sha256_hex(token)
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| LoginAlert {
+        user_id: record.user_id,
+        device: record.device,
+        ip_range: record.ip_range,
+        first_seen_at: record.first_seen_at,
+    }));
+}
+
+/// The user did not log in from the device of the alert,
+/// so whoever did knows the password. The device is
+/// forgotten, the password replaced, and the user logged out
+/// everywhere with the tokens issued to them revoked. The
+/// token can only be used once.
+pub async fn disown(
+    db: &MySqlPool,
+    redis: &SingleRedisPool,
+    token: &str,
+    password_hash: &str,
+) -> Result<Option<LoginAlert>, ApplicationError> {
+    let mut transaction = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let record = sqlx::query!(
+        "select id, user_id, device, ip_range, first_seen_at from user_devices
+        where alert_token = ? and alert_expires_at > current_timestamp for update",
+        sha256_hex(token)
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    sqlx::query!("delete from user_devices where id = ?", record.id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update users set password = ? where id = ?",
+        password_hash,
+        record.user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "delete from personal_access_tokens where user_id = ?",
+        record.user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update refresh_tokens set revoked_at = current_timestamp where user_id = ? and revoked_at is null",
+        record.user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update oauth_access_tokens set revoked_at = current_timestamp where user_id = ? and revoked_at is null",
+        record.user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update oauth_refresh_tokens set revoked_at = current_timestamp where user_id = ? and revoked_at is null",
+        record.user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    user_cache::forget(redis, record.user_id).await?;
+    user_session::revoke_others(redis, record.user_id, None).await?;
+
+    return Ok(Some(LoginAlert {
+        user_id: record.user_id,
+        device: record.device,
+        ip_range: record.ip_range,
+        first_seen_at: record.first_seen_at,
+    }));
+}
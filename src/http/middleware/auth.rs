@@ -1,69 +1,241 @@
+use std::sync::Arc;
+
+use crate::http::{
+    error::{ApplicationError, ProblemDetails},
+    extractor::client_ip,
+    personal_access_token::{self, PersonalAccessToken},
+    role::{self, Grants},
+    user_cache, user_session,
+};
 use crate::AppContext;
-use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{FromRequestParts, State},
+    http::{
+        header::{AUTHORIZATION, USER_AGENT},
+        request::Parts,
+        Request,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use axum_session::{Session, SessionRedisPool};
+use redis_pool::SingleRedisPool;
+use serde::Serialize;
 use sqlx::MySqlPool;
+use tokio::sync::OnceCell;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct User {
     pub id: u32,
     pub username: String,
     pub email: String,
 }
 
+/// The authentication state of the current request. The
+/// user is only loaded the first time a handler asks for
+/// it, so requests that never look at the user (static
+/// files, the login page, ...) do not hit the database.
 #[derive(Clone)]
 pub struct Auth {
-    user: Option<User>,
+    user: Arc<OnceCell<Option<User>>>,
+    grants: Arc<OnceCell<Grants>>,
+    token: Option<PersonalAccessToken>,
     session: Session<SessionRedisPool>,
     db: MySqlPool,
+    redis: SingleRedisPool,
 }
 
 impl Auth {
-    pub fn get_user(&self) -> Option<&User> {
-        return self.user.as_ref();
+    /// The id of the logged-in user, read from the access
+    /// token or the session without loading the user itself.
+    pub fn id(&self) -> Option<u32> {
+        if let Some(token) = &self.token {
+            return Some(token.user_id);
+        }
+
+        return self.session.get::<u32>("user_id");
     }
 
-    pub async fn set_user(&mut self) {
-        if let Some(user_id) = self.session.get::<i32>("user_id") {
-            let result = sqlx::query!(
-                "select id, username, email from users where id = ?",
-                user_id
-            )
-            .fetch_optional(&self.db)
-            .await;
+    /// The personal access token the request was
+    /// authenticated with, if any.
+    pub fn token(&self) -> Option<&PersonalAccessToken> {
+        return self.token.as_ref();
+    }
 
-            if let Ok(record) = result {
-                let user = User {
-                    id: record.as_ref().unwrap().id,
-                    username: record.as_ref().unwrap().username.clone(),
-                    email: record.as_ref().unwrap().email.clone(),
+    /// Whether the request may act within the scope. Sessions
+    /// may do anything, tokens only what they were given.
+    pub fn can(&self, scope: &str) -> bool {
+        return match &self.token {
+            Some(token) => token.can(scope),
+            None => self.check(),
+        };
+    }
+
+    /// Whether the session belongs to a logged-in user.
+    pub fn check(&self) -> bool {
+        return self.id().is_some();
+    }
+
+    pub async fn get_user(&self) -> Result<Option<&User>, ApplicationError> {
+        let user = self
+            .user
+            .get_or_try_init(|| async {
+                return match self.id() {
+                    Some(user_id) => user_cache::find(&self.redis, &self.db, user_id).await,
+                    None => Ok(None),
                 };
+            })
+            .await?;
 
-                self.user = Some(user);
-            } else if let Err(e) = result {
-                panic!("Error getting user: {}", e);
-            }
-        }
+        return Ok(user.as_ref());
+    }
+
+    /// The roles of the logged-in user and the permissions
+    /// they give, loaded the first time they are asked for.
+    /// Tokens do not carry them, they only act within their
+    /// scopes.
+    pub async fn grants(&self) -> Result<&Grants, ApplicationError> {
+        return self
+            .grants
+            .get_or_try_init(|| async {
+                return match self.id().filter(|_| self.token.is_none()) {
+                    Some(user_id) => role::grants(&self.db, user_id).await,
+                    None => Ok(Grants::default()),
+                };
+            })
+            .await;
+    }
+
+    /// Whether one of the roles of the logged-in user gives
+    /// the permission.
+    pub async fn has_permission(&self, permission: &str) -> Result<bool, ApplicationError> {
+        return Ok(self.grants().await?.can(permission));
     }
 }
 
+#[async_trait]
+impl<S> FromRequestParts<S> for Auth
+where
+    S: Send + Sync,
+{
+    type Rejection = ApplicationError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        return parts.extensions.get::<Auth>().cloned().ok_or_else(|| {
+            ApplicationError::ServerError("The auth middleware is not installed".to_string())
+        });
+    }
+}
+
+/// The personal access tokens are only accepted by the API,
+/// so a leaked token can not be used on the HTML routes to
+/// manage the account or mint other tokens.
+const TOKEN_PATH_PREFIX: &str = "/api/";
+
+/// The personal access token of the `Authorization: Bearer`
+/// header, if the request has one and is made to the API.
+fn bearer_token(request: &Request<Body>) -> Option<&str> {
+    if !request.uri().path().starts_with(TOKEN_PATH_PREFIX) {
+        return None;
+    }
+
+    return request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|v| v.starts_with(personal_access_token::PREFIX));
+}
+
 pub async fn auth(
-    State(AppContext { db }): State<AppContext>,
+    State(AppContext {
+        db,
+        redis,
+        session_limits,
+        ..
+    }): State<AppContext>,
     mut request: Request<Body>,
     next: Next<Body>,
 ) -> Response {
-    let mut auth = Auth {
-        session: request
-            .extensions()
-            .get::<Session<SessionRedisPool>>()
-            .unwrap()
-            .clone(),
-        db,
-        user: None,
+    // A request presenting a token is authenticated by that
+    // token alone. An unknown or expired token is rejected
+    // instead of falling back to a guest.
+    let token = match bearer_token(&request) {
+        None => None,
+        Some(token) => match personal_access_token::find_valid(&db, token).await {
+            Ok(Some(token)) => {
+                let ip = client_ip(request.extensions());
+
+                if let Err(e) = personal_access_token::touch(&db, token.id, ip).await {
+                    return e.into_response();
+                }
+
+                Some(token)
+            }
+            Ok(None) => {
+                return ProblemDetails::unauthenticated()
+                    .detail("The access token is invalid or has expired.")
+                    .into_response();
+            }
+            Err(e) => return ProblemDetails::from(e).into_response(),
+        },
     };
 
-    auth.set_user().await;
+    let session = request
+        .extensions()
+        .get::<Session<SessionRedisPool>>()
+        .unwrap()
+        .clone();
+
+    // A session revoked from another device, or which has
+    // expired, is logged out before the request is handled.
+    let is_session = token.is_none();
+    let ip = client_ip(request.extensions());
+    let session_id = user_session::current_id(&session);
+    if is_session {
+        if let Err(e) = user_session::verify(&redis, &session_limits, &session, ip).await {
+            return e.into_response();
+        }
+    }
+
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let auth = Auth {
+        token,
+        session: session.clone(),
+        db,
+        redis: redis.clone(),
+        user: Arc::new(OnceCell::new()),
+        grants: Arc::new(OnceCell::new()),
+    };
 
     request.extensions_mut().insert(auth);
 
-    return next.run(request).await;
+    let response = next.run(request).await;
+
+    // The handler may have logged the session in or out.
+    if is_session {
+        if let Err(e) = user_session::sync(
+            &redis,
+            &session_limits,
+            &session,
+            session_id,
+            &user_agent,
+            ip,
+        )
+        .await
+        {
+            return e.into_response();
+        }
+    }
+
+    return response;
 }
@@ -0,0 +1,436 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::http::{
+    error::ProblemDetails,
+    extractor::wants_json,
+    utils::{constant_time_eq, random_token},
+};
+use crate::view::error::page_expired;
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{FromRequest, Multipart},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        Method, Request, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use axum_session::{Session, SessionRedisPool};
+use serde::Deserialize;
+use tower::{Layer, Service as TowerService};
+
+const SESSION_KEY: &str = "_csrf_token";
+const API_PATH_PREFIX: &str = "/api/";
+const HEADER_NAME: &str = "X-CSRF-Token";
+pub const FIELD_NAME: &str = "_token";
+
+/// The largest form read for the token, the same as the
+/// `DefaultBodyLimit` of the extractors.
+const BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+tokio::task_local! {
+    static TOKEN: String;
+}
+
+/// The CSRF token of the session handling the current
+/// request. Views call this to embed the token into forms
+/// and into the htmx headers.
+pub fn csrf_token() -> String {
+    return TOKEN.try_with(|token| token.clone()).unwrap_or_default();
+}
+
+/// Why a state-changing request was refused.
+#[derive(Debug, PartialEq)]
+enum Refusal {
+    Mismatch,
+    TooLarge,
+}
+
+#[derive(Deserialize)]
+struct TokenField {
+    #[serde(rename = "_token")]
+    token: Option<String>,
+}
+
+/// Verify that every state-changing request carries the
+/// synchronizer token stored in the session, either in the
+/// `X-CSRF-Token` header (htmx) or in the `_token` field of
+/// an urlencoded or multipart form (plain HTML forms). The
+/// JSON API only reads the header, its clients get the token
+/// from `GET /api/v1/csrf-token`. Requests with an
+/// `Authorization: Bearer` header are not verified, browsers
+/// never add it to the requests of another site. Forms are
+/// read up to the 2 MB the extractors accept.
+#[derive(Clone)]
+pub struct VerifyCsrfToken {
+    except: Vec<&'static str>,
+}
+
+impl<S> Layer<S> for VerifyCsrfToken {
+    type Service = Service<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        return Service {
+            next: service,
+            except: self.except.clone(),
+        };
+    }
+}
+
+impl VerifyCsrfToken {
+    pub fn new() -> Self {
+        return Self { except: vec![] };
+    }
+
+    /// Skip the verification for the paths of the route, e.g.
+    /// `/saml/:tenant/:provider/acs`.
+    pub fn except(mut self, route: &'static str) -> Self {
+        self.except.push(route);
+        return self;
+    }
+}
+
+#[derive(Clone)]
+pub struct Service<S> {
+    next: S,
+    except: Vec<&'static str>,
+}
+
+impl<S> TowerService<Request<Body>> for Service<S>
+where
+    S: TowerService<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.next.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // Take the service that was driven to readiness and
+        // leave a fresh clone in its place.
+        let clone = self.next.clone();
+        let mut next = std::mem::replace(&mut self.next, clone);
+        let except = self.except.clone();
+
+        return Box::pin(async move {
+            let session = request
+                .extensions()
+                .get::<Session<SessionRedisPool>>()
+                .expect("The session layer must run before the CSRF layer")
+                .clone();
+
+            let token = match session.get::<String>(SESSION_KEY) {
+                Some(token) => token,
+                None => {
+                    let token = random_token(32);
+                    session.set(SESSION_KEY, &token);
+                    token
+                }
+            };
+
+            let is_excepted = is_bearer(&request)
+                || except
+                    .iter()
+                    .any(|route| matches(route, request.uri().path()));
+
+            let request = if is_reading(request.method()) || is_excepted {
+                request
+            } else {
+                let is_json = is_api(&request) || wants_json(request.headers());
+
+                match verify(request, &token).await {
+                    Ok(request) => request,
+                    Err(refusal) => return Ok(refuse(refusal, is_json)),
+                }
+            };
+
+            return TOKEN.scope(token, next.call(request)).await;
+        });
+    }
+}
+
+fn is_reading(method: &Method) -> bool {
+    return [Method::GET, Method::HEAD, Method::OPTIONS].contains(method);
+}
+
+/// Whether the path is one of the route, whose `:name`
+/// segments match any segment.
+fn matches(route: &str, path: &str) -> bool {
+    let mut route = route.split('/');
+    let mut path = path.split('/');
+
+    loop {
+        match (route.next(), path.next()) {
+            (None, None) => return true,
+            (Some(expected), Some(segment))
+                if expected == segment || (expected.starts_with(':') && !segment.is_empty()) =>
+            {
+                continue
+            }
+            _ => return false,
+        }
+    }
+}
+
+fn is_api(request: &Request<Body>) -> bool {
+    return request.uri().path().starts_with(API_PATH_PREFIX);
+}
+
+fn is_bearer(request: &Request<Body>) -> bool {
+    return request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("Bearer "));
+}
+
+/// Check the submitted token and give the request back when
+/// it matches. The body of forms has to be read for this, so
+/// the request is rebuilt from the buffered body.
+async fn verify(request: Request<Body>, token: &str) -> Result<Request<Body>, Refusal> {
+    if let Some(submitted) = request.headers().get(HEADER_NAME) {
+        return match submitted.to_str() {
+            Ok(submitted) if constant_time_eq(submitted, token) => Ok(request),
+            _ => Err(Refusal::Mismatch),
+        };
+    }
+
+    // The JSON bodies of the API are not read for a token.
+    if is_api(&request) {
+        return Err(Refusal::Mismatch);
+    }
+
+    let content_type = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    let is_form = content_type.starts_with("application/x-www-form-urlencoded");
+    let is_multipart = content_type.starts_with("multipart/form-data");
+
+    if !is_form && !is_multipart {
+        return Err(Refusal::Mismatch);
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = read_limited(body).await?;
+    let submitted = if is_form {
+        serde_urlencoded::from_bytes::<TokenField>(&bytes)
+            .ok()
+            .and_then(|field| field.token)
+    } else {
+        multipart_token(&content_type, bytes.clone()).await
+    };
+
+    match submitted {
+        Some(submitted) if constant_time_eq(&submitted, token) => {}
+        _ => return Err(Refusal::Mismatch),
+    }
+
+    return Ok(Request::from_parts(parts, Body::from(bytes)));
+}
+
+/// Buffer the body, up to `BODY_LIMIT` bytes.
+async fn read_limited(mut body: Body) -> Result<Bytes, Refusal> {
+    if body.size_hint().lower() > BODY_LIMIT as u64 {
+        return Err(Refusal::TooLarge);
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| Refusal::Mismatch)?;
+        if bytes.len() + chunk.len() > BODY_LIMIT {
+            return Err(Refusal::TooLarge);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    return Ok(Bytes::from(bytes));
+}
+
+/// Answer a request without a valid token with problem
+/// details for the JSON clients, or else with the page
+/// asking to reload the form. A form too large to be read
+/// gets a `413` instead.
+fn refuse(refusal: Refusal, is_json: bool) -> Response {
+    if refusal == Refusal::TooLarge {
+        let problem = ProblemDetails::new(StatusCode::PAYLOAD_TOO_LARGE, "Payload too large")
+            .detail("The body of the request is larger than 2 MB.");
+        if is_json {
+            return problem.into_response();
+        }
+
+        return (StatusCode::PAYLOAD_TOO_LARGE, "The request is too large.").into_response();
+    }
+
+    if is_json {
+        return ProblemDetails::new(StatusCode::FORBIDDEN, "CSRF token mismatch")
+            .kind("/problems/csrf-token-mismatch")
+            .detail("Send the token of GET /api/v1/csrf-token in the X-CSRF-Token header.")
+            .into_response();
+    }
+
+    return (StatusCode::FORBIDDEN, page_expired()).into_response();
+}
+
+/// The `_token` field of a multipart form. Browsers send the
+/// fields in the order of the form, so the field is expected
+/// before the files, but all of them are looked at.
+async fn multipart_token(content_type: &str, bytes: Bytes) -> Option<String> {
+    let request = Request::builder()
+        .header(CONTENT_TYPE, content_type)
+        .body(Body::from(bytes))
+        .ok()?;
+    let mut multipart = Multipart::from_request(request, &()).await.ok()?;
+
+    while let Some(field) = multipart.next_field().await.ok()? {
+        if field.name() == Some(FIELD_NAME) {
+            return field.text().await.ok();
+        }
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDARY: &str = "X-BOUNDARY";
+
+    fn multipart_request(token: &str) -> Request<Body> {
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"_token\"\r\n\r\n{token}\r\n\
+            --{b}\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"a.txt\"\r\nContent-Type: text/plain\r\n\r\nhello\r\n\
+            --{b}--\r\n",
+            b = BOUNDARY,
+            token = token
+        );
+
+        return Request::post("/settings/account")
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(body))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_accepts_the_token_of_a_multipart_form() {
+        let request = verify(multipart_request("secret"), "secret").await.unwrap();
+
+        // The handler still gets the whole body.
+        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn it_refuses_a_multipart_form_with_another_token() {
+        assert_eq!(
+            verify(multipart_request("other"), "secret").await.err(),
+            Some(Refusal::Mismatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_accepts_the_token_of_an_urlencoded_form() {
+        let request = Request::post("/settings/account")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("username=jane&_token=secret"))
+            .unwrap();
+
+        assert!(verify(request, "secret").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_a_form_without_token() {
+        let request = Request::post("/settings/account")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("username=jane"))
+            .unwrap();
+
+        assert_eq!(
+            verify(request, "secret").await.err(),
+            Some(Refusal::Mismatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_refuses_a_form_larger_than_the_limit() {
+        let body = format!("_token=secret&bio={}", "a".repeat(BODY_LIMIT));
+        let request = Request::post("/settings/account")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap();
+
+        let refusal = verify(request, "secret").await.err().unwrap();
+        assert_eq!(refusal, Refusal::TooLarge);
+        assert_eq!(
+            refuse(refusal, false).status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reads_only_the_header_under_the_api() {
+        let form = Request::post("/api/v1/logout")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("_token=secret"))
+            .unwrap();
+        let header = Request::post("/api/v1/logout")
+            .header(HEADER_NAME, "secret")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(verify(form, "secret").await.is_err());
+        assert!(verify(header, "secret").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_json_clients_with_problem_details() {
+        let body = hyper::body::to_bytes(refuse(Refusal::Mismatch, true).into_body())
+            .await
+            .unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(problem["status"], 403);
+        assert_eq!(problem["type"], "/problems/csrf-token-mismatch");
+    }
+
+    #[test]
+    fn it_matches_the_paths_of_the_excepted_routes() {
+        let route = "/saml/:tenant/:provider/acs";
+
+        assert!(matches(route, "/saml/acme/okta/acs"));
+        assert!(!matches(route, "/saml/acme/okta/login"));
+        assert!(!matches(route, "/saml/acme/acs"));
+        assert!(!matches(route, "/saml//okta/acs"));
+        assert!(!matches(route, "/saml/acme/okta/acs/more"));
+        assert!(matches("/oauth/token", "/oauth/token"));
+        assert!(!matches("/oauth/token", "/oauth/tokens"));
+    }
+
+    #[test]
+    fn it_skips_the_requests_with_a_bearer_token() {
+        let request = Request::post("/api/v1/token/revoke")
+            .header(AUTHORIZATION, "Bearer token")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(is_bearer(&request));
+        assert!(!is_bearer(
+            &Request::post("/api/v1/logout").body(Body::empty()).unwrap()
+        ));
+    }
+}
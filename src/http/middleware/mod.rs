@@ -1,5 +1,13 @@
 mod auth;
+mod csrf;
+mod permission;
 mod redirect_if_authenticated;
+mod security_headers;
+mod sudo;
 
-pub use auth::{auth, Auth};
+pub use auth::{auth, Auth, User};
+pub use csrf::{csrf_token, VerifyCsrfToken, FIELD_NAME as CSRF_FIELD_NAME};
+pub use permission::RequirePermission;
 pub use redirect_if_authenticated::RedirectIfAuthenticated;
+pub use security_headers::{csp_nonce, SecurityHeaders, SecurityHeadersConfig};
+pub use sudo::RequireSudo;
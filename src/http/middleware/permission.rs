@@ -0,0 +1,95 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::Auth;
+use crate::http::{authentication::login, response::redirect};
+use crate::view::error::forbidden;
+use axum::{
+    body::Body,
+    extract::OriginalUri,
+    http::{Method, Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_session::{Session, SessionRedisPool};
+use tower::{Layer, Service as TowerService};
+
+fn forbidden_response() -> Response {
+    return (StatusCode::FORBIDDEN, forbidden()).into_response();
+}
+
+/// Only let the users whose roles give the permission through
+/// to the routes behind this layer, e.g.
+/// `RequirePermission(MANAGE_USERS)`. Guests are sent to
+/// the login page. The permissions are only given to logged-in
+/// sessions, requests made with an access token are refused.
+#[derive(Clone)]
+pub struct RequirePermission(pub &'static str);
+
+impl<S> Layer<S> for RequirePermission {
+    type Service = Service<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        return Service {
+            next: service,
+            permission: self.0,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Service<S> {
+    next: S,
+    permission: &'static str,
+}
+
+impl<S> TowerService<Request<Body>> for Service<S>
+where
+    S: TowerService<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.next.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let clone = self.next.clone();
+        let mut next = std::mem::replace(&mut self.next, clone);
+        let permission = self.permission;
+
+        return Box::pin(async move {
+            let auth = request
+                .extensions()
+                .get::<Auth>()
+                .expect("The auth middleware must run before the permission layer")
+                .clone();
+
+            if !auth.check() {
+                if request.method() == Method::GET {
+                    if let (Some(session), Some(OriginalUri(uri))) = (
+                        request.extensions().get::<Session<SessionRedisPool>>(),
+                        request.extensions().get::<OriginalUri>(),
+                    ) {
+                        login::intend(session, &uri.to_string());
+                    }
+                }
+
+                let is_htmx = request.headers().contains_key("HX-Request");
+
+                return Ok(redirect(is_htmx, "/login"));
+            }
+
+            return match auth.has_permission(permission).await {
+                Ok(true) => next.call(request).await,
+                Ok(false) => Ok(forbidden_response()),
+                Err(e) => Ok(e.into_response()),
+            };
+        });
+    }
+}
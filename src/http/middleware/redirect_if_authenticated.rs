@@ -46,7 +46,7 @@ where
         return Box::pin(async move {
             let auth = request.extensions().get::<Auth>().unwrap();
 
-            if auth.get_user().is_some() && ["/login", "/register"].contains(&request.uri().path())
+            if auth.check() && ["/login", "/register"].contains(&request.uri().path())
             {
                 return Err("".to_string());
             }
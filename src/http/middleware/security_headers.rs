@@ -0,0 +1,268 @@
+use std::{
+    env,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::http::utils::random_token;
+use axum::{
+    body::Body,
+    http::{HeaderName, HeaderValue, Request},
+    response::Response,
+};
+use tower::{Layer, Service as TowerService};
+
+tokio::task_local! {
+    static NONCE: String;
+}
+
+/// The CSP nonce of the current request. Views put it on
+/// every `<script>` they render.
+pub fn csp_nonce() -> String {
+    return NONCE.try_with(|nonce| nonce.clone()).unwrap_or_default();
+}
+
+/// Configuration of the security headers. Every header can
+/// be replaced or turned off with `None`. The placeholder
+/// `{nonce}` in the content security policy is replaced with
+/// the nonce generated for the request.
+#[derive(Clone, Debug)]
+pub struct SecurityHeadersConfig {
+    pub content_security_policy: Option<String>,
+    pub strict_transport_security: Option<String>,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub permissions_policy: Option<String>,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        return Self {
+            content_security_policy: Some(
+                [
+                    "default-src 'self'",
+                    "script-src 'self' 'nonce-{nonce}'",
+                    "style-src 'self'",
+                    "img-src 'self' data:",
+                    "object-src 'none'",
+                    "base-uri 'self'",
+                    "form-action 'self'",
+                    "frame-ancestors 'none'",
+                ]
+                .join("; "),
+            ),
+            strict_transport_security: Some("max-age=31536000; includeSubDomains".to_string()),
+            frame_options: Some("DENY".to_string()),
+            referrer_policy: Some("strict-origin-when-cross-origin".to_string()),
+            permissions_policy: Some("camera=(), microphone=(), geolocation=()".to_string()),
+        };
+    }
+}
+
+impl SecurityHeadersConfig {
+    /// Read the headers from `SECURITY_CSP`, `SECURITY_HSTS`,
+    /// `SECURITY_FRAME_OPTIONS`, `SECURITY_REFERRER_POLICY` and
+    /// `SECURITY_PERMISSIONS_POLICY`. A variable that is not
+    /// set keeps the default, `off` turns the header off.
+    pub fn from_env() -> Result<Self, String> {
+        return Self::from_lookup(|name| env::var(name).ok());
+    }
+
+    /// Read the headers of `from_env` with `lookup`, which
+    /// returns the value of a variable if it is set.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let var = |name: &str| -> Result<Option<Option<String>>, String> {
+            let name = format!("SECURITY_{}", name);
+
+            return match lookup(&name) {
+                None => Ok(None),
+                Some(value) if value == "off" => Ok(Some(None)),
+                Some(value) if HeaderValue::from_str(&value).is_ok() => Ok(Some(Some(value))),
+                Some(_) => Err(format!("{} is not a valid header value", name)),
+            };
+        };
+
+        let mut config = Self::default();
+        if let Some(value) = var("CSP")? {
+            config = config.with_content_security_policy(value.as_deref());
+        }
+        if let Some(value) = var("HSTS")? {
+            config = config.with_strict_transport_security(value.as_deref());
+        }
+        if let Some(value) = var("FRAME_OPTIONS")? {
+            config = config.with_frame_options(value.as_deref());
+        }
+        if let Some(value) = var("REFERRER_POLICY")? {
+            config = config.with_referrer_policy(value.as_deref());
+        }
+        if let Some(value) = var("PERMISSIONS_POLICY")? {
+            config = config.with_permissions_policy(value.as_deref());
+        }
+
+        return Ok(config);
+    }
+
+    pub fn with_content_security_policy(mut self, policy: Option<&str>) -> Self {
+        self.content_security_policy = policy.map(String::from);
+        return self;
+    }
+
+    pub fn with_strict_transport_security(mut self, value: Option<&str>) -> Self {
+        self.strict_transport_security = value.map(String::from);
+        return self;
+    }
+
+    pub fn with_frame_options(mut self, value: Option<&str>) -> Self {
+        self.frame_options = value.map(String::from);
+        return self;
+    }
+
+    pub fn with_referrer_policy(mut self, value: Option<&str>) -> Self {
+        self.referrer_policy = value.map(String::from);
+        return self;
+    }
+
+    pub fn with_permissions_policy(mut self, value: Option<&str>) -> Self {
+        self.permissions_policy = value.map(String::from);
+        return self;
+    }
+
+    fn headers(&self, nonce: &str) -> Vec<(&'static str, String)> {
+        return [
+            (
+                "content-security-policy",
+                self.content_security_policy
+                    .as_ref()
+                    .map(|policy| policy.replace("{nonce}", nonce)),
+            ),
+            (
+                "strict-transport-security",
+                self.strict_transport_security.clone(),
+            ),
+            ("x-frame-options", self.frame_options.clone()),
+            ("referrer-policy", self.referrer_policy.clone()),
+            ("permissions-policy", self.permissions_policy.clone()),
+            ("x-content-type-options", Some("nosniff".to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect();
+    }
+}
+
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    config: Arc<SecurityHeadersConfig>,
+}
+
+impl<S> Layer<S> for SecurityHeaders {
+    type Service = Service<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        return Service {
+            next: service,
+            config: self.config.clone(),
+        };
+    }
+}
+
+impl SecurityHeaders {
+    pub fn new(config: SecurityHeadersConfig) -> Self {
+        return Self {
+            config: Arc::new(config),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Service<S> {
+    next: S,
+    config: Arc<SecurityHeadersConfig>,
+}
+
+impl<S> TowerService<Request<Body>> for Service<S>
+where
+    S: TowerService<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.next.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let clone = self.next.clone();
+        let mut next = std::mem::replace(&mut self.next, clone);
+        let config = self.config.clone();
+
+        return Box::pin(async move {
+            let nonce = random_token(16);
+            let mut response = NONCE.scope(nonce.clone(), next.call(request)).await?;
+
+            for (name, value) in config.headers(&nonce) {
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    response
+                        .headers_mut()
+                        .insert(HeaderName::from_static(name), value);
+                }
+            }
+
+            return Ok(response);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn header(config: &SecurityHeadersConfig, name: &str) -> Option<String> {
+        return config
+            .headers("n0nce")
+            .into_iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value);
+    }
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<SecurityHeadersConfig, String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+
+        return SecurityHeadersConfig::from_lookup(|name| vars.get(name).map(|v| v.to_string()));
+    }
+
+    #[test]
+    fn it_puts_the_nonce_in_the_content_security_policy() {
+        let config = SecurityHeadersConfig::default();
+
+        assert!(header(&config, "content-security-policy")
+            .unwrap()
+            .contains("script-src 'self' 'nonce-n0nce'"));
+    }
+
+    #[test]
+    fn it_reads_the_headers_from_the_environment() {
+        let config = from_vars(&[
+            ("SECURITY_HSTS", "off"),
+            ("SECURITY_FRAME_OPTIONS", "SAMEORIGIN"),
+        ])
+        .unwrap();
+
+        assert_eq!(header(&config, "strict-transport-security"), None);
+        assert_eq!(
+            header(&config, "x-frame-options").as_deref(),
+            Some("SAMEORIGIN")
+        );
+        assert_eq!(
+            header(&config, "referrer-policy").as_deref(),
+            Some("strict-origin-when-cross-origin")
+        );
+
+        assert!(from_vars(&[("SECURITY_REFERRER_POLICY", "no-referrer\n")]).is_err());
+    }
+}
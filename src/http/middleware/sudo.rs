@@ -0,0 +1,141 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::Auth;
+use crate::http::{
+    authentication::{confirm_password, login},
+    response::redirect,
+};
+use crate::view::error::forbidden;
+use axum::{
+    body::Body,
+    extract::OriginalUri,
+    http::{header::REFERER, Method, Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_session::{Session, SessionRedisPool};
+use time::Duration;
+use tower::{Layer, Service as TowerService};
+
+/// How long a password confirmation lasts when the
+/// `SUDO_WINDOW_MINUTES` variable is not set.
+const DEFAULT_WINDOW_MINUTES: i64 = 15;
+
+/// Send the user to confirm their password before the
+/// sensitive actions behind this layer, unless they logged in
+/// or confirmed it within the window. Requests made with an
+/// access token have no password to confirm and are refused.
+#[derive(Clone)]
+pub struct RequireSudo {
+    window: Duration,
+}
+
+impl<S> Layer<S> for RequireSudo {
+    type Service = Service<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        return Service {
+            next: service,
+            window: self.window,
+        };
+    }
+}
+
+impl RequireSudo {
+    pub fn new() -> Self {
+        let minutes = std::env::var("SUDO_WINDOW_MINUTES")
+            .ok()
+            .and_then(|minutes| minutes.parse::<i64>().ok())
+            .unwrap_or(DEFAULT_WINDOW_MINUTES);
+
+        return Self {
+            window: Duration::minutes(minutes),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Service<S> {
+    next: S,
+    window: Duration,
+}
+
+impl<S> TowerService<Request<Body>> for Service<S>
+where
+    S: TowerService<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.next.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let clone = self.next.clone();
+        let mut next = std::mem::replace(&mut self.next, clone);
+        let window = self.window;
+
+        return Box::pin(async move {
+            let auth = request
+                .extensions()
+                .get::<Auth>()
+                .expect("The auth middleware must run before the sudo layer")
+                .clone();
+
+            if auth.token().is_some() {
+                return Ok((StatusCode::FORBIDDEN, forbidden()).into_response());
+            }
+
+            let session = request
+                .extensions()
+                .get::<Session<SessionRedisPool>>()
+                .expect("The session layer must run before the sudo layer")
+                .clone();
+
+            // Guests are left to the handler, which sends them
+            // to the login page.
+            if !auth.check() || confirm_password::is_fresh(&session, window) {
+                return next.call(request).await;
+            }
+
+            // A form cannot be submitted again after the
+            // confirmation, so the user goes back to the page
+            // it was on instead.
+            let back = if request.method() == Method::GET {
+                // The path as requested, before nested routers
+                // strip their prefix from it.
+                request
+                    .extensions()
+                    .get::<OriginalUri>()
+                    .map(|OriginalUri(uri)| uri.to_string())
+            } else {
+                request
+                    .headers()
+                    .get(REFERER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(referer_path)
+            };
+            login::intend(&session, back.as_deref().unwrap_or("/settings/account"));
+
+            let is_htmx = request.headers().contains_key("HX-Request");
+
+            return Ok(redirect(is_htmx, "/confirm-password"));
+        });
+    }
+}
+
+/// The path of the referring page, which browsers send as an
+/// absolute URL. Only the path is kept, so the user is never
+/// sent back to another site.
+fn referer_path(referer: &str) -> Option<String> {
+    let (_, rest) = referer.split_once("://")?;
+    let path = &rest[rest.find('/')?..];
+
+    return Some(path.to_string());
+}
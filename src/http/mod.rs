@@ -1,81 +1,223 @@
+mod account_deletion;
+mod admin;
+mod api;
+mod assets;
+mod audit;
 mod authentication;
 mod check_email;
 mod check_username;
+mod credentials;
+mod data_export;
+mod email_change;
 mod error;
 mod extractor;
+mod flash;
+mod jwt;
+mod login_device;
+mod mailer;
 mod middleware;
+mod oauth;
+mod personal_access_token;
+mod refresh_token;
+mod response;
+mod role;
+mod settings;
+mod sso;
+#[cfg(test)]
+mod testing;
+mod user_cache;
+mod user_session;
 mod utils;
+mod well_known;
 
-use axum::{routing::get, Extension, Router};
-use axum_session::{Key, Session, SessionConfig, SessionLayer, SessionRedisPool, SessionStore};
-use middleware::{auth, RedirectIfAuthenticated};
-use redis_pool::RedisPool;
+use std::{env, net::SocketAddr, sync::Arc};
+
+use axum::{routing::get, Router};
+use axum_session::{Key, SessionConfig, SessionLayer, SessionRedisPool, SessionStore};
+use credentials::CredentialProvider;
+use extractor::RequiredUser;
+use jwt::JwtKeys;
+use mailer::Mailer;
+use middleware::{
+    auth, RedirectIfAuthenticated, SecurityHeaders, SecurityHeadersConfig, VerifyCsrfToken,
+};
+use redis_pool::{RedisPool, SingleRedisPool};
 use sqlx::MySqlPool;
 use tower::ServiceBuilder;
-use tower_http::services::ServeDir;
 
+pub use assets::{asset, find as find_asset};
+pub use audit::{export as export_audit_events, parse_date, AuditFilter, AuditRecord};
 pub use authentication::{LoginAttempRequest, RegisterRequest};
+pub use data_export::{build as build_data_export, DataExport};
 pub use error::ErrorBag;
-
-use self::middleware::Auth;
+pub use flash::{take_flashes, FlashMessage, Level as FlashLevel};
+pub use login_device::{KnownDevice, LoginAlert, SignIn};
+pub use middleware::{csp_nonce, csrf_token, CSRF_FIELD_NAME};
+pub use oauth::{
+    client::{create as create_oauth_client, NewClient as NewOAuthClient},
+    GRANT_TYPES as OAUTH_GRANT_TYPES, SCOPES as OAUTH_SCOPES,
+};
+pub use personal_access_token::{PersonalAccessToken, SCOPES as PERSONAL_ACCESS_TOKEN_SCOPES};
+pub use role::{add as add_role, Role, UserRoles, ADMINISTRATOR};
+pub use sso::{
+    providers as sso_providers,
+    saml::provider::{
+        create as create_saml_provider, validate_metadata as validate_saml_metadata,
+        NewSamlProvider,
+    },
+};
+pub use user_session::{SessionLimits, UserSession};
 
 #[derive(Clone)]
 pub struct AppContext {
     db: MySqlPool,
+    redis: SingleRedisPool,
+    jwt: Arc<JwtKeys>,
+    credentials: Arc<dyn CredentialProvider>,
+    mailer: Arc<Mailer>,
+    session_limits: SessionLimits,
 }
 
-pub async fn server(db: MySqlPool) {
-    let app_context = AppContext { db };
-
-    // Setup redis pool connections.
+/// Setup redis pool connections.
+pub fn redis_pool() -> SingleRedisPool {
     let redis_url = "redis://default@localhost:6379";
     let redis_client = redis::Client::open(redis_url).unwrap();
-    let redis_pool = RedisPool::from(redis_client);
 
-    // Setup session store.
+    return RedisPool::from(redis_client);
+}
+
+pub async fn server(db: MySqlPool) {
+    let redis_pool = redis_pool();
+
+    // Load the keys signing the JWT access tokens from
+    // `JWT_KEYS_DIR`, the newest one signing unless
+    // `JWT_ACTIVE_KID` is set. See `scripts/generate_jwt_key.sh`
+    // to create one.
+    let app_url = env::var("APP_URL").unwrap_or("http://localhost:3000".to_string());
+    let jwt_keys = match JwtKeys::load(
+        env::var("JWT_KEYS_DIR").unwrap_or("keys/jwt".to_string()),
+        &app_url,
+        env::var("JWT_ACTIVE_KID").ok().as_deref(),
+    ) {
+        Ok(jwt_keys) => jwt_keys,
+        Err(error) => {
+            println!("Failed to load the JWT keys: {}", error);
+            std::process::exit(1);
+        }
+    };
+    sso::init(&app_url).expect("Failed to load the identity providers");
+
+    // Check the passwords against our database, or against
+    // the directory set up with the `LDAP_*` variables.
+    let credentials =
+        credentials::from_env(db.clone()).expect("Failed to load the credential provider");
+
+    // Emails are sent over `SMTP_URL`, or printed in
+    // development with `MAIL_LOG=true`.
+    let mailer = Mailer::from_env(&app_url).expect("Failed to load the mailer");
+
+    // Logged-in sessions expire after `SESSION_IDLE_MINUTES`
+    // without activity and `SESSION_LIFETIME_HOURS` at most.
+    let session_limits = SessionLimits::from_env().expect("Failed to load the session limits");
+
+    // The security headers can be changed or turned off with
+    // the `SECURITY_*` variables.
+    let security_headers =
+        SecurityHeadersConfig::from_env().expect("Failed to load the security headers");
+
+    let app_context = AppContext {
+        db,
+        redis: redis_pool.clone(),
+        jwt: Arc::new(jwt_keys),
+        credentials,
+        mailer: Arc::new(mailer),
+        session_limits,
+    };
+
+    // Deleted accounts are purged once their grace period
+    // is over, see `ACCOUNT_DELETION_GRACE_DAYS`.
+    tokio::spawn(account_deletion::purge_periodically(
+        app_context.db.clone(),
+        app_context.redis.clone(),
+    ));
+
+    let session_store = session_store(&redis_pool, &session_limits).await;
+
+    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
+        .serve(
+            app(app_context, session_store, security_headers)
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+}
+
+/// Setup session store.
+async fn session_store(
+    redis_pool: &SingleRedisPool,
+    session_limits: &SessionLimits,
+) -> SessionStore<SessionRedisPool> {
     let session_config = SessionConfig::default()
         .with_secure(true)
         .with_cookie_same_site(axum_session::SameSite::Lax)
+        .with_lifetime(chrono::Duration::seconds(
+            session_limits.retention().whole_seconds(),
+        ))
         .with_key(Key::generate());
-    let session_store =
-        SessionStore::<SessionRedisPool>::new(Some(redis_pool.clone().into()), session_config)
-            .await
-            .expect("Failed to create session store");
 
-    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
-        .serve(
-            router_web()
+    return SessionStore::<SessionRedisPool>::new(Some(redis_pool.clone().into()), session_config)
+        .await
+        .expect("Failed to create session store");
+}
+
+/// The routes behind the whole middleware stack, as served
+/// by the server and driven by the tests.
+fn app(
+    app_context: AppContext,
+    session_store: SessionStore<SessionRedisPool>,
+    security_headers: SecurityHeadersConfig,
+) -> Router {
+    return router_web()
+        .layer(
+            ServiceBuilder::new()
+                .layer(SecurityHeaders::new(security_headers))
+                .layer(SessionLayer::new(session_store))
                 .layer(
-                    ServiceBuilder::new()
-                        .layer(SessionLayer::new(session_store))
-                        .layer(axum::middleware::from_fn_with_state(
-                            app_context.clone(),
-                            auth,
-                        ))
-                        .layer(RedirectIfAuthenticated::new()),
+                    VerifyCsrfToken::new()
+                        .except("/api/v1/token")
+                        .except("/api/v1/token/refresh")
+                        .except("/api/v1/token/revoke")
+                        .except("/oauth/token")
+                        .except("/oauth/revoke")
+                        .except("/oauth/introspect")
+                        .except("/oauth/userinfo")
+                        .except("/saml/:tenant/:provider/acs"),
                 )
-                .with_state(app_context)
-                .into_make_service(),
+                .layer(axum::middleware::from_fn(flash::flash))
+                .layer(axum::middleware::from_fn_with_state(
+                    app_context.clone(),
+                    auth,
+                ))
+                .layer(RedirectIfAuthenticated::new()),
         )
-        .await
-        .unwrap();
+        .with_state(app_context);
 }
 
 fn router_web() -> Router<AppContext> {
     return Router::new()
-        .nest_service("/public", ServeDir::new("public"))
+        .merge(assets::router())
+        .nest("/admin", admin::router())
+        .nest("/api/v1", api::router())
         .route("/home", get(get_home))
         .merge(authentication::router())
         .merge(check_email::router())
-        .merge(check_username::router());
+        .merge(check_username::router())
+        .merge(oauth::router())
+        .nest("/settings", settings::router())
+        .merge(sso::router())
+        .merge(well_known::router());
 }
 
-async fn get_home(session: Session<SessionRedisPool>, auth: Extension<Auth>) -> String {
-    let user = auth.get_user();
-    println!("{:?}", user);
-    let user = auth.get_user();
-    println!("{:?}", user);
-    let username: String = session.get("username").unwrap();
-
-    return format!("Hello, {}!", username);
+async fn get_home(RequiredUser(user): RequiredUser) -> String {
+    return format!("Hello, {}!", user.username);
 }
@@ -0,0 +1,327 @@
+use super::{
+    callback_url,
+    client::{self, Client},
+    grant::{self, AuthorizationCode},
+    parse_scope, SCOPES,
+};
+use crate::http::{
+    authentication::login,
+    error::ApplicationError,
+    extractor::{OptionalUser, RequiredUser},
+    AppContext,
+};
+use crate::view::oauth::{authorization_error_page, consent_page, redirect_page};
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Form, Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use serde::Deserialize;
+use sqlx::MySqlPool;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/oauth/authorize", get(show).post(store));
+}
+
+#[derive(Deserialize, Debug)]
+struct AuthorizationRequest {
+    response_type: Option<String>,
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    scope: Option<String>,
+    state: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    nonce: Option<String>,
+    decision: Option<String>,
+}
+
+/// An authorization request that passed the validation.
+struct Authorization {
+    client: Client,
+    redirect_uri: String,
+    redirect_uri_given: bool,
+    scopes: Vec<String>,
+    state: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    nonce: Option<String>,
+}
+
+enum AuthorizationError {
+    /// The client or its redirect URI can not be trusted, so
+    /// the error is shown to the user instead of being sent to
+    /// the redirect URI.
+    Invalid(&'static str),
+    /// The error is sent back to the client, as described by
+    /// RFC 6749 section 4.1.2.1.
+    Redirect {
+        redirect_uri: String,
+        error: &'static str,
+        description: &'static str,
+        state: Option<String>,
+    },
+    Server(ApplicationError),
+}
+
+impl From<ApplicationError> for AuthorizationError {
+    fn from(error: ApplicationError) -> Self {
+        return Self::Server(error);
+    }
+}
+
+impl AuthorizationError {
+    /// Answering the consent form, the browser must be sent
+    /// to the client by a page rather than by a redirect.
+    fn respond(self, from_form: bool) -> Response {
+        return match self {
+            Self::Invalid(message) => {
+                (StatusCode::BAD_REQUEST, authorization_error_page(message)).into_response()
+            }
+            Self::Redirect {
+                redirect_uri,
+                error,
+                description,
+                state,
+            } => {
+                let mut params = vec![("error", error), ("error_description", description)];
+                if let Some(state) = &state {
+                    params.push(("state", state));
+                }
+
+                let url = callback_url(&redirect_uri, &params);
+
+                if from_form {
+                    redirect_page(&url).into_response()
+                } else {
+                    Redirect::to(&url).into_response()
+                }
+            }
+            Self::Server(error) => error.into_response(),
+        };
+    }
+}
+
+async fn validate(
+    db: &MySqlPool,
+    request: &AuthorizationRequest,
+) -> Result<Authorization, AuthorizationError> {
+    // Until the client and the redirect URI are known to
+    // match, the errors can not be sent to the redirect URI.
+    let Some(client_id) = request.client_id.as_deref() else {
+        return Err(AuthorizationError::Invalid(
+            "The client_id parameter is missing.",
+        ));
+    };
+
+    let Some(client) = client::find(db, client_id).await? else {
+        return Err(AuthorizationError::Invalid(
+            "The application is not registered.",
+        ));
+    };
+
+    let redirect_uri = match request.redirect_uri.as_deref() {
+        Some(uri) if client.allows_redirect_uri(uri) => uri.to_string(),
+        Some(_) => {
+            return Err(AuthorizationError::Invalid(
+                "The redirect URI is not registered for the application.",
+            ))
+        }
+        None if client.redirect_uris.len() == 1 => client.redirect_uris[0].clone(),
+        None => {
+            return Err(AuthorizationError::Invalid(
+                "The redirect_uri parameter is missing.",
+            ))
+        }
+    };
+
+    let redirect = |error, description| AuthorizationError::Redirect {
+        redirect_uri: redirect_uri.clone(),
+        error,
+        description,
+        state: request.state.clone(),
+    };
+
+    if request.response_type.as_deref() != Some("code") {
+        return Err(redirect(
+            "unsupported_response_type",
+            "Only the code response type is supported.",
+        ));
+    }
+
+    if !client.allows_grant("authorization_code") {
+        return Err(redirect(
+            "unauthorized_client",
+            "The client is not allowed to use the authorization code grant.",
+        ));
+    }
+
+    let scopes = parse_scope(request.scope.as_deref()).unwrap_or(client.scopes.clone());
+
+    if !client.allows_scopes(&scopes) {
+        return Err(redirect(
+            "invalid_scope",
+            "The requested scope is invalid or not allowed for the client.",
+        ));
+    }
+
+    // Public clients can not authenticate when exchanging the
+    // code, so PKCE is what binds the code to the client that
+    // asked for it.
+    let method = request.code_challenge_method.as_deref();
+    let code_challenge_method = match (&request.code_challenge, method) {
+        (None, _) if !client.is_confidential() => {
+            return Err(redirect(
+                "invalid_request",
+                "A code challenge is required for public clients.",
+            ))
+        }
+        (None, Some(_)) => {
+            return Err(redirect(
+                "invalid_request",
+                "The code challenge method is given without a code challenge.",
+            ))
+        }
+        (None, None) => None,
+        (Some(_), Some(method)) if method != "S256" && method != "plain" => {
+            return Err(redirect(
+                "invalid_request",
+                "The code challenge method must be S256 or plain.",
+            ))
+        }
+        (Some(challenge), _) if !(43..=128).contains(&challenge.len()) => {
+            return Err(redirect(
+                "invalid_request",
+                "The code challenge must be between 43 and 128 characters long.",
+            ))
+        }
+        (Some(_), method) => Some(method.unwrap_or("plain").to_string()),
+    };
+
+    if request
+        .nonce
+        .as_ref()
+        .is_some_and(|nonce| nonce.len() > 255)
+    {
+        return Err(redirect(
+            "invalid_request",
+            "The nonce must be at most 255 characters long.",
+        ));
+    }
+
+    return Ok(Authorization {
+        client,
+        redirect_uri,
+        redirect_uri_given: request.redirect_uri.is_some(),
+        scopes,
+        state: request.state.clone(),
+        code_challenge: request.code_challenge.clone(),
+        code_challenge_method,
+        nonce: request.nonce.clone(),
+    });
+}
+
+async fn show(
+    State(AppContext { db, .. }): State<AppContext>,
+    session: Session<SessionRedisPool>,
+    OptionalUser(user): OptionalUser,
+    uri: Uri,
+    Query(request): Query<AuthorizationRequest>,
+) -> Result<Response, ApplicationError> {
+    let authorization = match validate(&db, &request).await {
+        Ok(authorization) => authorization,
+        Err(error) => return Ok(error.respond(false)),
+    };
+
+    // Guests have to log in first, and will come back here
+    // with the same request once they did.
+    let Some(user) = user else {
+        let url = uri
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/oauth/authorize");
+        login::intend(&session, url);
+
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let scopes: Vec<(&str, &str)> = SCOPES
+        .iter()
+        .filter(|(scope, _)| authorization.scopes.iter().any(|s| s == scope))
+        .copied()
+        .collect();
+
+    // The redirect URI is only passed on when it was given,
+    // the token request has to repeat it then.
+    let scope = authorization.scopes.join(" ");
+    let mut fields = vec![
+        ("response_type", "code"),
+        ("client_id", authorization.client.id.as_str()),
+        ("scope", scope.as_str()),
+    ];
+    if authorization.redirect_uri_given {
+        fields.push(("redirect_uri", authorization.redirect_uri.as_str()));
+    }
+    if let Some(state) = &authorization.state {
+        fields.push(("state", state));
+    }
+    if let Some(challenge) = &authorization.code_challenge {
+        fields.push(("code_challenge", challenge));
+    }
+    if let Some(method) = &authorization.code_challenge_method {
+        fields.push(("code_challenge_method", method));
+    }
+    if let Some(nonce) = &authorization.nonce {
+        fields.push(("nonce", nonce));
+    }
+
+    return Ok(
+        consent_page(&authorization.client.name, &user.username, &scopes, &fields).into_response(),
+    );
+}
+
+async fn store(
+    State(AppContext { db, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    Form(request): Form<AuthorizationRequest>,
+) -> Result<Response, ApplicationError> {
+    let authorization = match validate(&db, &request).await {
+        Ok(authorization) => authorization,
+        Err(error) => return Ok(error.respond(true)),
+    };
+
+    if request.decision.as_deref() != Some("approve") {
+        return Ok(AuthorizationError::Redirect {
+            redirect_uri: authorization.redirect_uri,
+            error: "access_denied",
+            description: "The user denied the request.",
+            state: authorization.state,
+        }
+        .respond(true));
+    }
+
+    let code = grant::create_code(
+        &db,
+        &AuthorizationCode {
+            client_id: authorization.client.id,
+            user_id: user.id,
+            redirect_uri: authorization.redirect_uri.clone(),
+            redirect_uri_given: authorization.redirect_uri_given,
+            scopes: authorization.scopes,
+            code_challenge: authorization.code_challenge,
+            code_challenge_method: authorization.code_challenge_method,
+            nonce: authorization.nonce,
+        },
+    )
+    .await?;
+
+    let mut params = vec![("code", code.as_str())];
+    if let Some(state) = &authorization.state {
+        params.push(("state", state));
+    }
+
+    return Ok(redirect_page(&callback_url(&authorization.redirect_uri, &params)).into_response());
+}
@@ -0,0 +1,121 @@
+use crate::http::{
+    error::ApplicationError,
+    utils::{constant_time_eq, random_token, sha256_hex},
+};
+use sqlx::MySqlPool;
+
+/// An application that obtains tokens from us on behalf of
+/// our users. Public clients (single page and native apps)
+/// can not keep a secret and must use PKCE.
+#[derive(Clone, Debug)]
+pub struct Client {
+    pub id: String,
+    pub name: String,
+    secret: Option<String>,
+    pub redirect_uris: Vec<String>,
+    pub post_logout_redirect_uris: Vec<String>,
+    pub grant_types: Vec<String>,
+    pub scopes: Vec<String>,
+}
+
+impl Client {
+    pub fn is_confidential(&self) -> bool {
+        return self.secret.is_some();
+    }
+
+    pub fn allows_redirect_uri(&self, uri: &str) -> bool {
+        return self
+            .redirect_uris
+            .iter()
+            .any(|registered| registered == uri);
+    }
+
+    pub fn allows_post_logout_redirect_uri(&self, uri: &str) -> bool {
+        return self
+            .post_logout_redirect_uris
+            .iter()
+            .any(|registered| registered == uri);
+    }
+
+    pub fn allows_grant(&self, grant_type: &str) -> bool {
+        return self.grant_types.iter().any(|g| g == grant_type);
+    }
+
+    pub fn allows_scopes(&self, scopes: &[String]) -> bool {
+        return scopes.iter().all(|scope| self.scopes.contains(scope));
+    }
+
+    pub fn verify_secret(&self, secret: &str) -> bool {
+        return match &self.secret {
+            Some(hash) => constant_time_eq(hash, &sha256_hex(secret)),
+            None => false,
+        };
+    }
+}
+
+pub fn split_list(value: &str) -> Vec<String> {
+    return value.split_whitespace().map(String::from).collect();
+}
+
+pub async fn find(db: &MySqlPool, id: &str) -> Result<Option<Client>, ApplicationError> {
+    let record = sqlx::query!(
+        "select id, secret, name, redirect_uris, post_logout_redirect_uris, grant_types, scopes
+        from oauth_clients
+        where id = ?",
+        id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| Client {
+        id: record.id,
+        name: record.name,
+        secret: record.secret,
+        redirect_uris: split_list(&record.redirect_uris),
+        post_logout_redirect_uris: record
+            .post_logout_redirect_uris
+            .as_deref()
+            .map(split_list)
+            .unwrap_or_default(),
+        grant_types: split_list(&record.grant_types),
+        scopes: split_list(&record.scopes),
+    }));
+}
+
+pub struct NewClient {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub post_logout_redirect_uris: Vec<String>,
+    pub grant_types: Vec<String>,
+    pub scopes: Vec<String>,
+    pub confidential: bool,
+}
+
+/// Register the client and return its id, with its secret
+/// for confidential clients. The secret is only stored
+/// hashed, so it can not be shown again.
+pub async fn create(
+    db: &MySqlPool,
+    client: &NewClient,
+) -> Result<(String, Option<String>), ApplicationError> {
+    let id = random_token(16);
+    let secret = client.confidential.then(|| random_token(32));
+
+    sqlx::query!(
+        "insert into oauth_clients (id, secret, name, redirect_uris, post_logout_redirect_uris, grant_types, scopes)
+        values (?, ?, ?, ?, ?, ?, ?)",
+        id,
+        secret.as_deref().map(sha256_hex),
+        client.name,
+        client.redirect_uris.join(" "),
+        client.post_logout_redirect_uris.join(" "),
+        client.grant_types.join(" "),
+        client.scopes.join(" ")
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok((id, secret));
+}
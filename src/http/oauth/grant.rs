@@ -0,0 +1,396 @@
+use crate::http::{
+    error::ApplicationError,
+    utils::{random_token, sha256_hex},
+};
+use sqlx::{MySql, MySqlPool, Transaction};
+use time::{Duration, OffsetDateTime};
+
+/// Prefixes of the plain-text tokens, so they can be told
+/// apart from the other tokens and spotted by secret scanners.
+pub const ACCESS_TOKEN_PREFIX: &str = "ak_oat_";
+pub const REFRESH_TOKEN_PREFIX: &str = "ak_ort_";
+
+/// Lifetime of the authorization codes, in seconds.
+pub const CODE_TTL: i64 = 10 * 60;
+
+/// Lifetime of the access tokens, in seconds.
+pub const ACCESS_TOKEN_TTL: i64 = 60 * 60;
+
+/// Lifetime of the refresh tokens, in days.
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+/// The code handed to the client once the user approved the
+/// request, to be exchanged for tokens at the token endpoint.
+pub struct AuthorizationCode {
+    pub client_id: String,
+    pub user_id: u32,
+    pub redirect_uri: String,
+    /// Whether the authorization request named the redirect
+    /// URI, which the token request must then repeat.
+    pub redirect_uri_given: bool,
+    pub scopes: Vec<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    /// Echoed in the ID token, so the client can tell it was
+    /// issued for its own request.
+    pub nonce: Option<String>,
+}
+
+pub struct IssuedTokens {
+    pub access_token: String,
+    pub expires_in: i64,
+    pub refresh_token: Option<String>,
+    pub scopes: Vec<String>,
+    pub user_id: Option<u32>,
+    pub id_token: Option<String>,
+}
+
+/// What an access or refresh token was issued for, as
+/// reported by introspection.
+pub struct TokenInfo {
+    pub client_id: String,
+    pub user_id: Option<u32>,
+    pub scopes: Vec<String>,
+    pub expires_at: OffsetDateTime,
+    pub created_at: Option<OffsetDateTime>,
+}
+
+fn parse_scopes(scopes: &str) -> Vec<String> {
+    return scopes.split_whitespace().map(String::from).collect();
+}
+
+/// Store a new authorization code and return its plain-text
+/// value. Only its hash is kept, as for every token.
+pub async fn create_code(
+    db: &MySqlPool,
+    code: &AuthorizationCode,
+) -> Result<String, ApplicationError> {
+    let value = random_token(32);
+    let expires_at = OffsetDateTime::now_utc() + Duration::seconds(CODE_TTL);
+
+    sqlx::query!(
+        "insert into oauth_authorization_codes (id, client_id, user_id, redirect_uri, redirect_uri_given, scopes, code_challenge, code_challenge_method, nonce, expires_at)
+        values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        sha256_hex(&value),
+        code.client_id,
+        code.user_id,
+        code.redirect_uri,
+        code.redirect_uri_given,
+        code.scopes.join(" "),
+        code.code_challenge,
+        code.code_challenge_method,
+        code.nonce,
+        expires_at
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(value);
+}
+
+/// Remove the code and return it, unless it has expired. A
+/// code can be exchanged once only. The code is checked while
+/// it is locked, and kept when the check fails, so a client
+/// presenting a code it was not issued can not burn it.
+pub async fn take_code<E: From<ApplicationError>>(
+    db: &MySqlPool,
+    code: &str,
+    check: impl FnOnce(&AuthorizationCode) -> Result<(), E>,
+) -> Result<Option<AuthorizationCode>, E> {
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let record = sqlx::query!(
+        "select id, client_id, user_id, redirect_uri, redirect_uri_given as `redirect_uri_given: bool`, scopes, code_challenge, code_challenge_method, nonce, expires_at
+        from oauth_authorization_codes
+        where id = ?
+        for update",
+        sha256_hex(code)
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    let is_expired = record.expires_at <= OffsetDateTime::now_utc();
+    let code = AuthorizationCode {
+        client_id: record.client_id,
+        user_id: record.user_id,
+        redirect_uri: record.redirect_uri,
+        redirect_uri_given: record.redirect_uri_given,
+        scopes: parse_scopes(&record.scopes),
+        code_challenge: record.code_challenge,
+        code_challenge_method: record.code_challenge_method,
+        nonce: record.nonce,
+    };
+
+    if !is_expired {
+        check(&code)?;
+    }
+
+    sqlx::query!(
+        "delete from oauth_authorization_codes where id = ?",
+        record.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    if is_expired {
+        return Ok(None);
+    }
+
+    return Ok(Some(code));
+}
+
+/// Issue an access token, along with a refresh token when
+/// the tokens are issued on behalf of a user.
+pub async fn issue_tokens(
+    db: &MySqlPool,
+    client_id: &str,
+    user_id: Option<u32>,
+    scopes: &[String],
+) -> Result<IssuedTokens, ApplicationError> {
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let tokens = insert_tokens(&mut tx, client_id, user_id, scopes).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(tokens);
+}
+
+async fn insert_tokens(
+    tx: &mut Transaction<'_, MySql>,
+    client_id: &str,
+    user_id: Option<u32>,
+    scopes: &[String],
+) -> Result<IssuedTokens, ApplicationError> {
+    let now = OffsetDateTime::now_utc();
+    let access_token = format!("{}{}", ACCESS_TOKEN_PREFIX, random_token(32));
+    let access_token_id = sha256_hex(&access_token);
+
+    sqlx::query!(
+        "insert into oauth_access_tokens (id, client_id, user_id, scopes, expires_at) values (?, ?, ?, ?, ?)",
+        access_token_id,
+        client_id,
+        user_id,
+        scopes.join(" "),
+        now + Duration::seconds(ACCESS_TOKEN_TTL)
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let refresh_token = match user_id {
+        Some(user_id) => {
+            let refresh_token = format!("{}{}", REFRESH_TOKEN_PREFIX, random_token(32));
+
+            sqlx::query!(
+                "insert into oauth_refresh_tokens (id, access_token_id, client_id, user_id, scopes, expires_at) values (?, ?, ?, ?, ?, ?)",
+                sha256_hex(&refresh_token),
+                access_token_id,
+                client_id,
+                user_id,
+                scopes.join(" "),
+                now + Duration::days(REFRESH_TOKEN_TTL_DAYS)
+            )
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+            Some(refresh_token)
+        }
+        None => None,
+    };
+
+    return Ok(IssuedTokens {
+        access_token,
+        expires_in: ACCESS_TOKEN_TTL,
+        refresh_token,
+        scopes: scopes.to_vec(),
+        user_id,
+        id_token: None,
+    });
+}
+
+/// Exchange the refresh token of the client for new tokens.
+/// The old refresh token and its access token are revoked,
+/// and the new tokens can only narrow down its scopes.
+pub async fn refresh(
+    db: &MySqlPool,
+    client_id: &str,
+    token: &str,
+    scopes: Option<&[String]>,
+) -> Result<Option<IssuedTokens>, ApplicationError> {
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let record = sqlx::query!(
+        "select id, access_token_id, user_id, scopes
+        from oauth_refresh_tokens
+        where id = ? and client_id = ? and revoked_at is null and expires_at > current_timestamp
+        for update",
+        sha256_hex(token),
+        client_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    let granted = parse_scopes(&record.scopes);
+    let scopes = match scopes {
+        Some(scopes) if scopes.iter().all(|scope| granted.contains(scope)) => scopes.to_vec(),
+        Some(_) => return Ok(None),
+        None => granted,
+    };
+
+    revoke_pair(&mut tx, &record.id, &record.access_token_id).await?;
+    let tokens = insert_tokens(&mut tx, client_id, Some(record.user_id), &scopes).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(Some(tokens));
+}
+
+async fn revoke_pair(
+    tx: &mut Transaction<'_, MySql>,
+    refresh_token_id: &str,
+    access_token_id: &str,
+) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "update oauth_refresh_tokens set revoked_at = current_timestamp where id = ? and revoked_at is null",
+        refresh_token_id
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "update oauth_access_tokens set revoked_at = current_timestamp where id = ? and revoked_at is null",
+        access_token_id
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+/// Find the access token, unless it has been revoked or has
+/// expired.
+pub async fn find_access_token(
+    db: &MySqlPool,
+    token: &str,
+) -> Result<Option<TokenInfo>, ApplicationError> {
+    let record = sqlx::query!(
+        "select client_id, user_id, scopes, expires_at, created_at
+        from oauth_access_tokens
+        where id = ? and revoked_at is null and expires_at > current_timestamp",
+        sha256_hex(token)
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| TokenInfo {
+        client_id: record.client_id,
+        user_id: record.user_id,
+        scopes: parse_scopes(&record.scopes),
+        expires_at: record.expires_at,
+        created_at: record.created_at,
+    }));
+}
+
+/// Find the access or refresh token, unless it has been
+/// revoked or has expired.
+pub async fn find_active(
+    db: &MySqlPool,
+    token: &str,
+) -> Result<Option<TokenInfo>, ApplicationError> {
+    if let Some(info) = find_access_token(db, token).await? {
+        return Ok(Some(info));
+    }
+
+    let record = sqlx::query!(
+        "select client_id, user_id, scopes, expires_at, created_at
+        from oauth_refresh_tokens
+        where id = ? and revoked_at is null and expires_at > current_timestamp",
+        sha256_hex(token)
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| TokenInfo {
+        client_id: record.client_id,
+        user_id: Some(record.user_id),
+        scopes: parse_scopes(&record.scopes),
+        expires_at: record.expires_at,
+        created_at: record.created_at,
+    }));
+}
+
+/// Revoke the token if it was issued to the client. Revoking
+/// a refresh token revokes its access token too. Unknown
+/// tokens are ignored.
+pub async fn revoke(db: &MySqlPool, client_id: &str, token: &str) -> Result<(), ApplicationError> {
+    let id = sha256_hex(token);
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let refresh_token = sqlx::query!(
+        "select id, access_token_id from oauth_refresh_tokens where id = ? and client_id = ?",
+        id,
+        client_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    match refresh_token {
+        Some(record) => revoke_pair(&mut tx, &record.id, &record.access_token_id).await?,
+        None => {
+            sqlx::query!(
+                "update oauth_access_tokens set revoked_at = current_timestamp
+                where id = ? and client_id = ? and revoked_at is null",
+                id,
+                client_id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+        }
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
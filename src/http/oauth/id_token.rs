@@ -0,0 +1,64 @@
+use super::grant::ACCESS_TOKEN_TTL;
+use crate::http::{error::ApplicationError, jwt::JwtKeys, middleware::User};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use time::OffsetDateTime;
+
+#[derive(Serialize)]
+struct IdTokenClaims<'a> {
+    iss: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<&'a str>,
+    #[serde(flatten)]
+    user: Map<String, Value>,
+}
+
+fn has_scope(scopes: &[String], scope: &str) -> bool {
+    return scopes.iter().any(|s| s == scope);
+}
+
+/// The standard claims about the user that the granted scopes
+/// release, for both the ID token and the userinfo endpoint.
+pub fn user_claims(user: &User, scopes: &[String]) -> Map<String, Value> {
+    let mut claims = Map::new();
+    claims.insert("sub".to_string(), json!(user.id.to_string()));
+
+    if has_scope(scopes, "profile") {
+        claims.insert("preferred_username".to_string(), json!(user.username));
+    }
+
+    if has_scope(scopes, "email") {
+        claims.insert("email".to_string(), json!(user.email));
+        // We do not confirm the addresses users register
+        // with, so the clients must not rely on them.
+        claims.insert("email_verified".to_string(), json!(false));
+    }
+
+    return claims;
+}
+
+/// Sign an ID token telling the client who the user is. It
+/// lives as long as the access token issued along with it.
+pub fn issue(
+    jwt: &JwtKeys,
+    client_id: &str,
+    user: &User,
+    scopes: &[String],
+    nonce: Option<&str>,
+) -> Result<String, ApplicationError> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    return jwt
+        .sign(&IdTokenClaims {
+            iss: jwt.issuer(),
+            aud: client_id,
+            iat: now,
+            exp: now + ACCESS_TOKEN_TTL,
+            nonce,
+            user: user_claims(user, scopes),
+        })
+        .map_err(ApplicationError::ServerError);
+}
@@ -0,0 +1,70 @@
+use super::{authenticate_client, grant, OAuthError};
+use crate::http::AppContext;
+use axum::{
+    extract::{rejection::FormRejection, State},
+    http::{header::CACHE_CONTROL, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::post,
+    Form, Json, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/oauth/introspect", post(store));
+}
+
+#[derive(Deserialize, Debug)]
+struct IntrospectRequest {
+    token: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Tell the resource servers whether a token is active and
+/// what it grants, as described by RFC 7662. Only confidential
+/// clients may ask, as the answer reveals who the token is for.
+async fn store(
+    State(context): State<AppContext>,
+    headers: HeaderMap,
+    request: Result<Form<IntrospectRequest>, FormRejection>,
+) -> Result<Response, OAuthError> {
+    let Ok(Form(request)) = request else {
+        return Err(OAuthError::invalid_request(
+            "The request must be sent as application/x-www-form-urlencoded.",
+        ));
+    };
+
+    let client = authenticate_client(
+        &context.db,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    if !client.is_confidential() {
+        return Err(OAuthError::invalid_client());
+    }
+
+    let Some(token) = request.token.as_deref() else {
+        return Err(OAuthError::invalid_request(
+            "The token parameter is required.",
+        ));
+    };
+
+    let body = match grant::find_active(&context.db, token).await? {
+        Some(info) => json!({
+            "active": true,
+            "scope": info.scopes.join(" "),
+            "client_id": info.client_id,
+            "sub": info.user_id.map(|id| id.to_string()),
+            "exp": info.expires_at.unix_timestamp(),
+            "iat": info.created_at.map(|created_at| created_at.unix_timestamp()),
+            "iss": context.jwt.issuer(),
+        }),
+        None => json!({ "active": false }),
+    };
+
+    return Ok(([(CACHE_CONTROL, "no-store")], Json(body)).into_response());
+}
@@ -0,0 +1,165 @@
+use super::{
+    callback_url,
+    client::{self, Client},
+};
+use crate::http::{authentication::logout::log_out, flash::Flash, middleware::Auth, AppContext};
+use crate::view::oauth::{authorization_error_page, logout_page, redirect_page};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Form, Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use serde::Deserialize;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/oauth/logout", get(show).post(store));
+}
+
+/// A logout request initiated by a client, as described by
+/// OpenID Connect RP-Initiated Logout.
+#[derive(Deserialize, Debug)]
+struct LogoutRequest {
+    id_token_hint: Option<String>,
+    client_id: Option<String>,
+    post_logout_redirect_uri: Option<String>,
+    state: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HintClaims {
+    aud: String,
+}
+
+/// The client asking for the logout, and where the user is
+/// sent once logged out.
+struct Logout {
+    client: Option<Client>,
+    redirect_uri: Option<String>,
+}
+
+fn invalid(message: &str) -> Response {
+    return (StatusCode::BAD_REQUEST, authorization_error_page(message)).into_response();
+}
+
+async fn validate(context: &AppContext, request: &LogoutRequest) -> Result<Logout, Response> {
+    // The ID token tells which client the user logged into,
+    // we accept it even if it has expired in the meantime.
+    let audience = match request.id_token_hint.as_deref() {
+        Some(hint) => match context.jwt.verify_issued::<HintClaims>(hint) {
+            Ok(claims) => Some(claims.aud),
+            Err(_) => return Err(invalid("The id_token_hint is invalid.")),
+        },
+        None => None,
+    };
+
+    let client_id = match (audience, request.client_id.as_deref()) {
+        (Some(audience), Some(client_id)) if audience != client_id => {
+            return Err(invalid("The id_token_hint was not issued to the client."))
+        }
+        (Some(audience), _) => Some(audience),
+        (None, client_id) => client_id.map(String::from),
+    };
+
+    let client = match client_id {
+        Some(client_id) => client::find(&context.db, &client_id)
+            .await
+            .map_err(IntoResponse::into_response)?,
+        None => None,
+    };
+
+    let Some(redirect_uri) = request.post_logout_redirect_uri.as_deref() else {
+        return Ok(Logout {
+            client,
+            redirect_uri: None,
+        });
+    };
+
+    // Only the URIs registered by the client are followed,
+    // the endpoint would be an open redirect otherwise.
+    if !client
+        .as_ref()
+        .is_some_and(|client| client.allows_post_logout_redirect_uri(redirect_uri))
+    {
+        return Err(invalid(
+            "The post logout redirect URI is not registered for the application.",
+        ));
+    }
+
+    let mut params = vec![];
+    if let Some(state) = &request.state {
+        params.push(("state", state.as_str()));
+    }
+
+    let redirect_uri = if params.is_empty() {
+        redirect_uri.to_string()
+    } else {
+        callback_url(redirect_uri, &params)
+    };
+
+    return Ok(Logout {
+        client,
+        redirect_uri: Some(redirect_uri),
+    });
+}
+
+/// Ask the logged-in user to confirm, so another site can
+/// not log them out with a link. Guests are sent on directly.
+async fn show(
+    State(context): State<AppContext>,
+    auth: Auth,
+    Query(request): Query<LogoutRequest>,
+) -> Response {
+    let logout = match validate(&context, &request).await {
+        Ok(logout) => logout,
+        Err(response) => return response,
+    };
+
+    if !auth.check() {
+        return Redirect::to(logout.redirect_uri.as_deref().unwrap_or("/login")).into_response();
+    }
+
+    let mut fields = vec![];
+    if let Some(hint) = &request.id_token_hint {
+        fields.push(("id_token_hint", hint.as_str()));
+    }
+    if let Some(client_id) = &request.client_id {
+        fields.push(("client_id", client_id.as_str()));
+    }
+    if let Some(uri) = &request.post_logout_redirect_uri {
+        fields.push(("post_logout_redirect_uri", uri.as_str()));
+    }
+    if let Some(state) = &request.state {
+        fields.push(("state", state.as_str()));
+    }
+
+    return logout_page(
+        logout.client.as_ref().map(|client| client.name.as_str()),
+        &fields,
+    )
+    .into_response();
+}
+
+async fn store(
+    State(context): State<AppContext>,
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    Form(request): Form<LogoutRequest>,
+) -> Response {
+    let logout = match validate(&context, &request).await {
+        Ok(logout) => logout,
+        Err(response) => return response,
+    };
+
+    log_out(&session);
+
+    return match logout.redirect_uri {
+        Some(uri) => redirect_page(&uri).into_response(),
+        None => {
+            flash.info("You have been logged out.");
+            Redirect::to("/login").into_response()
+        }
+    };
+}
@@ -0,0 +1,506 @@
+use super::{personal_access_token, AppContext};
+use axum::{
+    http::{
+        header::{AUTHORIZATION, CACHE_CONTROL, WWW_AUTHENTICATE},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use client::Client;
+use serde_json::json;
+use sqlx::MySqlPool;
+
+use super::error::ApplicationError;
+
+mod authorize;
+pub mod client;
+mod grant;
+mod id_token;
+mod introspect;
+mod logout;
+mod revoke;
+mod token;
+mod userinfo;
+
+/// The scopes clients can ask for, with the description shown
+/// on the consent screen: the OpenID Connect scopes releasing
+/// the claims of the user, then the scopes guarding our own
+/// API, the same as for personal access tokens.
+pub const SCOPES: [(&str, &str); 5] = [
+    ("openid", "Sign you in with your account"),
+    ("profile", "See your username"),
+    ("email", "See your email address"),
+    personal_access_token::SCOPES[0],
+    personal_access_token::SCOPES[1],
+];
+
+/// The grant types a client can be registered for.
+pub const GRANT_TYPES: [&str; 3] = ["authorization_code", "refresh_token", "client_credentials"];
+
+/// The endpoints making us an OAuth 2.0 authorization server
+/// and OpenID Connect provider for the applications registered
+/// as clients.
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .merge(authorize::router())
+        .merge(token::router())
+        .merge(revoke::router())
+        .merge(introspect::router())
+        .merge(userinfo::router())
+        .merge(logout::router());
+}
+
+/// An error of the token, revocation and introspection
+/// endpoints, in the format of RFC 6749 section 5.2.
+#[derive(Debug)]
+pub(super) struct OAuthError {
+    status: StatusCode,
+    error: &'static str,
+    description: String,
+}
+
+impl OAuthError {
+    fn new(status: StatusCode, error: &'static str, description: &str) -> Self {
+        return Self {
+            status,
+            error,
+            description: description.to_string(),
+        };
+    }
+
+    pub(super) fn invalid_request(description: &str) -> Self {
+        return Self::new(StatusCode::BAD_REQUEST, "invalid_request", description);
+    }
+
+    pub(super) fn invalid_client() -> Self {
+        return Self::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_client",
+            "The client authentication failed.",
+        );
+    }
+
+    pub(super) fn invalid_grant(description: &str) -> Self {
+        return Self::new(StatusCode::BAD_REQUEST, "invalid_grant", description);
+    }
+
+    pub(super) fn unauthorized_client(description: &str) -> Self {
+        return Self::new(StatusCode::BAD_REQUEST, "unauthorized_client", description);
+    }
+
+    pub(super) fn unsupported_grant_type() -> Self {
+        return Self::new(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            "The grant type is not supported.",
+        );
+    }
+
+    pub(super) fn invalid_scope() -> Self {
+        return Self::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_scope",
+            "The requested scope is invalid or exceeds the granted scope.",
+        );
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let mut response = (
+            self.status,
+            [(CACHE_CONTROL, "no-store")],
+            Json(json!({
+                "error": self.error,
+                "error_description": self.description,
+            })),
+        )
+            .into_response();
+
+        if self.status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, r#"Basic realm="oauth""#.parse().unwrap());
+        }
+
+        return response;
+    }
+}
+
+impl From<ApplicationError> for OAuthError {
+    fn from(error: ApplicationError) -> Self {
+        println!("Server error: {:?}", error);
+
+        return Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "server_error",
+            "The server encountered an unexpected error.",
+        );
+    }
+}
+
+/// Authenticate the client with the credentials from the
+/// `Authorization: Basic` header, or else from the body.
+/// Public clients only give their id, confidential clients
+/// must give their secret as well.
+pub(super) async fn authenticate_client(
+    db: &MySqlPool,
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<Client, OAuthError> {
+    let basic = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .map(|v| {
+            STANDARD
+                .decode(v.trim())
+                .ok()
+                .and_then(|decoded| String::from_utf8(decoded).ok())
+                .and_then(|decoded| {
+                    decoded
+                        .split_once(':')
+                        .map(|(id, secret)| (id.to_string(), secret.to_string()))
+                })
+                .ok_or_else(OAuthError::invalid_client)
+        })
+        .transpose()?;
+
+    let (client_id, client_secret) = match &basic {
+        Some((id, secret)) => (Some(id.as_str()), Some(secret.as_str())),
+        None => (client_id, client_secret),
+    };
+
+    let Some(client_id) = client_id else {
+        return Err(OAuthError::invalid_client());
+    };
+
+    let Some(client) = client::find(db, client_id).await? else {
+        return Err(OAuthError::invalid_client());
+    };
+
+    let is_authenticated = match client_secret {
+        Some(secret) => client.verify_secret(secret),
+        None => !client.is_confidential(),
+    };
+
+    if !is_authenticated {
+        return Err(OAuthError::invalid_client());
+    }
+
+    return Ok(client);
+}
+
+/// Split the space-delimited `scope` parameter.
+pub(super) fn parse_scope(scope: Option<&str>) -> Option<Vec<String>> {
+    return scope
+        .map(client::split_list)
+        .filter(|scopes| !scopes.is_empty());
+}
+
+/// Append the parameters to the query of the redirect URI,
+/// keeping the query it was registered with.
+fn callback_url(redirect_uri: &str, params: &[(&str, &str)]) -> String {
+    let query = serde_urlencoded::to_string(params).unwrap_or_default();
+    let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+
+    return format!("{}{}{}", redirect_uri, separator, query);
+}
+
+/// The whole authorization code flow, played by a client
+/// registered for the tests and the browser of its user.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{
+        jwt::JwtKeys,
+        testing::{app_with, body_json, body_text, context, create_user, Browser},
+    };
+    use axum::{
+        body::Body,
+        http::{header::CONTENT_TYPE, request::Builder, Request},
+    };
+    use client::NewClient;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    const PASSWORD: &str = "correct horse battery staple";
+    const REDIRECT_URI: &str = "https://app.example.com/callback";
+    /// The verifier and challenge of RFC 7636 appendix B.
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGMSstw-cM";
+
+    struct TestClient {
+        browser: Browser,
+        jwt: Arc<JwtKeys>,
+        client_id: String,
+        user_id: u32,
+    }
+
+    impl TestClient {
+        async fn new(db: MySqlPool) -> Self {
+            let user_id = create_user(&db, "janedoe", PASSWORD).await;
+            let (client_id, _) = client::create(&db, &public_client()).await.unwrap();
+
+            let context = context(db);
+            let jwt = context.jwt.clone();
+
+            return Self {
+                browser: Browser::new(app_with(context).await),
+                jwt,
+                client_id,
+                user_id,
+            };
+        }
+
+        async fn send(&mut self, request: Builder, body: String) -> Response {
+            return self.browser.send(request, Body::from(body)).await;
+        }
+
+        /// Log in with the login form, as the user would.
+        async fn log_in(&mut self) {
+            let token = self.browser.csrf_token().await;
+            let body = serde_urlencoded::to_string([
+                ("username", "janedoe"),
+                ("password", PASSWORD),
+                ("_token", token.as_str()),
+            ])
+            .unwrap();
+
+            let response = self
+                .send(
+                    Request::post("/login")
+                        .header(CONTENT_TYPE, "application/x-www-form-urlencoded"),
+                    body,
+                )
+                .await;
+
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        }
+
+        /// Ask for a code and approve the request on the
+        /// consent screen, as the user would.
+        async fn authorize(&mut self) -> String {
+            return self.authorize_with(true).await;
+        }
+
+        /// Ask for a code, naming the redirect URI or leaving
+        /// it to the one the client registered.
+        async fn authorize_with(&mut self, with_redirect_uri: bool) -> String {
+            let mut params = vec![
+                ("response_type", "code"),
+                ("client_id", self.client_id.as_str()),
+                ("scope", "openid profile email"),
+                ("state", "af0ifjsldkj"),
+                ("code_challenge", CHALLENGE),
+                ("code_challenge_method", "S256"),
+                ("nonce", "n-0S6_WzA2Mj"),
+            ];
+            if with_redirect_uri {
+                params.push(("redirect_uri", REDIRECT_URI));
+            }
+            let query = serde_urlencoded::to_string(&params).unwrap();
+
+            let uri = format!("/oauth/authorize?{}", query);
+            let consent = self.send(Request::get(uri), String::new()).await;
+            assert_eq!(consent.status(), StatusCode::OK);
+
+            let token = self.browser.csrf_token().await;
+            let response = self
+                .send(
+                    Request::post("/oauth/authorize")
+                        .header(CONTENT_TYPE, "application/x-www-form-urlencoded"),
+                    format!("{}&decision=approve&_token={}", query, token),
+                )
+                .await;
+            let page = body_text(response).await;
+
+            let callback = format!("{}?code=", REDIRECT_URI);
+            let code = &page[page.find(&callback).unwrap() + callback.len()..];
+            assert!(code.contains("state=af0ifjsldkj"));
+
+            return code[..code.find('&').unwrap()].to_string();
+        }
+
+        async fn token(&mut self, params: &[(&str, &str)]) -> (StatusCode, Value) {
+            let response = self
+                .send(
+                    Request::post("/oauth/token")
+                        .header(CONTENT_TYPE, "application/x-www-form-urlencoded"),
+                    serde_urlencoded::to_string(params).unwrap(),
+                )
+                .await;
+
+            return (response.status(), body_json(response).await);
+        }
+
+        async fn exchange(&mut self, code: &str) -> (StatusCode, Value) {
+            let client_id = self.client_id.clone();
+
+            return self
+                .token(&[
+                    ("grant_type", "authorization_code"),
+                    ("code", code),
+                    ("redirect_uri", REDIRECT_URI),
+                    ("client_id", client_id.as_str()),
+                    ("code_verifier", VERIFIER),
+                ])
+                .await;
+        }
+
+        async fn userinfo(&mut self, access_token: &str) -> (StatusCode, Value) {
+            let response = self
+                .send(
+                    Request::get("/oauth/userinfo")
+                        .header(AUTHORIZATION, format!("Bearer {}", access_token)),
+                    String::new(),
+                )
+                .await;
+            let status = response.status();
+
+            if status != StatusCode::OK {
+                return (status, Value::Null);
+            }
+
+            return (status, body_json(response).await);
+        }
+    }
+
+    fn public_client() -> NewClient {
+        return NewClient {
+            name: "Test app".to_string(),
+            redirect_uris: vec![REDIRECT_URI.to_string()],
+            post_logout_redirect_uris: vec![],
+            grant_types: vec![
+                "authorization_code".to_string(),
+                "refresh_token".to_string(),
+            ],
+            scopes: vec![
+                "openid".to_string(),
+                "profile".to_string(),
+                "email".to_string(),
+            ],
+            confidential: false,
+        };
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_signs_the_user_in_to_the_client(db: MySqlPool) {
+        let mut client = TestClient::new(db).await;
+        client.log_in().await;
+
+        let code = client.authorize().await;
+        let (status, tokens) = client.exchange(&code).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tokens["token_type"], "Bearer");
+        assert_eq!(tokens["scope"], "openid profile email");
+
+        let id_token: Value = client
+            .jwt
+            .verify(tokens["id_token"].as_str().unwrap(), &client.client_id)
+            .unwrap();
+        assert_eq!(id_token["sub"], client.user_id.to_string());
+        assert_eq!(id_token["nonce"], "n-0S6_WzA2Mj");
+
+        let access_token = tokens["access_token"].as_str().unwrap();
+        let (status, userinfo) = client.userinfo(access_token).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(userinfo["preferred_username"], "janedoe");
+        assert_eq!(userinfo["email"], "janedoe@example.org");
+
+        let client_id = client.client_id.clone();
+        let (status, refreshed) = client
+            .token(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", tokens["refresh_token"].as_str().unwrap()),
+                ("client_id", client_id.as_str()),
+            ])
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(refreshed["access_token"], tokens["access_token"]);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_exchanges_a_code_once_only(db: MySqlPool) {
+        let mut client = TestClient::new(db).await;
+        client.log_in().await;
+
+        let code = client.authorize().await;
+        assert_eq!(client.exchange(&code).await.0, StatusCode::OK);
+
+        let (status, error) = client.exchange(&code).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"], "invalid_grant");
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_keeps_the_code_when_it_is_presented_wrongly(db: MySqlPool) {
+        let mut client = TestClient::new(db.clone()).await;
+        client.log_in().await;
+        let (other_client_id, _) = client::create(&db, &public_client()).await.unwrap();
+
+        let code = client.authorize().await;
+        let client_id = client.client_id.clone();
+        let attempts = [
+            [
+                ("client_id", other_client_id.as_str()),
+                ("redirect_uri", REDIRECT_URI),
+                ("code_verifier", VERIFIER),
+            ],
+            [
+                ("client_id", client_id.as_str()),
+                ("redirect_uri", "https://app.example.com/other"),
+                ("code_verifier", VERIFIER),
+            ],
+            [
+                ("client_id", client_id.as_str()),
+                ("redirect_uri", REDIRECT_URI),
+                ("code_verifier", CHALLENGE),
+            ],
+        ];
+
+        for attempt in attempts {
+            let mut params = vec![
+                ("grant_type", "authorization_code"),
+                ("code", code.as_str()),
+            ];
+            params.extend(attempt);
+
+            let (status, error) = client.token(&params).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(error["error"], "invalid_grant");
+        }
+
+        assert_eq!(client.exchange(&code).await.0, StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs the redis of scripts/init_redis.sh"]
+    async fn it_requires_the_redirect_uri_when_the_authorization_named_it(db: MySqlPool) {
+        let mut client = TestClient::new(db).await;
+        client.log_in().await;
+        let client_id = client.client_id.clone();
+        let params = |code| {
+            return [
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("client_id", client_id.as_str()),
+                ("code_verifier", VERIFIER),
+            ];
+        };
+
+        let code = client.authorize().await;
+        let (status, error) = client.token(&params(code.as_str())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"], "invalid_grant");
+
+        let code = client.authorize_with(false).await;
+        assert_eq!(client.token(&params(code.as_str())).await.0, StatusCode::OK);
+    }
+}
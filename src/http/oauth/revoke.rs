@@ -0,0 +1,54 @@
+use super::{authenticate_client, grant, OAuthError};
+use crate::http::AppContext;
+use axum::{
+    extract::{rejection::FormRejection, State},
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Form, Router,
+};
+use serde::Deserialize;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/oauth/revoke", post(store));
+}
+
+#[derive(Deserialize, Debug)]
+struct RevokeRequest {
+    token: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Revoke an access or refresh token of the client, as
+/// described by RFC 7009. Tokens that are unknown or belong
+/// to another client are ignored, so the response does not
+/// tell whether the token existed.
+async fn store(
+    State(AppContext { db, .. }): State<AppContext>,
+    headers: HeaderMap,
+    request: Result<Form<RevokeRequest>, FormRejection>,
+) -> Result<StatusCode, OAuthError> {
+    let Ok(Form(request)) = request else {
+        return Err(OAuthError::invalid_request(
+            "The request must be sent as application/x-www-form-urlencoded.",
+        ));
+    };
+
+    let client = authenticate_client(
+        &db,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let Some(token) = request.token.as_deref() else {
+        return Err(OAuthError::invalid_request(
+            "The token parameter is required.",
+        ));
+    };
+
+    grant::revoke(&db, &client.id, token).await?;
+
+    return Ok(StatusCode::OK);
+}
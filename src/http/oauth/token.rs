@@ -0,0 +1,275 @@
+use super::{
+    authenticate_client,
+    client::Client,
+    grant::{self, AuthorizationCode, IssuedTokens},
+    id_token, parse_scope, OAuthError,
+};
+use crate::http::{user_cache, utils::constant_time_eq, AppContext};
+use axum::{
+    extract::{rejection::FormRejection, State},
+    http::{header::CACHE_CONTROL, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::post,
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::MySqlPool;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/oauth/token", post(store));
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenRequest {
+    grant_type: Option<String>,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+    scope: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_token: Option<String>,
+}
+
+impl IntoResponse for IssuedTokens {
+    fn into_response(self) -> Response {
+        return (
+            [(CACHE_CONTROL, "no-store")],
+            Json(TokenResponse {
+                access_token: self.access_token,
+                token_type: "Bearer",
+                expires_in: self.expires_in,
+                refresh_token: self.refresh_token,
+                scope: self.scopes.join(" "),
+                id_token: self.id_token,
+            }),
+        )
+            .into_response();
+    }
+}
+
+async fn store(
+    State(context): State<AppContext>,
+    headers: HeaderMap,
+    request: Result<Form<TokenRequest>, FormRejection>,
+) -> Result<IssuedTokens, OAuthError> {
+    let Ok(Form(request)) = request else {
+        return Err(OAuthError::invalid_request(
+            "The request must be sent as application/x-www-form-urlencoded.",
+        ));
+    };
+
+    let client = authenticate_client(
+        &context.db,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let Some(grant_type) = request.grant_type.as_deref() else {
+        return Err(OAuthError::invalid_request(
+            "The grant_type parameter is required.",
+        ));
+    };
+
+    if !client.allows_grant(grant_type) {
+        return Err(OAuthError::unauthorized_client(
+            "The client is not allowed to use this grant type.",
+        ));
+    }
+
+    return match grant_type {
+        "authorization_code" => authorization_code(&context, &client, &request).await,
+        "refresh_token" => refresh_token(&context, &client, &request).await,
+        "client_credentials" => client_credentials(&context.db, &client, &request).await,
+        _ => Err(OAuthError::unsupported_grant_type()),
+    };
+}
+
+/// Exchange the code the user approved for tokens. The code
+/// must have been issued to the same client and redirect URI,
+/// and the verifier must match the PKCE challenge.
+async fn authorization_code(
+    context: &AppContext,
+    client: &Client,
+    request: &TokenRequest,
+) -> Result<IssuedTokens, OAuthError> {
+    let Some(code) = request.code.as_deref() else {
+        return Err(OAuthError::invalid_request(
+            "The code parameter is required.",
+        ));
+    };
+
+    let code = grant::take_code(&context.db, code, |code| {
+        return check_code(code, client, request);
+    })
+    .await?;
+
+    let Some(code) = code else {
+        return Err(OAuthError::invalid_grant(
+            "The authorization code is invalid or has expired.",
+        ));
+    };
+
+    let tokens =
+        grant::issue_tokens(&context.db, &client.id, Some(code.user_id), &code.scopes).await?;
+
+    return with_id_token(context, tokens, &client.id, code.nonce.as_deref()).await;
+}
+
+/// Check that the code was issued to the client and redirect
+/// URI presenting it, and that the verifier matches its PKCE
+/// challenge. The redirect URI must be repeated when the
+/// authorization request named it (RFC 6749 section 4.1.3).
+fn check_code(
+    code: &AuthorizationCode,
+    client: &Client,
+    request: &TokenRequest,
+) -> Result<(), OAuthError> {
+    if code.client_id != client.id {
+        return Err(OAuthError::invalid_grant(
+            "The authorization code was issued to another client.",
+        ));
+    }
+
+    let is_redirect_uri_valid = match request.redirect_uri.as_ref() {
+        Some(uri) => *uri == code.redirect_uri,
+        None => !code.redirect_uri_given,
+    };
+
+    if !is_redirect_uri_valid {
+        return Err(OAuthError::invalid_grant(
+            "The redirect URI does not match the authorization request.",
+        ));
+    }
+
+    let is_verified = match (&code.code_challenge, request.code_verifier.as_deref()) {
+        (Some(challenge), Some(verifier)) => verify_pkce(
+            challenge,
+            code.code_challenge_method.as_deref().unwrap_or("plain"),
+            verifier,
+        ),
+        (None, None) => true,
+        _ => false,
+    };
+
+    if !is_verified {
+        return Err(OAuthError::invalid_grant("The code verifier is invalid."));
+    }
+
+    return Ok(());
+}
+
+/// Check the verifier against the challenge of the code, as
+/// described by RFC 7636.
+fn verify_pkce(challenge: &str, method: &str, verifier: &str) -> bool {
+    let is_well_formed = (43..=128).contains(&verifier.len())
+        && verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c));
+
+    if !is_well_formed {
+        return false;
+    }
+
+    return match method {
+        "S256" => constant_time_eq(
+            challenge,
+            &URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())),
+        ),
+        "plain" => constant_time_eq(challenge, verifier),
+        _ => false,
+    };
+}
+
+async fn refresh_token(
+    context: &AppContext,
+    client: &Client,
+    request: &TokenRequest,
+) -> Result<IssuedTokens, OAuthError> {
+    let Some(token) = request.refresh_token.as_deref() else {
+        return Err(OAuthError::invalid_request(
+            "The refresh_token parameter is required.",
+        ));
+    };
+
+    let scopes = parse_scope(request.scope.as_deref());
+
+    let Some(tokens) = grant::refresh(&context.db, &client.id, token, scopes.as_deref()).await?
+    else {
+        return Err(OAuthError::invalid_grant(
+            "The refresh token is invalid, has expired or was revoked.",
+        ));
+    };
+
+    return with_id_token(context, tokens, &client.id, None).await;
+}
+
+/// Add an ID token to the tokens issued on behalf of a user
+/// who granted the `openid` scope, as OpenID Connect does.
+async fn with_id_token(
+    context: &AppContext,
+    mut tokens: IssuedTokens,
+    client_id: &str,
+    nonce: Option<&str>,
+) -> Result<IssuedTokens, OAuthError> {
+    let Some(user_id) = tokens.user_id else {
+        return Ok(tokens);
+    };
+
+    if !tokens.scopes.iter().any(|scope| scope == "openid") {
+        return Ok(tokens);
+    }
+
+    let Some(user) = user_cache::find(&context.redis, &context.db, user_id).await? else {
+        return Err(OAuthError::invalid_grant("The user no longer exists."));
+    };
+
+    tokens.id_token = Some(id_token::issue(
+        &context.jwt,
+        client_id,
+        &user,
+        &tokens.scopes,
+        nonce,
+    )?);
+
+    return Ok(tokens);
+}
+
+/// Issue a token to the client itself, for the machine to
+/// machine calls where no user is involved. Only clients
+/// that can keep a secret may do so.
+async fn client_credentials(
+    db: &MySqlPool,
+    client: &Client,
+    request: &TokenRequest,
+) -> Result<IssuedTokens, OAuthError> {
+    if !client.is_confidential() {
+        return Err(OAuthError::unauthorized_client(
+            "Public clients can not use the client credentials grant.",
+        ));
+    }
+
+    let scopes = parse_scope(request.scope.as_deref()).unwrap_or(client.scopes.clone());
+
+    if !client.allows_scopes(&scopes) {
+        return Err(OAuthError::invalid_scope());
+    }
+
+    return Ok(grant::issue_tokens(db, &client.id, None, &scopes).await?);
+}
@@ -0,0 +1,71 @@
+use super::{grant, id_token::user_claims};
+use crate::http::{error::ApplicationError, user_cache, AppContext};
+use axum::{
+    extract::State,
+    http::{
+        header::{AUTHORIZATION, CACHE_CONTROL, WWW_AUTHENTICATE},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/oauth/userinfo", get(show).post(show));
+}
+
+/// The access token is missing or can not be used here, as
+/// described by RFC 6750 section 3.
+fn invalid_token(description: &str) -> Response {
+    return (
+        StatusCode::UNAUTHORIZED,
+        [(
+            WWW_AUTHENTICATE,
+            format!(
+                r#"Bearer error="invalid_token", error_description="{}""#,
+                description
+            ),
+        )],
+    )
+        .into_response();
+}
+
+/// The claims about the user the access token was issued
+/// for, limited to the scopes the user granted.
+async fn show(
+    State(AppContext { db, redis, .. }): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response, ApplicationError> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    let Some(token) = token else {
+        return Ok((StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")]).into_response());
+    };
+
+    let Some(info) = grant::find_access_token(&db, token.trim()).await? else {
+        return Ok(invalid_token("The access token is invalid or has expired."));
+    };
+
+    let Some(user_id) = info
+        .user_id
+        .filter(|_| info.scopes.iter().any(|s| s == "openid"))
+    else {
+        return Ok(invalid_token(
+            "The access token was not issued for OpenID Connect.",
+        ));
+    };
+
+    let Some(user) = user_cache::find(&redis, &db, user_id).await? else {
+        return Ok(invalid_token("The user no longer exists."));
+    };
+
+    return Ok((
+        [(CACHE_CONTROL, "no-store")],
+        Json(user_claims(&user, &info.scopes)),
+    )
+        .into_response());
+}
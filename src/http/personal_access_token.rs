@@ -0,0 +1,153 @@
+use std::net::IpAddr;
+
+use super::{
+    error::ApplicationError,
+    utils::{random_token, sha256_hex},
+};
+use sqlx::MySqlPool;
+use time::OffsetDateTime;
+
+/// Prefix of the plain-text tokens, so they can be told
+/// apart from other bearer tokens and spotted by secret
+/// scanners.
+pub const PREFIX: &str = "ak_pat_";
+
+/// The scopes a token can be given, with their description.
+pub const SCOPES: [(&str, &str); 2] = [
+    ("profile:read", "Read your profile"),
+    ("profile:write", "Update your profile"),
+];
+
+#[derive(Clone, Debug)]
+pub struct PersonalAccessToken {
+    pub id: u32,
+    pub user_id: u32,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<OffsetDateTime>,
+    pub last_used_at: Option<OffsetDateTime>,
+    pub last_used_ip: Option<String>,
+    pub created_at: Option<OffsetDateTime>,
+}
+
+impl PersonalAccessToken {
+    pub fn can(&self, scope: &str) -> bool {
+        return self.scopes.iter().any(|s| s == scope);
+    }
+}
+
+fn parse_scopes(scopes: &str) -> Vec<String> {
+    return scopes.split_whitespace().map(String::from).collect();
+}
+
+/// Store a new token for the user and return its plain-text
+/// value. Only the hash is kept, so this is the one and only
+/// time the value is known.
+pub async fn create(
+    db: &MySqlPool,
+    user_id: u32,
+    name: &str,
+    scopes: &[String],
+    expires_at: Option<OffsetDateTime>,
+) -> Result<String, ApplicationError> {
+    let token = format!("{}{}", PREFIX, random_token(32));
+
+    sqlx::query!(
+        "insert into personal_access_tokens (user_id, name, token, scopes, expires_at) values (?, ?, ?, ?, ?)",
+        user_id,
+        name,
+        sha256_hex(&token),
+        scopes.join(" "),
+        expires_at
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(token);
+}
+
+/// Find the token matching the plain-text value, unless it
+/// has expired.
+pub async fn find_valid(
+    db: &MySqlPool,
+    token: &str,
+) -> Result<Option<PersonalAccessToken>, ApplicationError> {
+    let record = sqlx::query!(
+        "select id, user_id, name, scopes, expires_at, last_used_at, last_used_ip, created_at
+        from personal_access_tokens
+        where token = ? and (expires_at is null or expires_at > current_timestamp)",
+        sha256_hex(token)
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| PersonalAccessToken {
+        id: record.id,
+        user_id: record.user_id,
+        name: record.name,
+        scopes: parse_scopes(&record.scopes),
+        expires_at: record.expires_at,
+        last_used_at: record.last_used_at,
+        last_used_ip: record.last_used_ip,
+        created_at: record.created_at,
+    }));
+}
+
+/// Record that the token has just been used from the address.
+pub async fn touch(db: &MySqlPool, id: u32, ip: Option<IpAddr>) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "update personal_access_tokens set last_used_at = current_timestamp, last_used_ip = ? where id = ?",
+        ip.map(|ip| ip.to_string()),
+        id
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+pub async fn list_for_user(
+    db: &MySqlPool,
+    user_id: u32,
+) -> Result<Vec<PersonalAccessToken>, ApplicationError> {
+    let records = sqlx::query!(
+        "select id, user_id, name, scopes, expires_at, last_used_at, last_used_ip, created_at
+        from personal_access_tokens
+        where user_id = ?
+        order by id desc",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(records
+        .into_iter()
+        .map(|record| PersonalAccessToken {
+            id: record.id,
+            user_id: record.user_id,
+            name: record.name,
+            scopes: parse_scopes(&record.scopes),
+            expires_at: record.expires_at,
+            last_used_at: record.last_used_at,
+            last_used_ip: record.last_used_ip,
+            created_at: record.created_at,
+        })
+        .collect());
+}
+
+pub async fn delete(db: &MySqlPool, user_id: u32, id: u32) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "delete from personal_access_tokens where id = ? and user_id = ?",
+        id,
+        user_id
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
@@ -0,0 +1,156 @@
+use super::{
+    error::ApplicationError,
+    utils::{random_token, sha256_hex},
+};
+use sqlx::{MySql, MySqlPool, Transaction};
+use time::{Duration, OffsetDateTime};
+
+/// Lifetime of the refresh tokens, in days.
+pub const TTL_DAYS: i64 = 30;
+
+/// The outcome of exchanging a refresh token for a new one.
+pub enum Rotation {
+    Rotated { user_id: u32, token: String },
+    Invalid,
+    /// The token had already been exchanged. Someone else holds
+    /// a copy, so its whole family has been revoked.
+    Reused,
+}
+
+/// Start a new family of refresh tokens for the user, e.g.
+/// on login, and return the plain-text token.
+pub async fn issue(db: &MySqlPool, user_id: u32) -> Result<String, ApplicationError> {
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let (_, token) = insert(&mut tx, user_id, &random_token(32)).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(token);
+}
+
+async fn insert(
+    tx: &mut Transaction<'_, MySql>,
+    user_id: u32,
+    family: &str,
+) -> Result<(u64, String), ApplicationError> {
+    let token = random_token(32);
+    let expires_at = OffsetDateTime::now_utc() + Duration::days(TTL_DAYS);
+
+    let result = sqlx::query!(
+        "insert into refresh_tokens (user_id, family, token, expires_at) values (?, ?, ?, ?)",
+        user_id,
+        family,
+        sha256_hex(&token),
+        expires_at
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok((result.last_insert_id(), token));
+}
+
+/// Exchange the refresh token for a new one of the same
+/// family. Every token can be exchanged once only.
+pub async fn rotate(db: &MySqlPool, token: &str) -> Result<Rotation, ApplicationError> {
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let record = sqlx::query!(
+        "select id, user_id, family, replaced_by, revoked_at, expires_at
+        from refresh_tokens
+        where token = ?
+        for update",
+        sha256_hex(token)
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let Some(record) = record else {
+        return Ok(Rotation::Invalid);
+    };
+
+    if record.replaced_by.is_some() || record.revoked_at.is_some() {
+        revoke_family(&mut tx, &record.family).await?;
+        tx.commit()
+            .await
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        return Ok(Rotation::Reused);
+    }
+
+    if record.expires_at <= OffsetDateTime::now_utc() {
+        return Ok(Rotation::Invalid);
+    }
+
+    let (new_id, new_token) = insert(&mut tx, record.user_id, &record.family).await?;
+
+    sqlx::query!(
+        "update refresh_tokens set replaced_by = ?, revoked_at = current_timestamp where id = ?",
+        new_id,
+        record.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(Rotation::Rotated {
+        user_id: record.user_id,
+        token: new_token,
+    });
+}
+
+/// Revoke the family the refresh token belongs to. Unknown
+/// tokens are ignored.
+pub async fn revoke(db: &MySqlPool, token: &str) -> Result<(), ApplicationError> {
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let record = sqlx::query!(
+        "select family from refresh_tokens where token = ?",
+        sha256_hex(token)
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    if let Some(record) = record {
+        revoke_family(&mut tx, &record.family).await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+async fn revoke_family(
+    tx: &mut Transaction<'_, MySql>,
+    family: &str,
+) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "update refresh_tokens set revoked_at = current_timestamp where family = ? and revoked_at is null",
+        family
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
@@ -0,0 +1,16 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+
+/// Redirect after a successful form submission. htmx loads
+/// the page of the `HX-Redirect` header, it would otherwise
+/// follow a `303` in the background. Any other client gets a
+/// regular `303 See Other`.
+pub(super) fn redirect(is_htmx: bool, to: &str) -> Response {
+    if is_htmx {
+        return (StatusCode::OK, [("HX-Redirect", to)]).into_response();
+    }
+
+    return Redirect::to(to).into_response();
+}
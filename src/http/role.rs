@@ -0,0 +1,189 @@
+use super::error::ApplicationError;
+use sqlx::MySqlPool;
+
+/// Assign roles to the users.
+pub const MANAGE_USERS: &str = "users.manage";
+
+/// Browse and export the audit log.
+pub const VIEW_AUDIT_LOG: &str = "audit.view";
+
+/// The role the first administrator is given, with every
+/// permission.
+pub const ADMINISTRATOR: &str = "administrator";
+
+/// The roles of a user, and the permissions they give.
+#[derive(Default, Debug)]
+pub struct Grants {
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+}
+
+impl Grants {
+    pub fn can(&self, permission: &str) -> bool {
+        return self.permissions.iter().any(|p| p == permission);
+    }
+}
+
+pub async fn grants(db: &MySqlPool, user_id: u32) -> Result<Grants, ApplicationError> {
+    let roles = sqlx::query!(
+        "select roles.name from roles
+        join user_roles on user_roles.role_id = roles.id
+        where user_roles.user_id = ?
+        order by roles.name",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let permissions = sqlx::query!(
+        "select distinct permissions.name from permissions
+        join role_permissions on role_permissions.permission_id = permissions.id
+        join user_roles on user_roles.role_id = role_permissions.role_id
+        where user_roles.user_id = ?
+        order by permissions.name",
+        user_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(Grants {
+        roles: roles.into_iter().map(|record| record.name).collect(),
+        permissions: permissions.into_iter().map(|record| record.name).collect(),
+    });
+}
+
+pub struct Role {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+}
+
+pub async fn all(db: &MySqlPool) -> Result<Vec<Role>, ApplicationError> {
+    let records = sqlx::query!("select id, name, description from roles order by name")
+        .fetch_all(db)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(records
+        .into_iter()
+        .map(|record| Role {
+            id: record.id,
+            name: record.name,
+            description: record.description,
+        })
+        .collect());
+}
+
+/// A user, with the names of their roles.
+pub struct UserRoles {
+    pub id: u32,
+    pub username: String,
+    pub email: String,
+    pub roles: Vec<String>,
+}
+
+/// The users whose username starts with the search, or all
+/// of them, in alphabetical order. The deleted accounts are
+/// left out.
+pub async fn users(
+    db: &MySqlPool,
+    search: Option<&str>,
+    limit: u32,
+) -> Result<Vec<UserRoles>, ApplicationError> {
+    let records = sqlx::query!(
+        "select users.id, users.username, users.email, coalesce(group_concat(roles.name order by roles.name), '') as roles
+        from users
+        left join user_roles on user_roles.user_id = users.id
+        left join roles on roles.id = user_roles.role_id
+        where users.deleted_at is null and (? is null or users.username like concat(?, '%'))
+        group by users.id, users.username, users.email
+        order by users.username
+        limit ?",
+        search,
+        search,
+        limit
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(records
+        .into_iter()
+        .map(|record| UserRoles {
+            id: record.id,
+            username: record.username,
+            email: record.email,
+            roles: record
+                .roles
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+        .collect());
+}
+
+/// Replace the roles of the user with the given ones. The ids
+/// of roles that do not exist, or given twice, are ignored.
+pub async fn assign(
+    db: &MySqlPool,
+    user_id: u32,
+    role_ids: &[u32],
+) -> Result<(), ApplicationError> {
+    let mut role_ids = role_ids.to_vec();
+    role_ids.sort_unstable();
+    role_ids.dedup();
+
+    let mut transaction = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    sqlx::query!("delete from user_roles where user_id = ?", user_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    for role_id in role_ids {
+        sqlx::query!(
+            "insert into user_roles (user_id, role_id) select ?, id from roles where id = ?",
+            user_id,
+            role_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+/// Give the role to the user, on top of the roles they have.
+/// Returns false when there is no such role.
+pub async fn add(db: &MySqlPool, user_id: u32, role: &str) -> Result<bool, ApplicationError> {
+    let Some(record) = sqlx::query!("select id from roles where name = ?", role)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+    else {
+        return Ok(false);
+    };
+
+    sqlx::query!(
+        "insert ignore into user_roles (user_id, role_id) values (?, ?)",
+        user_id,
+        record.id
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(true);
+}
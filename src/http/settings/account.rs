@@ -0,0 +1,422 @@
+use crate::http::{
+    account_deletion,
+    audit::{Audit, AuditEvent},
+    authentication::{
+        login::{attempt, LoginAttempRequest},
+        logout::log_out,
+        register::hash_password,
+    },
+    check_email::email_errors,
+    check_username::username_errors,
+    credentials::CredentialProvider,
+    email_change::{self, Confirmation},
+    error::{ApplicationError, ErrorBag},
+    extractor::{error_bag, HxRequest, RequiredUser},
+    flash::Flash,
+    middleware::{RequireSudo, User},
+    response::redirect,
+    user_cache,
+    user_session::{self, current_id},
+    utils::deserialize_empty_string_as_none,
+    AppContext,
+};
+use crate::view::{
+    authentication::confirm_email_page,
+    error::forbidden,
+    settings::{account_page, AccountForm},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use maud::Markup;
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use validator::Validate;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/account", get(index))
+        .route("/account/username", post(update_username))
+        .route(
+            "/account/email",
+            post(update_email).route_layer(RequireSudo::new()),
+        )
+        .route(
+            "/account/email/confirm/:token",
+            get(show_email_confirmation).post(confirm_email),
+        )
+        .route("/account/password", post(update_password))
+        .route(
+            "/account/delete",
+            post(destroy).route_layer(RequireSudo::new()),
+        );
+}
+
+async fn render(
+    db: &MySqlPool,
+    credentials: &dyn CredentialProvider,
+    user: &User,
+    form: Option<AccountForm<'_>>,
+    errors: Option<&ErrorBag>,
+) -> Result<Markup, ApplicationError> {
+    let pending_email = email_change::pending(db, user.id).await?;
+
+    return Ok(account_page(
+        &user.username,
+        &user.email,
+        pending_email.as_deref(),
+        credentials.manages_passwords(),
+        account_deletion::grace_period().whole_days(),
+        form,
+        errors,
+    ));
+}
+
+async fn index(
+    State(AppContext {
+        db, credentials, ..
+    }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+) -> Result<Markup, ApplicationError> {
+    return render(&db, credentials.as_ref(), &user, None, None).await;
+}
+
+#[derive(Deserialize, Debug)]
+struct ChangeUsernameRequest {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    username: Option<String>,
+}
+
+/// The new username is checked the same way as on the
+/// registration page.
+async fn update_username(
+    State(AppContext {
+        db,
+        redis,
+        credentials,
+        ..
+    }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    flash: Flash,
+    audit: Audit,
+    Form(request): Form<ChangeUsernameRequest>,
+) -> Result<Response, ApplicationError> {
+    let errors = if request.username.as_deref() == Some(user.username.as_str()) {
+        vec!["This is already your username.".to_string()]
+    } else {
+        username_errors(&db, request.username.as_deref()).await?
+    };
+
+    if !errors.is_empty() {
+        let errors = ErrorBag::from([("username".to_string(), errors)]);
+        let form = AccountForm::Username(request.username.as_deref().unwrap_or(""));
+
+        return Ok(
+            render(&db, credentials.as_ref(), &user, Some(form), Some(&errors))
+                .await?
+                .into_response(),
+        );
+    }
+
+    sqlx::query!(
+        "update users set username = ? where id = ?",
+        request.username,
+        user.id
+    )
+    .execute(&db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    user_cache::forget(&redis, user.id).await?;
+
+    audit
+        .record(
+            AuditEvent::success("username.change")
+                .user(user.id)
+                .with("from", user.username.as_str())
+                .with("to", request.username.as_deref()),
+        )
+        .await?;
+
+    flash.success("Your username has been changed.");
+
+    return Ok(redirect(is_htmx, "/settings/account"));
+}
+
+#[derive(Deserialize, Debug)]
+struct ChangeEmailRequest {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    email: Option<String>,
+}
+
+/// The address is only changed once the user follows the
+/// link sent to it. The current address is told about the
+/// change, in case someone else is behind it.
+async fn update_email(
+    State(AppContext {
+        db,
+        mailer,
+        credentials,
+        ..
+    }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    flash: Flash,
+    audit: Audit,
+    Form(request): Form<ChangeEmailRequest>,
+) -> Result<Response, ApplicationError> {
+    let errors = email_errors(&db, request.email.as_deref()).await?;
+
+    let Some(email) = request.email.as_deref().filter(|_| errors.is_empty()) else {
+        let errors = ErrorBag::from([("email".to_string(), errors)]);
+        let form = AccountForm::Email(request.email.as_deref().unwrap_or(""));
+
+        return Ok(
+            render(&db, credentials.as_ref(), &user, Some(form), Some(&errors))
+                .await?
+                .into_response(),
+        );
+    };
+
+    let token = email_change::create(&db, user.id, email).await?;
+
+    audit
+        .record(
+            AuditEvent::success("email.change_requested")
+                .user(user.id)
+                .with("email", email),
+        )
+        .await?;
+
+    mailer
+        .send(
+            email,
+            "Confirm your new email address",
+            format!(
+                "Hello {},\n\nFollow this link within 24 hours to use this address for your account:\n\n{}\n\nIf you did not ask for it, you can ignore this email.\n",
+                user.username,
+                mailer.url(&format!("/settings/account/email/confirm/{}", token))
+            ),
+        )
+        .await?;
+
+    mailer
+        .send(
+            &user.email,
+            "Your email address is being changed",
+            format!(
+                "Hello {},\n\nSomeone asked to change the email address of your account to {}. It will be changed once the new address is confirmed.\n\nIf it was not you, change your password right away.\n",
+                user.username, email
+            ),
+        )
+        .await?;
+
+    flash.info(format!("We have sent a confirmation link to {}.", email));
+
+    return Ok(redirect(is_htmx, "/settings/account"));
+}
+
+/// The link can be followed from any device, the token
+/// alone tells whose address changes.
+async fn show_email_confirmation(
+    State(AppContext { db, .. }): State<AppContext>,
+    flash: Flash,
+    Path(token): Path<String>,
+) -> Result<Response, ApplicationError> {
+    let Some(email) = email_change::find(&db, &token).await? else {
+        flash.error("The confirmation link is invalid or has expired.");
+        return Ok(Redirect::to("/settings/account").into_response());
+    };
+
+    return Ok(confirm_email_page(&token, &email).into_response());
+}
+
+/// Nothing changes until the page is submitted, so that mail
+/// scanners following the link do not use up the token.
+async fn confirm_email(
+    State(AppContext { db, redis, .. }): State<AppContext>,
+    flash: Flash,
+    audit: Audit,
+    Path(token): Path<String>,
+) -> Result<Response, ApplicationError> {
+    match email_change::confirm(&db, &token).await? {
+        Confirmation::Changed { user_id, email } => {
+            user_cache::forget(&redis, user_id).await?;
+            audit
+                .record(
+                    AuditEvent::success("email.change")
+                        .user(user_id)
+                        .with("email", email.as_str()),
+                )
+                .await?;
+            flash.success(format!("Your email address is now {}.", email));
+        }
+        Confirmation::Taken => {
+            flash.error("The email address is already used by another account.");
+        }
+        Confirmation::Invalid => {
+            flash.error("The confirmation link is invalid or has expired.");
+        }
+    }
+
+    return Ok(Redirect::to("/settings/account").into_response());
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct ChangePasswordRequest {
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[validate(required(message = "This field is required."))]
+    current_password: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[validate(required(message = "This field is required."))]
+    password: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_empty_string_as_none")]
+    #[validate(
+        required(message = "This field is required."),
+        must_match(other = "password", message = "Does not match with password field.")
+    )]
+    password_confirmation: Option<String>,
+}
+
+async fn update_password(
+    State(AppContext {
+        db,
+        redis,
+        credentials,
+        ..
+    }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    audit: Audit,
+    Form(request): Form<ChangePasswordRequest>,
+) -> Result<Response, ApplicationError> {
+    // A local hash would never be used to log in.
+    if !credentials.manages_passwords() {
+        return Ok((StatusCode::FORBIDDEN, forbidden()).into_response());
+    }
+
+    let mut errors = match request.validate() {
+        Ok(_) => ErrorBag::new(),
+        Err(err) => error_bag(&err),
+    };
+
+    // The current password is checked the same way as on
+    // the login page, against the account of the user. The
+    // directory may know them by another username.
+    if request.current_password.is_some() {
+        let attempt_request = LoginAttempRequest {
+            username: Some(credentials.login_name(user.id, &user.username).await?),
+            password: request.current_password.clone(),
+        };
+
+        if attempt(credentials.as_ref(), &attempt_request)
+            .await?
+            .filter(|authenticated| authenticated.id == user.id)
+            .is_none()
+        {
+            audit
+                .record(AuditEvent::failure("password.change").user(user.id))
+                .await?;
+            errors.insert(
+                "current_password".to_string(),
+                vec!["The password is incorrect.".to_string()],
+            );
+        }
+    }
+
+    if !errors.is_empty() {
+        return Ok(render(
+            &db,
+            credentials.as_ref(),
+            &user,
+            Some(AccountForm::Password),
+            Some(&errors),
+        )
+        .await?
+        .into_response());
+    }
+
+    let password_hash = hash_password(request.password.as_deref().unwrap())?;
+
+    sqlx::query!(
+        "update users set password = ? where id = ?",
+        password_hash,
+        user.id
+    )
+    .execute(&db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    user_cache::forget(&redis, user.id).await?;
+
+    // Whoever knew the old password must not stay logged in.
+    user_session::revoke_others(&redis, user.id, current_id(&session).as_deref()).await?;
+
+    audit
+        .record(AuditEvent::success("password.change").user(user.id))
+        .await?;
+
+    flash.success("Your password has been changed.");
+
+    return Ok(redirect(is_htmx, "/settings/account"));
+}
+
+#[derive(Deserialize, Debug)]
+struct DeleteAccountRequest {
+    #[serde(default)]
+    confirmation: String,
+}
+
+/// The account is only purged once the grace period is over,
+/// logging in again before then restores it.
+async fn destroy(
+    State(AppContext {
+        db,
+        redis,
+        credentials,
+        ..
+    }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    audit: Audit,
+    Form(request): Form<DeleteAccountRequest>,
+) -> Result<Response, ApplicationError> {
+    if request.confirmation != user.username {
+        let errors = ErrorBag::from([(
+            "confirmation".to_string(),
+            vec!["Type your username to confirm.".to_string()],
+        )]);
+        let form = AccountForm::Delete(&request.confirmation);
+
+        return Ok(
+            render(&db, credentials.as_ref(), &user, Some(form), Some(&errors))
+                .await?
+                .into_response(),
+        );
+    }
+
+    account_deletion::schedule(&db, &redis, user.id).await?;
+    audit
+        .record(AuditEvent::success("account.delete_requested").user(user.id))
+        .await?;
+    log_out(&session);
+
+    flash.info(format!(
+        "Your account has been deleted. Log in within {} days if you change your mind.",
+        account_deletion::grace_period().whole_days()
+    ));
+
+    return Ok(redirect(is_htmx, "/login"));
+}
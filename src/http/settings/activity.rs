@@ -0,0 +1,21 @@
+use crate::http::{error::ApplicationError, extractor::RequiredUser, login_device, AppContext};
+use crate::view::settings::activity_page;
+use axum::{extract::State, routing::get, Router};
+use maud::Markup;
+
+/// How many of the latest logins are shown.
+const HISTORY_LENGTH: u32 = 20;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/activity", get(index));
+}
+
+async fn index(
+    State(AppContext { db, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+) -> Result<Markup, ApplicationError> {
+    let sign_ins = login_device::history(&db, user.id, HISTORY_LENGTH).await?;
+    let devices = login_device::list(&db, user.id).await?;
+
+    return Ok(activity_page(&sign_ins, &devices));
+}
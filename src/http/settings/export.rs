@@ -0,0 +1,101 @@
+use crate::http::{
+    audit::{Audit, AuditEvent},
+    data_export::{self, DataExport},
+    error::ApplicationError,
+    extractor::{HxRequest, RequiredUser},
+    flash::Flash,
+    middleware::RequireSudo,
+    response::redirect,
+    AppContext,
+};
+use crate::view::settings::export_page;
+use axum::{
+    extract::{Path, Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Router,
+};
+use maud::Markup;
+use serde::Deserialize;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route(
+            "/export",
+            get(index).merge(post(store).route_layer(RequireSudo::new())),
+        )
+        .route("/export/:id/download", get(download));
+}
+
+async fn index(
+    State(AppContext { redis, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+) -> Result<Markup, ApplicationError> {
+    let export = data_export::latest(&redis, user.id).await?;
+
+    return Ok(export_page(export.as_ref()));
+}
+
+/// The archive is built in the background, the user gets an
+/// email with the link to download it.
+async fn store(
+    State(AppContext {
+        db,
+        redis,
+        mailer,
+        session_limits,
+        ..
+    }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    flash: Flash,
+    audit: Audit,
+) -> Result<Response, ApplicationError> {
+    if let Some(DataExport::Building) = data_export::latest(&redis, user.id).await? {
+        return Ok(redirect(is_htmx, "/settings/export"));
+    }
+
+    data_export::request(&db, &redis, &session_limits, &mailer, &user).await?;
+    audit
+        .record(AuditEvent::success("data_export.request").user(user.id))
+        .await?;
+
+    flash.info("We are preparing a copy of your data, you will receive an email once it is ready.");
+
+    return Ok(redirect(is_htmx, "/settings/export"));
+}
+
+#[derive(Deserialize, Debug)]
+struct DownloadRequest {
+    expires: i64,
+    signature: String,
+}
+
+/// The link alone gives access to the archive, so it can be
+/// followed from the email on any device.
+async fn download(
+    State(AppContext { redis, .. }): State<AppContext>,
+    flash: Flash,
+    Path(id): Path<String>,
+    Query(request): Query<DownloadRequest>,
+) -> Result<Response, ApplicationError> {
+    let Some(archive) =
+        data_export::download(&redis, &id, request.expires, &request.signature).await?
+    else {
+        flash.error("The download link is invalid or has expired.");
+        return Ok(Redirect::to("/settings/export").into_response());
+    };
+
+    return Ok((
+        [
+            (CONTENT_TYPE, "application/zip"),
+            (
+                CONTENT_DISPOSITION,
+                r#"attachment; filename="personal-data.zip""#,
+            ),
+        ],
+        archive,
+    )
+        .into_response());
+}
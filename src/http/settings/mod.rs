@@ -0,0 +1,18 @@
+use super::AppContext;
+use axum::Router;
+
+mod account;
+mod activity;
+mod export;
+mod sessions;
+mod tokens;
+
+/// The pages where logged-in users manage their account.
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .merge(account::router())
+        .merge(activity::router())
+        .merge(export::router())
+        .merge(sessions::router())
+        .merge(tokens::router());
+}
@@ -0,0 +1,75 @@
+use crate::http::{
+    audit::{Audit, AuditEvent},
+    error::ApplicationError,
+    extractor::{HxRequest, RequiredUser},
+    flash::Flash,
+    response::redirect,
+    user_session::{self, current_id},
+    AppContext,
+};
+use crate::view::settings::sessions_page;
+use axum::{
+    extract::{Path, State},
+    response::Response,
+    routing::{get, post},
+    Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use maud::Markup;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/sessions", get(index))
+        .route("/sessions/:id/delete", post(destroy))
+        .route("/other-sessions/delete", post(destroy_others));
+}
+
+async fn index(
+    State(AppContext {
+        redis,
+        session_limits,
+        ..
+    }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    session: Session<SessionRedisPool>,
+) -> Result<Markup, ApplicationError> {
+    let sessions = user_session::list(&redis, &session_limits, user.id).await?;
+
+    return Ok(sessions_page(&sessions, current_id(&session).as_deref()));
+}
+
+async fn destroy(
+    State(AppContext { redis, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    flash: Flash,
+    audit: Audit,
+    Path(id): Path<String>,
+) -> Result<Response, ApplicationError> {
+    if user_session::revoke(&redis, user.id, &id).await? {
+        audit
+            .record(AuditEvent::success("session.revoke").user(user.id))
+            .await?;
+        flash.success("The session has been logged out.");
+    }
+
+    return Ok(redirect(is_htmx, "/settings/sessions"));
+}
+
+async fn destroy_others(
+    State(AppContext { redis, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    audit: Audit,
+) -> Result<Response, ApplicationError> {
+    user_session::revoke_others(&redis, user.id, current_id(&session).as_deref()).await?;
+    audit
+        .record(AuditEvent::success("session.revoke_others").user(user.id))
+        .await?;
+
+    flash.success("All your other sessions have been logged out.");
+
+    return Ok(redirect(is_htmx, "/settings/sessions"));
+}
@@ -0,0 +1,163 @@
+use crate::http::{
+    audit::{Audit, AuditEvent},
+    error::ApplicationError,
+    extractor::{error_bag, HxRequest, RequiredUser},
+    flash::Flash,
+    middleware::RequireSudo,
+    personal_access_token::{self, SCOPES},
+    response::redirect,
+    AppContext,
+};
+use crate::view::settings::{tokens_page, NewTokenForm};
+use axum::{
+    extract::{Path, State},
+    response::Response,
+    routing::{get, post},
+    Form, Router,
+};
+use maud::Markup;
+use time::{Duration, OffsetDateTime};
+use validator::Validate;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route(
+            "/tokens",
+            get(index).merge(post(store).route_layer(RequireSudo::new())),
+        )
+        .route("/tokens/:id/delete", post(destroy));
+}
+
+async fn index(
+    State(AppContext { db, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+) -> Result<Markup, ApplicationError> {
+    let tokens = personal_access_token::list_for_user(&db, user.id).await?;
+
+    return Ok(tokens_page(&tokens, None, None, None));
+}
+
+#[derive(Debug, Validate)]
+struct CreateTokenRequest {
+    #[validate(
+        required(message = "This field is required."),
+        length(max = 255, message = "The name must be at most 255 characters.")
+    )]
+    name: Option<String>,
+
+    #[validate(length(min = 1, message = "Select at least one scope."))]
+    scopes: Vec<String>,
+
+    #[validate(range(min = 1, max = 365, message = "Choose an expiration from the list."))]
+    expires_in_days: Option<i64>,
+
+    expires_in_days_input: String,
+}
+
+impl CreateTokenRequest {
+    /// Build the request from the submitted pairs. The scopes
+    /// are checkboxes sharing one name, which a struct can not
+    /// be deserialized from.
+    fn from_pairs(pairs: Vec<(String, String)>) -> Self {
+        let mut request = Self {
+            name: None,
+            scopes: vec![],
+            expires_in_days: None,
+            expires_in_days_input: String::new(),
+        };
+
+        for (key, value) in pairs {
+            match key.as_str() {
+                "name" if !value.is_empty() => request.name = Some(value),
+                "scopes" if SCOPES.iter().any(|(scope, _)| *scope == value) => {
+                    request.scopes.push(value)
+                }
+                "expires_in_days" => {
+                    request.expires_in_days = value.parse().ok().or(if value.is_empty() {
+                        None
+                    } else {
+                        Some(0)
+                    });
+                    request.expires_in_days_input = value;
+                }
+                _ => {}
+            }
+        }
+
+        return request;
+    }
+
+    fn form(&self) -> NewTokenForm {
+        return NewTokenForm {
+            name: self.name.as_deref().unwrap_or(""),
+            scopes: &self.scopes,
+            expires_in_days: &self.expires_in_days_input,
+        };
+    }
+}
+
+async fn store(
+    State(AppContext { db, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    audit: Audit,
+    Form(pairs): Form<Vec<(String, String)>>,
+) -> Result<Markup, ApplicationError> {
+    let request = CreateTokenRequest::from_pairs(pairs);
+
+    if let Err(err) = request.validate() {
+        let tokens = personal_access_token::list_for_user(&db, user.id).await?;
+
+        return Ok(tokens_page(
+            &tokens,
+            Some(request.form()),
+            Some(&error_bag(&err)),
+            None,
+        ));
+    }
+
+    let expires_at = request
+        .expires_in_days
+        .map(|days| OffsetDateTime::now_utc() + Duration::days(days));
+
+    let token = personal_access_token::create(
+        &db,
+        user.id,
+        request.name.as_deref().unwrap(),
+        &request.scopes,
+        expires_at,
+    )
+    .await?;
+
+    audit
+        .record(
+            AuditEvent::success("token.create")
+                .user(user.id)
+                .with("name", request.name.as_deref())
+                .with("scopes", request.scopes.clone()),
+        )
+        .await?;
+
+    // The plain-text token is rendered right away instead
+    // of redirecting, as this is the only time it is known.
+    let tokens = personal_access_token::list_for_user(&db, user.id).await?;
+
+    return Ok(tokens_page(&tokens, None, None, Some(&token)));
+}
+
+async fn destroy(
+    State(AppContext { db, .. }): State<AppContext>,
+    RequiredUser(user): RequiredUser,
+    HxRequest(is_htmx): HxRequest,
+    flash: Flash,
+    audit: Audit,
+    Path(id): Path<u32>,
+) -> Result<Response, ApplicationError> {
+    personal_access_token::delete(&db, user.id, id).await?;
+    audit
+        .record(AuditEvent::success("token.revoke").user(user.id).with("token_id", id))
+        .await?;
+
+    flash.success("The token has been revoked.");
+
+    return Ok(redirect(is_htmx, "/settings/tokens"));
+}
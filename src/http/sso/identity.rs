@@ -0,0 +1,189 @@
+use super::provider::ExternalIdentity;
+use crate::http::{
+    authentication::register::hash_password, check_username::username_errors,
+    error::ApplicationError, utils::random_token,
+};
+use sqlx::{MySqlConnection, MySqlPool};
+
+/// The id and username of the user the external identity
+/// is linked to.
+pub async fn find_user(
+    db: &MySqlPool,
+    identity: &ExternalIdentity,
+) -> Result<Option<(u32, String)>, ApplicationError> {
+    let record = sqlx::query!(
+        "select users.id, users.username from user_identities
+        inner join users on users.id = user_identities.user_id
+        where user_identities.provider = ? and user_identities.subject = ?",
+        identity.provider,
+        identity.subject
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| (record.id, record.username)));
+}
+
+pub async fn link(
+    db: &MySqlPool,
+    user_id: u32,
+    identity: &ExternalIdentity,
+) -> Result<(), ApplicationError> {
+    let mut connection = db
+        .acquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return insert_identity(&mut connection, user_id, identity).await;
+}
+
+async fn insert_identity(
+    connection: &mut MySqlConnection,
+    user_id: u32,
+    identity: &ExternalIdentity,
+) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "insert into user_identities (user_id, provider, subject, username, email) values (?, ?, ?, ?, ?)",
+        user_id,
+        identity.provider,
+        identity.subject,
+        identity.username,
+        identity.email
+    )
+    .execute(connection)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+/// Keep the username the provider knows the user by, which
+/// may have changed since the identity was linked.
+pub async fn update_username(
+    db: &MySqlPool,
+    identity: &ExternalIdentity,
+) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "update user_identities set username = ? where provider = ? and subject = ?",
+        identity.username,
+        identity.provider,
+        identity.subject
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+/// The username the provider knows the user by, if they have
+/// an identity linked at the provider.
+pub async fn find_username(
+    db: &MySqlPool,
+    user_id: u32,
+    provider: &str,
+) -> Result<Option<String>, ApplicationError> {
+    let record = sqlx::query!(
+        "select username from user_identities where user_id = ? and provider = ?",
+        user_id,
+        provider
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.and_then(|record| record.username));
+}
+
+/// The id and username of the account registered with the
+/// email address.
+pub async fn find_user_by_email(
+    db: &MySqlPool,
+    email: &str,
+) -> Result<Option<(u32, String)>, ApplicationError> {
+    let record = sqlx::query!("select id, username from users where email = ?", email)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| (record.id, record.username)));
+}
+
+/// Register a new user for the external identity and link
+/// them. The user gets a random password nobody knows, so
+/// they can only sign in through the provider until they
+/// reset it. A user is never left without the identity.
+pub async fn create_user(
+    db: &MySqlPool,
+    identity: &ExternalIdentity,
+    email: &str,
+) -> Result<(u32, String), ApplicationError> {
+    let username = available_username(db, identity.username.as_deref()).await?;
+    let password_hash = hash_password(&random_token(32))?;
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let result = sqlx::query!(
+        "insert into users (username, email, password) values (?, ?, ?)",
+        username,
+        email,
+        password_hash
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let user_id = result.last_insert_id() as u32;
+    insert_identity(&mut tx, user_id, identity).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok((user_id, username));
+}
+
+/// Derive a free username from the one the provider knows,
+/// within the 5-12 characters our usernames are made of.
+async fn available_username(
+    db: &MySqlPool,
+    preferred: Option<&str>,
+) -> Result<String, ApplicationError> {
+    let mut base: String = preferred
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .take(8)
+        .collect();
+
+    if base.len() >= 5 && username_errors(db, Some(&base)).await?.is_empty() {
+        return Ok(base);
+    }
+
+    if base.is_empty() {
+        base = "user".to_string();
+    }
+
+    // Otherwise we will try a few random suffixes, which
+    // keep the username within 12 characters.
+    for _ in 0..5 {
+        let suffix: String = random_token(8)
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .take(4)
+            .collect();
+        let username = format!("{}{}", base, suffix);
+
+        if username_errors(db, Some(&username)).await?.is_empty() {
+            return Ok(username);
+        }
+    }
+
+    return Err(ApplicationError::ServerError(
+        "Failed to find an available username".to_string(),
+    ));
+}
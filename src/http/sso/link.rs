@@ -0,0 +1,126 @@
+use super::{identity, provider::ExternalIdentity};
+use crate::http::{
+    account_deletion,
+    audit::{Audit, AuditEvent},
+    authentication::login::{attempt, intended_url, log_in, LoginAttempRequest, ACCOUNT_RESTORED},
+    error::ApplicationError,
+    flash::Flash,
+    AppContext,
+};
+use crate::view::sso::link_account_page;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Form, Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use serde::{Deserialize, Serialize};
+
+const PENDING_LINK: &str = "_sso_link";
+
+pub fn router() -> Router<AppContext> {
+    return Router::new().route("/link-account", get(show).post(store));
+}
+
+#[derive(Deserialize, Debug)]
+struct LinkAccountRequest {
+    #[serde(default)]
+    password: String,
+}
+
+/// The external identity waiting for the user to prove they
+/// own the account registered with its email address.
+#[derive(Serialize, Deserialize)]
+pub struct PendingLink {
+    pub identity: ExternalIdentity,
+    pub provider_name: String,
+}
+
+pub fn set_pending(session: &Session<SessionRedisPool>, pending: &PendingLink) {
+    session.set(PENDING_LINK, pending);
+}
+
+async fn show(session: Session<SessionRedisPool>) -> Response {
+    let Some(pending) = session.get::<PendingLink>(PENDING_LINK) else {
+        return Redirect::to("/login").into_response();
+    };
+
+    return link_account_page(
+        &pending.provider_name,
+        pending.identity.email.as_deref().unwrap_or_default(),
+        None,
+    )
+    .into_response();
+}
+
+async fn store(
+    State(AppContext {
+        db, credentials, ..
+    }): State<AppContext>,
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    audit: Audit,
+    Form(request): Form<LinkAccountRequest>,
+) -> Result<Response, ApplicationError> {
+    let Some(PendingLink {
+        identity,
+        provider_name,
+    }) = session.get::<PendingLink>(PENDING_LINK)
+    else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let email = identity.email.as_deref().unwrap_or_default();
+
+    let Some((user_id, username)) = identity::find_user_by_email(&db, email).await? else {
+        session.remove(PENDING_LINK);
+        flash.error("The account no longer exists.");
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    // The password is checked the same way as on the login
+    // page, so the account is linked only by its owner.
+    let attempt_request = LoginAttempRequest {
+        username: Some(credentials.login_name(user_id, &username).await?),
+        password: Some(request.password).filter(|password| !password.is_empty()),
+    };
+    let Some(user) = attempt(credentials.as_ref(), &attempt_request)
+        .await?
+        .filter(|user| user.id == user_id)
+    else {
+        audit
+            .record(
+                AuditEvent::failure("sso.link")
+                    .username(attempt_request.username.as_deref().unwrap_or_default())
+                    .with("provider", provider_name.as_str()),
+            )
+            .await?;
+        return Ok(
+            link_account_page(&provider_name, email, Some("The password is incorrect."))
+                .into_response(),
+        );
+    };
+
+    identity::link(&db, user.id, &identity).await?;
+    session.remove(PENDING_LINK);
+    audit
+        .record(
+            AuditEvent::success("sso.link")
+                .user(user.id)
+                .with("provider", provider_name.as_str()),
+        )
+        .await?;
+
+    if account_deletion::restore(&db, user.id).await? {
+        audit
+            .record(AuditEvent::success("account.restored").user(user.id))
+            .await?;
+        flash.success(ACCOUNT_RESTORED);
+    }
+
+    let to = intended_url(&session);
+    log_in(&session, &user);
+    flash.success(format!("Your {} account has been linked.", provider_name));
+
+    return Ok(Redirect::to(&to).into_response());
+}
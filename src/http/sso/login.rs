@@ -0,0 +1,140 @@
+use super::{find, sign_in};
+use crate::http::{
+    audit::Audit,
+    error::ApplicationError,
+    flash::Flash,
+    middleware::Auth,
+    utils::{constant_time_eq, random_token},
+    AppContext,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const PENDING_LOGIN: &str = "_sso";
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/login/:provider", get(redirect))
+        .route("/login/:provider/callback", get(callback));
+}
+
+/// The sign-in the user was sent to the provider for, kept
+/// in the session until they come back.
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    provider: String,
+    state: String,
+    nonce: String,
+    verifier: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CallbackRequest {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Send the user to the provider to sign in.
+async fn redirect(
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    Path(provider_id): Path<String>,
+) -> Response {
+    let Some(provider) = find(&provider_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let pending = PendingLogin {
+        provider: provider.id.clone(),
+        state: random_token(32),
+        nonce: random_token(32),
+        verifier: random_token(32),
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(pending.verifier.as_bytes()));
+
+    return match provider
+        .authorization_url(&pending.state, &pending.nonce, &challenge)
+        .await
+    {
+        Ok(url) => {
+            session.set(PENDING_LOGIN, &pending);
+            Redirect::to(&url).into_response()
+        }
+        Err(error) => {
+            println!("Server error: {:?}", error);
+            flash.error(format!("{} is not available right now.", provider.name));
+            Redirect::to("/login").into_response()
+        }
+    };
+}
+
+/// The user is back from the provider.
+async fn callback(
+    State(AppContext { db, .. }): State<AppContext>,
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    auth: Auth,
+    audit: Audit,
+    Path(provider_id): Path<String>,
+    Query(request): Query<CallbackRequest>,
+) -> Result<Response, ApplicationError> {
+    let failed = |message: &str| {
+        flash.error(message);
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    // The state must be the one we sent the user away with,
+    // otherwise another site could log them in as someone else.
+    let pending = session
+        .get_remove::<PendingLogin>(PENDING_LOGIN)
+        .filter(|pending| pending.provider == provider_id)
+        .filter(|pending| {
+            request
+                .state
+                .as_deref()
+                .is_some_and(|state| constant_time_eq(state, &pending.state))
+        });
+
+    let (Some(pending), Some(provider)) = (pending, find(&provider_id)) else {
+        return failed("The sign-in request has expired, please try again.");
+    };
+
+    let Some(code) = request.code.filter(|_| request.error.is_none()) else {
+        return failed(&format!(
+            "The sign-in with {} was cancelled.",
+            provider.name
+        ));
+    };
+
+    let identity = match provider
+        .identity(&code, &pending.verifier, &pending.nonce)
+        .await
+    {
+        Ok(identity) => identity,
+        Err(error) => {
+            println!("Server error: {:?}", error);
+            return failed(&format!("The sign-in with {} failed.", provider.name));
+        }
+    };
+
+    return sign_in(
+        &db,
+        &session,
+        &flash,
+        &auth,
+        &audit,
+        identity,
+        &provider.name,
+    )
+    .await;
+}
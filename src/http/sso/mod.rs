@@ -0,0 +1,181 @@
+use std::{env, sync::OnceLock};
+
+use super::{
+    account_deletion,
+    audit::{Audit, AuditEvent},
+    authentication::login::{intended_url, log_in, AuthenticatedUser, ACCOUNT_RESTORED},
+    error::ApplicationError,
+    flash::Flash,
+    middleware::Auth,
+    AppContext,
+};
+use axum::{
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use link::PendingLink;
+use provider::{ExternalIdentity, Provider};
+use sqlx::MySqlPool;
+
+pub(super) mod identity;
+mod link;
+mod login;
+pub(super) mod provider;
+pub(super) mod saml;
+
+static PROVIDERS: OnceLock<Vec<Provider>> = OnceLock::new();
+static APP_URL: OnceLock<String> = OnceLock::new();
+
+/// Read the identity providers listed in `SSO_PROVIDERS`, a
+/// comma-separated list of ids such as `corporate,github`.
+/// See `Provider::from_env` for the settings of each one.
+pub fn init(app_url: &str) -> Result<(), String> {
+    let http = reqwest::Client::new();
+    let providers = env::var("SSO_PROVIDERS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| Provider::from_env(id, app_url, http.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let _ = APP_URL.set(app_url.to_string());
+
+    return PROVIDERS
+        .set(providers)
+        .map_err(|_| "The identity providers are already loaded".to_string());
+}
+
+/// The URL the providers send the users back to.
+fn app_url() -> &'static str {
+    return APP_URL.get().map(String::as_str).unwrap_or_default();
+}
+
+fn find(id: &str) -> Option<&'static Provider> {
+    return PROVIDERS.get()?.iter().find(|provider| provider.id == id);
+}
+
+/// The ids and names of the providers, for the login page.
+pub fn providers() -> Vec<(&'static str, &'static str)> {
+    return PROVIDERS
+        .get()
+        .map(|providers| {
+            providers
+                .iter()
+                .map(|provider| (provider.id.as_str(), provider.name.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+}
+
+/// Signing in with an external identity provider, and linking
+/// the identities to our accounts.
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .merge(login::router())
+        .merge(saml::router())
+        .merge(link::router());
+}
+
+/// Finish signing in with the external identity. We will log
+/// the user in with the account the identity is linked to, or
+/// link it to the logged-in user, or else to a new account.
+async fn sign_in(
+    db: &MySqlPool,
+    session: &Session<SessionRedisPool>,
+    flash: &Flash,
+    auth: &Auth,
+    audit: &Audit,
+    identity: ExternalIdentity,
+    provider_name: &str,
+) -> Result<Response, ApplicationError> {
+    if let Some((id, username)) = identity::find_user(db, &identity).await? {
+        if auth.id().is_some_and(|user_id| user_id != id) {
+            audit
+                .record(
+                    AuditEvent::failure("sso.link")
+                        .user(auth.id().unwrap_or_default())
+                        .with("provider", provider_name),
+                )
+                .await?;
+            flash.error(format!(
+                "This {} account is already linked to another user.",
+                provider_name
+            ));
+            return Ok(Redirect::to("/home").into_response());
+        }
+
+        audit
+            .record(
+                AuditEvent::success("login")
+                    .user(id)
+                    .with("provider", provider_name),
+            )
+            .await?;
+
+        if account_deletion::restore(db, id).await? {
+            audit
+                .record(AuditEvent::success("account.restored").user(id))
+                .await?;
+            flash.success(ACCOUNT_RESTORED);
+        }
+
+        let to = intended_url(session);
+        log_in(session, &AuthenticatedUser { id, username });
+
+        return Ok(Redirect::to(&to).into_response());
+    }
+
+    // A logged-in user is adding the identity to their account.
+    if let Some(user_id) = auth.id() {
+        identity::link(db, user_id, &identity).await?;
+        audit
+            .record(
+                AuditEvent::success("sso.link")
+                    .user(user_id)
+                    .with("provider", provider_name),
+            )
+            .await?;
+        flash.success(format!("Your {} account has been linked.", provider_name));
+
+        return Ok(Redirect::to("/home").into_response());
+    }
+
+    let Some(email) = identity.email.clone() else {
+        flash.error(format!(
+            "{} did not share your email address with us.",
+            provider_name
+        ));
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    // The address belongs to one of our accounts. We will
+    // not trust the provider to have verified it, so the user
+    // has to prove they own the account before linking it.
+    if identity::find_user_by_email(db, &email).await?.is_some() {
+        link::set_pending(
+            session,
+            &PendingLink {
+                identity,
+                provider_name: provider_name.to_string(),
+            },
+        );
+
+        return Ok(Redirect::to("/link-account").into_response());
+    }
+
+    let (id, username) = identity::create_user(db, &identity, &email).await?;
+    audit
+        .record(
+            AuditEvent::success("register")
+                .user(id)
+                .with("provider", provider_name),
+        )
+        .await?;
+    let to = intended_url(session);
+    log_in(session, &AuthenticatedUser { id, username });
+    flash.success("Welcome! Your account has been created.");
+
+    return Ok(Redirect::to(&to).into_response());
+}
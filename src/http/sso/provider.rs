@@ -0,0 +1,547 @@
+use std::env;
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use reqwest::header::{ACCEPT, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{OnceCell, RwLock};
+
+/// An identity provider users can sign in with, e.g. our
+/// corporate IdP (OpenID Connect) or GitHub (plain OAuth2).
+pub struct Provider {
+    pub id: String,
+    pub name: String,
+    client_id: String,
+    client_secret: String,
+    scopes: String,
+    redirect_uri: String,
+    /// Set for OpenID Connect providers, whose endpoints are
+    /// discovered from it and whose ID tokens it must issue.
+    issuer: Option<String>,
+    endpoints: OnceCell<Endpoints>,
+    /// The keys signing the ID tokens, as last fetched from
+    /// the `jwks_uri` of the provider.
+    keys: RwLock<Option<JwkSet>>,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+struct Endpoints {
+    #[serde(rename = "authorization_endpoint")]
+    authorization: String,
+    #[serde(rename = "token_endpoint")]
+    token: String,
+    #[serde(rename = "userinfo_endpoint")]
+    userinfo: Option<String>,
+    #[serde(rename = "jwks_uri")]
+    jwks: Option<String>,
+}
+
+/// The user as known by the provider.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExternalIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub username: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    preferred_username: Option<String>,
+    email: Option<String>,
+}
+
+impl Provider {
+    /// Read the provider from the `SSO_<ID>_*` variables:
+    /// `NAME`, `CLIENT_ID`, `CLIENT_SECRET` and `SCOPES`, plus
+    /// either `ISSUER` for an OpenID Connect provider, or
+    /// `AUTHORIZE_URL`, `TOKEN_URL` and `USERINFO_URL` for a
+    /// plain OAuth2 one. The provider must send the users back
+    /// to `<APP_URL>/login/<id>/callback`.
+    pub fn from_env(id: &str, app_url: &str, http: reqwest::Client) -> Result<Self, String> {
+        let prefix = format!("SSO_{}_", id.to_uppercase());
+        let var = |name: &str| env::var(format!("{}{}", prefix, name)).ok();
+        let required =
+            |name: &str| var(name).ok_or_else(|| format!("{}{} is not set", prefix, name));
+
+        let issuer = var("ISSUER").map(|issuer| issuer.trim_end_matches('/').to_string());
+        let endpoints = match issuer {
+            Some(_) => OnceCell::new(),
+            None => OnceCell::new_with(Some(Endpoints {
+                authorization: required("AUTHORIZE_URL")?,
+                token: required("TOKEN_URL")?,
+                userinfo: Some(required("USERINFO_URL")?),
+                jwks: None,
+            })),
+        };
+
+        return Ok(Self {
+            id: id.to_string(),
+            name: var("NAME").unwrap_or(id.to_string()),
+            client_id: required("CLIENT_ID")?,
+            client_secret: required("CLIENT_SECRET")?,
+            scopes: var("SCOPES").unwrap_or(match issuer {
+                Some(_) => "openid profile email".to_string(),
+                None => String::new(),
+            }),
+            redirect_uri: format!("{}/login/{}/callback", app_url, id),
+            issuer,
+            endpoints,
+            keys: RwLock::new(None),
+            http,
+        });
+    }
+
+    /// The endpoints of OpenID Connect providers are read from
+    /// their discovery document the first time they are needed.
+    async fn endpoints(&self) -> Result<&Endpoints, String> {
+        return self
+            .endpoints
+            .get_or_try_init(|| async {
+                let issuer = self.issuer.as_deref().unwrap_or_default();
+
+                return self
+                    .http
+                    .get(format!("{}/.well-known/openid-configuration", issuer))
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| e.to_string())?
+                    .json::<Endpoints>()
+                    .await
+                    .map_err(|e| e.to_string());
+            })
+            .await;
+    }
+
+    /// Where to send the user to sign in. The `state` ties the
+    /// callback to the session, the `nonce` ties the ID token to
+    /// this request and the PKCE challenge ties the code to us.
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String, String> {
+        let endpoints = self.endpoints().await?;
+        let mut params = vec![
+            ("response_type", "code"),
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("state", state),
+            ("code_challenge", code_challenge),
+            ("code_challenge_method", "S256"),
+        ];
+        if !self.scopes.is_empty() {
+            params.push(("scope", &self.scopes));
+        }
+        if self.issuer.is_some() {
+            params.push(("nonce", nonce));
+        }
+
+        let query = serde_urlencoded::to_string(&params).map_err(|e| e.to_string())?;
+        let separator = if endpoints.authorization.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+
+        return Ok(format!("{}{}{}", endpoints.authorization, separator, query));
+    }
+
+    /// Exchange the code for tokens and find out who the user
+    /// is, from the ID token or else from the userinfo endpoint.
+    pub async fn identity(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, String> {
+        let endpoints = self.endpoints().await?;
+
+        let tokens = self
+            .http
+            .post(&endpoints.token)
+            .header(ACCEPT, "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<TokenResponse>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut identity = match (&self.issuer, &tokens.id_token) {
+            (Some(issuer), Some(id_token)) => self.verify_id_token(issuer, id_token, nonce).await?,
+            (Some(_), None) => return Err("The provider did not return an ID token".to_string()),
+            (None, _) => self.userinfo(endpoints, &tokens.access_token).await?,
+        };
+
+        // Many providers only release the email address from
+        // their userinfo endpoint.
+        if identity.email.is_none() && self.issuer.is_some() && endpoints.userinfo.is_some() {
+            let userinfo = self.userinfo(endpoints, &tokens.access_token).await?;
+
+            if userinfo.subject != identity.subject {
+                return Err("The userinfo does not belong to the ID token".to_string());
+            }
+
+            identity.email = userinfo.email;
+            identity.username = identity.username.or(userinfo.username);
+        }
+
+        return Ok(identity);
+    }
+
+    /// Check the signature of the ID token with the keys the
+    /// provider publishes, then its claims. Tokens signed with
+    /// a shared secret are refused, the secret being ours too.
+    async fn verify_id_token(
+        &self,
+        issuer: &str,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, String> {
+        let header = decode_header(id_token).map_err(|e| e.to_string())?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err("The ID token is not signed with a key of the provider".to_string());
+        }
+
+        let jwk = self.signing_key(header.kid.as_deref()).await?;
+        let key = DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[issuer]);
+        validation.set_audience(&[&self.client_id]);
+
+        let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|e| e.to_string())?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err("The nonce of the ID token does not match".to_string());
+        }
+
+        return Ok(ExternalIdentity {
+            provider: self.id.clone(),
+            subject: claims.sub,
+            username: claims.preferred_username,
+            email: claims.email,
+        });
+    }
+
+    /// The key named by the ID token. The keys are fetched again
+    /// when the token names one we do not know, as providers
+    /// rotate their keys.
+    async fn signing_key(&self, kid: Option<&str>) -> Result<Jwk, String> {
+        // Without a key id, the provider must have one key only.
+        let find = |keys: &JwkSet| match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        };
+
+        if let Some(jwk) = self.keys.read().await.as_ref().and_then(find) {
+            return Ok(jwk);
+        }
+
+        let Some(url) = &self.endpoints().await?.jwks else {
+            return Err("The provider has no jwks_uri".to_string());
+        };
+
+        let keys = self
+            .http
+            .get(url)
+            .header(ACCEPT, "application/json")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<JwkSet>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let jwk = find(&keys);
+        *self.keys.write().await = Some(keys);
+
+        return jwk.ok_or_else(|| "The ID token is signed with an unknown key".to_string());
+    }
+
+    /// Read the user from the userinfo endpoint. Plain OAuth2
+    /// providers name the claims their own way, e.g. GitHub
+    /// has `id` and `login` instead of `sub` and
+    /// `preferred_username`.
+    async fn userinfo(
+        &self,
+        endpoints: &Endpoints,
+        access_token: &str,
+    ) -> Result<ExternalIdentity, String> {
+        let Some(url) = &endpoints.userinfo else {
+            return Err("The provider has no userinfo endpoint".to_string());
+        };
+
+        let userinfo = self
+            .http
+            .get(url)
+            .bearer_auth(access_token)
+            .header(ACCEPT, "application/json")
+            .header(USER_AGENT, "AuthKit")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<Value>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let text = |names: &[&str]| {
+            names.iter().find_map(|name| match &userinfo[name] {
+                Value::String(value) => Some(value.clone()),
+                Value::Number(value) => Some(value.to_string()),
+                _ => None,
+            })
+        };
+
+        let Some(subject) = text(&["sub", "id"]) else {
+            return Err("The userinfo has no subject".to_string());
+        };
+
+        return Ok(ExternalIdentity {
+            provider: self.id.clone(),
+            subject,
+            username: text(&["preferred_username", "login"]),
+            email: text(&["email"]),
+        });
+    }
+}
+
+/// The identity provider is played by a server on a random
+/// port: its discovery document, its keys, and a token
+/// endpoint answering with the ID token of each test.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::jwt::JwtKeys;
+    use axum::{
+        routing::{get, post},
+        Json, Router,
+    };
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+    use std::net::TcpListener;
+    use time::OffsetDateTime;
+
+    const CLIENT_ID: &str = "authkit";
+    const NONCE: &str = "n-0S6_WzA2Mj";
+
+    /// The keys of the provider are in `fixtures/idp`, those in
+    /// `fixtures/other` have the same key id.
+    fn keys(directory: &str, issuer: &str) -> JwtKeys {
+        let directory = format!(
+            "{}/src/http/sso/fixtures/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            directory
+        );
+
+        return JwtKeys::load(directory, issuer, None).unwrap();
+    }
+
+    fn claims(issuer: &str) -> Value {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        return json!({
+            "iss": issuer,
+            "sub": "248289761001",
+            "aud": CLIENT_ID,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": NONCE,
+            "preferred_username": "jane.doe",
+            "email": "jane@example.org",
+        });
+    }
+
+    fn signed(issuer: &str, claims: &Value) -> String {
+        return keys("idp", issuer).sign(claims).unwrap();
+    }
+
+    /// Start the provider. Its token endpoint answers with the
+    /// ID token `id_token` makes for the issuer.
+    fn identity_provider(id_token: impl FnOnce(&str) -> String) -> Provider {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let discovery = json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "jwks_uri": format!("{}/jwks", issuer),
+        });
+        let jwks = keys("idp", &issuer).jwks();
+        let tokens = json!({
+            "access_token": "SlAV32hkKG",
+            "token_type": "Bearer",
+            "id_token": id_token(&issuer),
+        });
+
+        let app = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                get(|| async move { Json(discovery) }),
+            )
+            .route("/jwks", get(|| async move { Json(jwks) }))
+            .route("/token", post(|| async move { Json(tokens) }));
+
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        return Provider {
+            id: "corporate".to_string(),
+            name: "Corporate".to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: "secret".to_string(),
+            scopes: "openid profile email".to_string(),
+            redirect_uri: "https://auth.example.com/login/corporate/callback".to_string(),
+            issuer: Some(issuer),
+            endpoints: OnceCell::new(),
+            keys: RwLock::new(None),
+            http: reqwest::Client::new(),
+        };
+    }
+
+    async fn identity(id_token: impl FnOnce(&str) -> String) -> Result<ExternalIdentity, String> {
+        return identity_provider(id_token)
+            .identity("SplxlOBeZQQYbYS6WxSbIA", "verifier", NONCE)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn it_reads_the_identity_from_a_signed_id_token() {
+        let identity = identity(|issuer| signed(issuer, &claims(issuer)))
+            .await
+            .unwrap();
+
+        assert_eq!(identity.provider, "corporate");
+        assert_eq!(identity.subject, "248289761001");
+        assert_eq!(identity.username.as_deref(), Some("jane.doe"));
+        assert_eq!(identity.email.as_deref(), Some("jane@example.org"));
+    }
+
+    #[tokio::test]
+    async fn it_fetches_the_keys_again_once_they_were_rotated() {
+        let provider = identity_provider(|issuer| signed(issuer, &claims(issuer)));
+        *provider.keys.write().await = Some(JwkSet { keys: vec![] });
+
+        assert!(provider
+            .identity("SplxlOBeZQQYbYS6WxSbIA", "verifier", NONCE)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_an_id_token_signed_with_another_key() {
+        let identity = identity(|issuer| keys("other", issuer).sign(&claims(issuer)).unwrap());
+
+        assert!(identity.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_an_id_token_signed_with_a_shared_secret() {
+        let identity = identity(|issuer| {
+            let header = Header {
+                kid: Some("idp".to_string()),
+                ..Header::new(Algorithm::HS256)
+            };
+
+            return encode(
+                &header,
+                &claims(issuer),
+                &EncodingKey::from_secret(b"secret"),
+            )
+            .unwrap();
+        });
+
+        assert!(identity.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_an_unsigned_id_token() {
+        let identity = identity(|issuer| {
+            let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"none","kid":"idp"}"#);
+            let payload = URL_SAFE_NO_PAD.encode(claims(issuer).to_string());
+
+            return format!("{}.{}.", header, payload);
+        });
+
+        assert!(identity.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_a_tampered_id_token() {
+        let identity = identity(|issuer| {
+            let token = signed(issuer, &claims(issuer));
+            let (header, rest) = token.split_once('.').unwrap();
+            let (_, signature) = rest.split_once('.').unwrap();
+
+            let mut claims = claims(issuer);
+            claims["sub"] = json!("1");
+            let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+
+            return format!("{}.{}.{}", header, payload, signature);
+        });
+
+        assert!(identity.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_an_id_token_issued_to_another_client() {
+        let identity = identity(|issuer| {
+            let mut claims = claims(issuer);
+            claims["aud"] = json!("another-client");
+
+            return signed(issuer, &claims);
+        });
+
+        assert!(identity.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_an_id_token_of_another_request() {
+        let identity = identity(|issuer| {
+            let mut claims = claims(issuer);
+            claims["nonce"] = json!("another-nonce");
+
+            return signed(issuer, &claims);
+        });
+
+        assert!(identity.await.is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use super::{app_url, sign_in};
+use crate::http::{
+    audit::Audit, error::ApplicationError, flash::Flash, middleware::Auth, utils::random_token,
+    AppContext,
+};
+use crate::view::sso::saml_tenant_page;
+use axum::{
+    extract::{Path, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Router,
+};
+use axum_session::{Session, SessionRedisPool};
+use redis::AsyncCommands;
+use redis_pool::SingleRedisPool;
+use serde::{Deserialize, Serialize};
+
+pub mod provider;
+
+/// How long the user has to sign in at the provider.
+const REQUEST_TTL_SECONDS: usize = 600;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/saml/:tenant", get(tenant))
+        .route("/saml/:tenant/:provider/metadata", get(metadata))
+        .route("/saml/:tenant/:provider/login", get(login))
+        .route("/saml/:tenant/:provider/acs", post(acs));
+}
+
+/// The AuthnRequest the user was sent to the provider with.
+/// It is kept in redis under the RelayState rather than in
+/// the session: the provider posts the response back from
+/// its own site, so the browser leaves our `SameSite=Lax`
+/// cookie out.
+#[derive(Serialize, Deserialize)]
+struct PendingRequest {
+    tenant: String,
+    provider: String,
+    request_id: String,
+}
+
+fn key(relay_state: &str) -> String {
+    return format!("saml_request:{}", relay_state);
+}
+
+async fn remember(
+    redis: &SingleRedisPool,
+    relay_state: &str,
+    pending: &PendingRequest,
+) -> Result<(), ApplicationError> {
+    let value =
+        serde_json::to_string(pending).map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    connection
+        .set_ex::<_, _, ()>(key(relay_state), value, REQUEST_TTL_SECONDS)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+/// The pending request of the RelayState, which can only be
+/// answered once.
+async fn take(
+    redis: &SingleRedisPool,
+    relay_state: &str,
+) -> Result<Option<PendingRequest>, ApplicationError> {
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let (value,): (Option<String>,) = redis::pipe()
+        .atomic()
+        .get(key(relay_state))
+        .del(key(relay_state))
+        .ignore()
+        .query_async(&mut *connection)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(value.and_then(|value| serde_json::from_str(&value).ok()));
+}
+
+/// The sign-in page of the tenant, where its users pick one
+/// of its providers. There is nothing to pick from when the
+/// tenant has a single provider.
+async fn tenant(
+    State(AppContext { db, .. }): State<AppContext>,
+    Path(tenant): Path<String>,
+) -> Result<Response, ApplicationError> {
+    let providers = provider::tenant_providers(&db, &tenant).await?;
+
+    return Ok(match providers.as_slice() {
+        [] => StatusCode::NOT_FOUND.into_response(),
+        [(slug, _)] => Redirect::to(&format!("/saml/{}/{}/login", tenant, slug)).into_response(),
+        providers => saml_tenant_page(&tenant, providers).into_response(),
+    });
+}
+
+/// The metadata of our service provider, to be registered
+/// with the identity provider.
+async fn metadata(
+    State(AppContext { db, .. }): State<AppContext>,
+    Path((tenant, slug)): Path<(String, String)>,
+) -> Result<Response, ApplicationError> {
+    let Some(provider) = provider::find(&db, &tenant, &slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let xml = provider
+        .metadata_xml(app_url())
+        .map_err(ApplicationError::ServerError)?;
+
+    return Ok(([(CONTENT_TYPE, "application/samlmetadata+xml")], xml).into_response());
+}
+
+/// Send the user to the provider with an AuthnRequest.
+async fn login(
+    State(AppContext { db, redis, .. }): State<AppContext>,
+    flash: Flash,
+    Path((tenant, slug)): Path<(String, String)>,
+) -> Result<Response, ApplicationError> {
+    let Some(provider) = provider::find(&db, &tenant, &slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let relay_state = random_token(32);
+    let (request_id, url) = match provider.authentication_request(app_url(), &relay_state) {
+        Ok(request) => request,
+        Err(error) => {
+            println!("Server error: {:?}", error);
+            flash.error(format!("{} is not available right now.", provider.name));
+            return Ok(Redirect::to("/login").into_response());
+        }
+    };
+
+    remember(
+        &redis,
+        &relay_state,
+        &PendingRequest {
+            tenant: provider.tenant,
+            provider: provider.slug,
+            request_id,
+        },
+    )
+    .await?;
+
+    return Ok(Redirect::to(&url).into_response());
+}
+
+#[derive(Deserialize)]
+struct AssertionConsumerRequest {
+    #[serde(rename = "SAMLResponse")]
+    saml_response: String,
+    #[serde(rename = "RelayState", default)]
+    relay_state: String,
+}
+
+/// The provider posts the response back here. Responses
+/// sent without a request of ours (IdP-initiated) are
+/// refused, they could be replayed to log someone in.
+async fn acs(
+    State(AppContext { db, redis, .. }): State<AppContext>,
+    session: Session<SessionRedisPool>,
+    flash: Flash,
+    auth: Auth,
+    audit: Audit,
+    Path((tenant, slug)): Path<(String, String)>,
+    Form(request): Form<AssertionConsumerRequest>,
+) -> Result<Response, ApplicationError> {
+    let pending = take(&redis, &request.relay_state)
+        .await?
+        .filter(|pending| pending.tenant == tenant && pending.provider == slug);
+
+    let (Some(pending), Some(provider)) = (pending, provider::find(&db, &tenant, &slug).await?)
+    else {
+        flash.error("The sign-in request has expired, please try again.");
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let identity = match provider.identity(app_url(), &request.saml_response, &pending.request_id) {
+        Ok(identity) => identity,
+        Err(error) => {
+            println!("Server error: {:?}", error);
+            flash.error(format!("The sign-in with {} failed.", provider.name));
+            return Ok(Redirect::to("/login").into_response());
+        }
+    };
+
+    return sign_in(
+        &db,
+        &session,
+        &flash,
+        &auth,
+        &audit,
+        identity,
+        &provider.name,
+    )
+    .await;
+}
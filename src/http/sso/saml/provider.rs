@@ -0,0 +1,392 @@
+use super::super::provider::ExternalIdentity;
+use crate::http::error::ApplicationError;
+use samael::{
+    metadata::{EntityDescriptor, HTTP_REDIRECT_BINDING},
+    schema::Assertion,
+    service_provider::{ServiceProvider, ServiceProviderBuilder},
+};
+use sqlx::MySqlPool;
+
+/// The attributes the email address and the username are
+/// read from when the provider does not name its own, as
+/// sent by the common IdPs (ADFS, Okta, Keycloak...).
+const EMAIL_ATTRIBUTES: [&str; 4] = [
+    "email",
+    "mail",
+    "urn:oid:0.9.2342.19200300.100.1.3",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/emailaddress",
+];
+const USERNAME_ATTRIBUTES: [&str; 3] = ["username", "uid", "urn:oid:0.9.2342.19200300.100.1.1"];
+
+/// A SAML identity provider, e.g. the one of a customer
+/// federating their employees with us. Each customer is a
+/// tenant, which can register as many providers as it needs.
+pub struct SamlProvider {
+    pub tenant: String,
+    pub slug: String,
+    pub name: String,
+    metadata: String,
+    email_attribute: Option<String>,
+    username_attribute: Option<String>,
+}
+
+pub async fn find(
+    db: &MySqlPool,
+    tenant: &str,
+    slug: &str,
+) -> Result<Option<SamlProvider>, ApplicationError> {
+    let record = sqlx::query!(
+        "select tenant, slug, name, metadata, email_attribute, username_attribute
+        from saml_identity_providers where tenant = ? and slug = ?",
+        tenant,
+        slug
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(record.map(|record| SamlProvider {
+        tenant: record.tenant,
+        slug: record.slug,
+        name: record.name,
+        metadata: record.metadata,
+        email_attribute: record.email_attribute,
+        username_attribute: record.username_attribute,
+    }));
+}
+
+/// The slug and name of the providers of the tenant, in
+/// alphabetical order.
+pub async fn tenant_providers(
+    db: &MySqlPool,
+    tenant: &str,
+) -> Result<Vec<(String, String)>, ApplicationError> {
+    let records = sqlx::query!(
+        "select slug, name from saml_identity_providers where tenant = ? order by name",
+        tenant
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(records
+        .into_iter()
+        .map(|record| (record.slug, record.name))
+        .collect());
+}
+
+pub struct NewSamlProvider {
+    pub tenant: String,
+    pub slug: String,
+    pub name: String,
+    pub metadata: String,
+    pub email_attribute: Option<String>,
+    pub username_attribute: Option<String>,
+}
+
+pub async fn create(db: &MySqlPool, provider: &NewSamlProvider) -> Result<(), ApplicationError> {
+    sqlx::query!(
+        "insert into saml_identity_providers (tenant, slug, name, metadata, email_attribute, username_attribute)
+        values (?, ?, ?, ?, ?, ?)",
+        provider.tenant,
+        provider.slug,
+        provider.name,
+        provider.metadata,
+        provider.email_attribute,
+        provider.username_attribute
+    )
+    .execute(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+/// Check the metadata of an identity provider before it is
+/// registered: we send the users to it with the HTTP-Redirect
+/// binding, and only accept the responses it signed.
+pub fn validate_metadata(metadata: &str) -> Result<(), String> {
+    let idp_metadata: EntityDescriptor = metadata.parse().map_err(|e| format!("{}", e))?;
+    let service_provider = ServiceProviderBuilder::default()
+        .idp_metadata(idp_metadata)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    if service_provider
+        .sso_binding_location(HTTP_REDIRECT_BINDING)
+        .is_none()
+    {
+        return Err("The identity provider has no HTTP-Redirect SSO service.".to_string());
+    }
+
+    if service_provider
+        .idp_signing_certs()
+        .map_err(|e| e.to_string())?
+        .is_none()
+    {
+        return Err("The identity provider has no signing certificate.".to_string());
+    }
+
+    return Ok(());
+}
+
+impl SamlProvider {
+    /// The path of our endpoints for the provider, e.g.
+    /// `/saml/acme/okta`.
+    pub fn path(&self) -> String {
+        return format!("/saml/{}/{}", self.tenant, self.slug);
+    }
+
+    /// Our side of the federation with the provider. Each
+    /// provider gets its own entity id, the URL of the
+    /// metadata, so a customer can register us several times.
+    fn service_provider(&self, app_url: &str) -> Result<ServiceProvider, String> {
+        let idp_metadata: EntityDescriptor = self.metadata.parse().map_err(|e| format!("{}", e))?;
+        let metadata_url = format!("{}{}/metadata", app_url, self.path());
+
+        return ServiceProviderBuilder::default()
+            .entity_id(metadata_url.clone())
+            .metadata_url(metadata_url)
+            .acs_url(format!("{}{}/acs", app_url, self.path()))
+            .slo_url(None::<String>)
+            .idp_metadata(idp_metadata)
+            .build()
+            .map_err(|e| e.to_string());
+    }
+
+    /// The metadata the provider is set up with on their side.
+    pub fn metadata_xml(&self, app_url: &str) -> Result<String, String> {
+        return self
+            .service_provider(app_url)?
+            .metadata()
+            .and_then(|metadata| metadata.to_xml())
+            .map_err(|e| e.to_string());
+    }
+
+    /// The id of a new AuthnRequest, and the URL sending the
+    /// user to the provider with it (HTTP-Redirect binding).
+    pub fn authentication_request(
+        &self,
+        app_url: &str,
+        relay_state: &str,
+    ) -> Result<(String, String), String> {
+        let service_provider = self.service_provider(app_url)?;
+        let Some(sso_url) = service_provider.sso_binding_location(HTTP_REDIRECT_BINDING) else {
+            return Err("The identity provider has no HTTP-Redirect SSO service".to_string());
+        };
+
+        let request = service_provider
+            .make_authentication_request(&sso_url)
+            .map_err(|e| e.to_string())?;
+        let url = request
+            .redirect(relay_state)
+            .map_err(|e| e.to_string())?
+            .ok_or("Failed to encode the AuthnRequest")?;
+
+        return Ok((request.id, url.to_string()));
+    }
+
+    /// Read the user from the response the provider posted
+    /// back (HTTP-POST binding). The response must answer our
+    /// request and carry the signature of the provider, which
+    /// the assertions are read from.
+    pub fn identity(
+        &self,
+        app_url: &str,
+        saml_response: &str,
+        request_id: &str,
+    ) -> Result<ExternalIdentity, String> {
+        let service_provider = self.service_provider(app_url)?;
+
+        // Without certificates, the signatures would not be
+        // checked at all.
+        if service_provider
+            .idp_signing_certs()
+            .map_err(|e| e.to_string())?
+            .is_none()
+        {
+            return Err("The identity provider has no signing certificate".to_string());
+        }
+
+        let assertion = service_provider
+            .parse_base64_response(saml_response.trim(), Some(&[request_id]))
+            .map_err(|e| e.to_string())?;
+
+        let Some(subject) = assertion
+            .subject
+            .as_ref()
+            .and_then(|subject| subject.name_id.as_ref())
+            .map(|name_id| name_id.value.clone())
+        else {
+            return Err("The assertion has no NameID".to_string());
+        };
+
+        let email = match &self.email_attribute {
+            Some(name) => attribute(&assertion, &[name]),
+            None => attribute(&assertion, &EMAIL_ATTRIBUTES),
+        };
+        let username = match &self.username_attribute {
+            Some(name) => attribute(&assertion, &[name]),
+            None => attribute(&assertion, &USERNAME_ATTRIBUTES),
+        };
+
+        return Ok(ExternalIdentity {
+            provider: format!("saml:{}:{}", self.tenant, self.slug),
+            subject,
+            username,
+            email,
+        });
+    }
+}
+
+/// The first value of the first attribute named, by its
+/// name or its friendly name.
+fn attribute(assertion: &Assertion, names: &[&str]) -> Option<String> {
+    let attributes = assertion
+        .attribute_statements
+        .iter()
+        .flatten()
+        .flat_map(|statement| statement.attributes.iter());
+
+    for name in names {
+        let value = attributes
+            .clone()
+            .filter(|attribute| {
+                attribute.name.as_deref() == Some(name)
+                    || attribute.friendly_name.as_deref() == Some(name)
+            })
+            .flat_map(|attribute| attribute.values.iter())
+            .find_map(|value| value.value.clone());
+
+        if value.is_some() {
+            return value;
+        }
+    }
+
+    return None;
+}
+
+/// The identity provider is played by the fixtures: its
+/// metadata, the response it posts back, and its key which
+/// signs the response in each test.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use chrono::{Duration, SecondsFormat, Utc};
+    use samael::crypto::sign_xml;
+
+    const APP_URL: &str = "https://auth.example.com";
+    const REQUEST_ID: &str = "_request";
+    const IDP_KEY: &[u8] = include_bytes!("fixtures/idp_private_key.der");
+    const OTHER_KEY: &[u8] = include_bytes!("fixtures/other_private_key.der");
+
+    fn provider() -> SamlProvider {
+        return SamlProvider {
+            tenant: "acme".to_string(),
+            slug: "okta".to_string(),
+            name: "Okta".to_string(),
+            metadata: include_str!("fixtures/idp_metadata.xml").to_string(),
+            email_attribute: None,
+            username_attribute: None,
+        };
+    }
+
+    /// The response of the fixture, answering the request.
+    fn response_xml(request_id: &str) -> String {
+        let now = Utc::now();
+        let timestamp = |at: chrono::DateTime<Utc>| at.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        return include_str!("fixtures/response.xml")
+            .replace("{now}", &timestamp(now))
+            .replace("{later}", &timestamp(now + Duration::minutes(5)))
+            .replace("{acs_url}", &format!("{}/saml/acme/okta/acs", APP_URL))
+            .replace(
+                "{audience}",
+                &format!("{}/saml/acme/okta/metadata", APP_URL),
+            )
+            .replace("{request_id}", request_id);
+    }
+
+    fn signed(xml: &str, key: &[u8]) -> String {
+        return sign_xml(xml, key).unwrap();
+    }
+
+    fn identity(xml: &str) -> Result<ExternalIdentity, String> {
+        return provider().identity(APP_URL, &STANDARD.encode(xml), REQUEST_ID);
+    }
+
+    #[test]
+    fn it_accepts_the_metadata_of_the_fixture() {
+        assert!(validate_metadata(include_str!("fixtures/idp_metadata.xml")).is_ok());
+    }
+
+    #[test]
+    fn it_sends_the_user_to_the_provider() {
+        let (request_id, url) = provider().authentication_request(APP_URL, "relay").unwrap();
+
+        assert!(!request_id.is_empty());
+        assert!(url.starts_with("https://idp.example.com/sso?SAMLRequest="));
+        assert!(url.contains("RelayState=relay"));
+    }
+
+    #[test]
+    fn it_reads_the_identity_of_a_signed_response() {
+        let identity = identity(&signed(&response_xml(REQUEST_ID), IDP_KEY)).unwrap();
+
+        assert_eq!(identity.provider, "saml:acme:okta");
+        assert_eq!(identity.subject, "jane@example.org");
+        assert_eq!(identity.email.as_deref(), Some("jane@example.org"));
+        assert_eq!(identity.username.as_deref(), Some("jane.doe"));
+    }
+
+    #[test]
+    fn it_reads_the_attributes_the_provider_is_set_up_with() {
+        let provider = SamlProvider {
+            username_attribute: Some("mail".to_string()),
+            ..provider()
+        };
+        let xml = signed(&response_xml(REQUEST_ID), IDP_KEY);
+
+        let identity = provider
+            .identity(APP_URL, &STANDARD.encode(xml), REQUEST_ID)
+            .unwrap();
+
+        assert_eq!(identity.username.as_deref(), Some("jane@example.org"));
+    }
+
+    #[test]
+    fn it_refuses_an_unsigned_response() {
+        assert!(identity(&response_xml(REQUEST_ID)).is_err());
+    }
+
+    #[test]
+    fn it_refuses_a_response_signed_with_another_key() {
+        assert!(identity(&signed(&response_xml(REQUEST_ID), OTHER_KEY)).is_err());
+    }
+
+    #[test]
+    fn it_refuses_a_tampered_response() {
+        let xml = signed(&response_xml(REQUEST_ID), IDP_KEY).replace("jane.doe", "admin");
+
+        assert!(identity(&xml).is_err());
+    }
+
+    #[test]
+    fn it_refuses_a_response_to_another_request() {
+        assert!(identity(&signed(&response_xml("_other"), IDP_KEY)).is_err());
+    }
+
+    #[test]
+    fn it_refuses_a_response_for_another_provider() {
+        let provider = SamlProvider {
+            slug: "azure".to_string(),
+            ..provider()
+        };
+        let xml = signed(&response_xml(REQUEST_ID), IDP_KEY);
+
+        assert!(provider
+            .identity(APP_URL, &STANDARD.encode(xml), REQUEST_ID)
+            .is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::{collections::HashMap, sync::Arc};
+
+use super::{
+    app, authentication::register::hash_password, credentials::DatabaseCredentials, jwt::JwtKeys,
+    mailer::Mailer, redis_pool, session_store, AppContext, SecurityHeadersConfig, SessionLimits,
+};
+use axum::{
+    body::Body,
+    http::{
+        header::{COOKIE, SET_COOKIE},
+        request::Builder,
+        Request,
+    },
+    response::Response,
+    Router,
+};
+use serde_json::Value;
+use sqlx::MySqlPool;
+use tower::ServiceExt;
+
+pub const APP_URL: &str = "https://auth.example.com";
+
+/// The context of the application, its tokens signed with
+/// the key of `fixtures/jwt`. Sessions and the user cache
+/// need the redis of `scripts/init_redis.sh`.
+pub fn context(db: MySqlPool) -> AppContext {
+    let keys = concat!(env!("CARGO_MANIFEST_DIR"), "/src/http/fixtures/jwt");
+
+    return AppContext {
+        credentials: Arc::new(DatabaseCredentials::new(db.clone())),
+        db,
+        redis: redis_pool(),
+        jwt: Arc::new(JwtKeys::load(keys, APP_URL, None).unwrap()),
+        mailer: Arc::new(Mailer::log(APP_URL)),
+        session_limits: SessionLimits::from_env().unwrap(),
+    };
+}
+
+/// The whole application, behind the same middleware as
+/// when it is served.
+pub async fn app_with(context: AppContext) -> Router {
+    let session_store = session_store(&context.redis, &context.session_limits).await;
+
+    return app(context, session_store, SecurityHeadersConfig::default());
+}
+
+pub async fn create_user(db: &MySqlPool, username: &str, password: &str) -> u32 {
+    return sqlx::query!(
+        "insert into users (username, email, password) values (?, ?, ?)",
+        username,
+        format!("{}@example.org", username),
+        hash_password(password).unwrap()
+    )
+    .execute(db)
+    .await
+    .unwrap()
+    .last_insert_id() as u32;
+}
+
+/// Sends the requests with the cookies the application set
+/// on the previous responses, as a browser does.
+pub struct Browser {
+    app: Router,
+    cookies: HashMap<String, String>,
+}
+
+impl Browser {
+    pub fn new(app: Router) -> Self {
+        return Self {
+            app,
+            cookies: HashMap::new(),
+        };
+    }
+
+    pub async fn send(&mut self, request: Builder, body: impl Into<Body>) -> Response {
+        let cookie = self
+            .cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        let response = self
+            .app
+            .clone()
+            .oneshot(request.header(COOKIE, cookie).body(body.into()).unwrap())
+            .await
+            .unwrap();
+
+        for header in response.headers().get_all(SET_COOKIE) {
+            let pair = header.to_str().unwrap().split(';').next().unwrap();
+            if let Some((name, value)) = pair.split_once('=') {
+                self.cookies.insert(name.to_string(), value.to_string());
+            }
+        }
+
+        return response;
+    }
+
+    /// The CSRF token of the session, as handed to the clients
+    /// of the JSON API.
+    pub async fn csrf_token(&mut self) -> String {
+        let response = self
+            .send(Request::get("/api/v1/csrf-token"), Body::empty())
+            .await;
+
+        return body_json(response).await["csrf_token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+    }
+}
+
+pub async fn body_text(response: Response) -> String {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+    return String::from_utf8(body.to_vec()).unwrap();
+}
+
+pub async fn body_json(response: Response) -> Value {
+    return serde_json::from_str(&body_text(response).await).unwrap();
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use super::{error::ApplicationError, middleware::User};
+use redis::AsyncCommands;
+use redis_pool::SingleRedisPool;
+use sqlx::MySqlPool;
+
+// Cached users are dropped after a day so a missed
+// invalidation can not serve stale data forever.
+const TTL_SECONDS: usize = 60 * 60 * 24;
+
+fn key(user_id: u32) -> String {
+    return format!("users:{}", user_id);
+}
+
+/// Find the user by id, looking into the Redis cache
+/// first and falling back to the database. A user found
+/// in the database is written back into the cache. Deleted
+/// accounts are not found until they are restored.
+pub async fn find(
+    redis: &SingleRedisPool,
+    db: &MySqlPool,
+    user_id: u32,
+) -> Result<Option<User>, ApplicationError> {
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let cached: HashMap<String, String> = connection
+        .hgetall(key(user_id))
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    if let (Some(username), Some(email)) = (cached.get("username"), cached.get("email")) {
+        return Ok(Some(User {
+            id: user_id,
+            username: username.clone(),
+            email: email.clone(),
+        }));
+    }
+
+    let record = sqlx::query!(
+        "select id, username, email from users where id = ? and deleted_at is null",
+        user_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    let user = User {
+        id: record.id,
+        username: record.username,
+        email: record.email,
+    };
+
+    redis::pipe()
+        .hset_multiple(
+            key(user.id),
+            &[("username", &user.username), ("email", &user.email)],
+        )
+        .expire(key(user.id), TTL_SECONDS)
+        .query_async::<_, ()>(&mut *connection)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(Some(user));
+}
+
+/// Remove the cached copy of the user. This must be called
+/// whenever the profile or the password of the user changes.
+pub async fn forget(redis: &SingleRedisPool, user_id: u32) -> Result<(), ApplicationError> {
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    connection
+        .del::<_, ()>(key(user_id))
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
@@ -0,0 +1,387 @@
+use std::{collections::HashMap, env, net::IpAddr};
+
+use super::{
+    authentication::logout::log_out, error::ApplicationError, flash::Flash, utils::random_token,
+};
+use axum_session::{Session, SessionRedisPool};
+use redis::AsyncCommands;
+use redis_pool::SingleRedisPool;
+use time::{Duration, OffsetDateTime};
+
+/// The key of the session holding the id of its entry in
+/// our index. The id of the session itself changes each time
+/// it is renewed, and the store does not tell which sessions
+/// belong to a user.
+pub const SESSION_KEY: &str = "_sid";
+
+/// How often the last-seen time is written, so not every
+/// request costs a write.
+const TOUCH_INTERVAL_SECONDS: i64 = 60;
+
+fn key(id: &str) -> String {
+    return format!("user_session:{}", id);
+}
+
+fn user_key(user_id: u32) -> String {
+    return format!("user_sessions:{}", user_id);
+}
+
+/// How long the logged-in sessions last, and how many of
+/// them a user may have at once.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionLimits {
+    /// Log out the sessions without a request for this long.
+    pub idle_timeout: Duration,
+    /// Log out the sessions this long after they logged in,
+    /// however active they are.
+    pub lifetime: Duration,
+    /// Log out the oldest sessions of a user who logs in on
+    /// more devices than this.
+    pub max_per_user: Option<usize>,
+}
+
+impl SessionLimits {
+    /// Read the limits from `SESSION_IDLE_MINUTES` (6 hours by
+    /// default), `SESSION_LIFETIME_HOURS` (a day by default)
+    /// and `SESSION_MAX_PER_USER` (no limit by default).
+    pub fn from_env() -> Result<Self, String> {
+        let number = |name: &str| -> Result<Option<i64>, String> {
+            return match env::var(name) {
+                Ok(value) => match value.parse::<i64>() {
+                    Ok(number) if number > 0 => Ok(Some(number)),
+                    _ => Err(format!("{} must be a positive number", name)),
+                },
+                Err(_) => Ok(None),
+            };
+        };
+
+        return Ok(Self {
+            idle_timeout: Duration::minutes(number("SESSION_IDLE_MINUTES")?.unwrap_or(6 * 60)),
+            lifetime: Duration::hours(number("SESSION_LIFETIME_HOURS")?.unwrap_or(24)),
+            max_per_user: number("SESSION_MAX_PER_USER")?.map(|max| max as usize),
+        });
+    }
+
+    /// How long the sessions are stored. They outlive the
+    /// limits by a day, so users coming back later are told
+    /// their session expired instead of silently finding
+    /// themselves logged out.
+    pub fn retention(&self) -> Duration {
+        return self.lifetime + Duration::days(1);
+    }
+
+    fn has_expired(&self, created_at: i64, last_seen_at: i64, now: i64) -> bool {
+        return now - last_seen_at >= self.idle_timeout.whole_seconds()
+            || now - created_at >= self.lifetime.whole_seconds();
+    }
+}
+
+/// A logged-in session of the user, on one of their devices.
+pub struct UserSession {
+    pub id: String,
+    pub device: String,
+    pub ip: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub last_seen_at: OffsetDateTime,
+}
+
+/// The id of the entry of the session, if it is logged in.
+pub fn current_id(session: &Session<SessionRedisPool>) -> Option<String> {
+    return session.get::<String>(SESSION_KEY);
+}
+
+/// Log the session out if it has been revoked in the
+/// meantime or has expired, otherwise record that it was seen.
+pub async fn verify(
+    redis: &SingleRedisPool,
+    limits: &SessionLimits,
+    session: &Session<SessionRedisPool>,
+    ip: Option<IpAddr>,
+) -> Result<(), ApplicationError> {
+    let Some(id) = current_id(session) else {
+        return Ok(());
+    };
+
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let entry: HashMap<String, String> = connection
+        .hgetall(key(&id))
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let timestamp = |name: &str| entry.get(name).and_then(|v| v.parse::<i64>().ok());
+
+    let (Some(created_at), Some(last_seen_at)) =
+        (timestamp("created_at"), timestamp("last_seen_at"))
+    else {
+        log_out(session);
+        return Ok(());
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    if limits.has_expired(created_at, last_seen_at, now) {
+        forget(redis, &id).await?;
+        log_out(session);
+        Flash::new(session.clone()).info("Your session has expired, please log in again.");
+        return Ok(());
+    }
+
+    if now - last_seen_at < TOUCH_INTERVAL_SECONDS {
+        return Ok(());
+    }
+
+    let ip = ip.map(|ip| ip.to_string()).unwrap_or_default();
+
+    connection
+        .hset_multiple::<_, _, _, ()>(key(&id), &[("last_seen_at", now.to_string()), ("ip", ip)])
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+/// Keep the index in line with what the request did to the
+/// session: add the sessions that just logged in, and drop
+/// the entry of the sessions that logged out.
+pub async fn sync(
+    redis: &SingleRedisPool,
+    limits: &SessionLimits,
+    session: &Session<SessionRedisPool>,
+    id_before: Option<String>,
+    user_agent: &str,
+    ip: Option<IpAddr>,
+) -> Result<(), ApplicationError> {
+    let id_after = current_id(session);
+
+    if let Some(id) = id_before.filter(|id| Some(id) != id_after.as_ref()) {
+        forget(redis, &id).await?;
+    }
+
+    let Some(user_id) = session.get::<u32>("user_id").filter(|_| id_after.is_none()) else {
+        return Ok(());
+    };
+
+    let id = random_token(16);
+    let now = OffsetDateTime::now_utc().unix_timestamp().to_string();
+    let retention = limits.retention().whole_seconds() as usize;
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    redis::pipe()
+        .hset_multiple(
+            key(&id),
+            &[
+                ("user_id", user_id.to_string()),
+                ("user_agent", user_agent.to_string()),
+                ("ip", ip.map(|ip| ip.to_string()).unwrap_or_default()),
+                ("created_at", now.clone()),
+                ("last_seen_at", now),
+            ],
+        )
+        .expire(key(&id), retention)
+        .sadd(user_key(user_id), &id)
+        .expire(user_key(user_id), retention)
+        .query_async::<_, ()>(&mut *connection)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    drop(connection);
+    session.set(SESSION_KEY, &id);
+
+    // Beyond the limit, the sessions that logged in first
+    // make room for the new one.
+    if let Some(max) = limits.max_per_user {
+        let mut sessions = list(redis, limits, user_id).await?;
+        sessions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        for session in sessions
+            .iter()
+            .filter(|session| session.id != id)
+            .skip(max - 1)
+        {
+            forget(redis, &session.id).await?;
+        }
+    }
+
+    return Ok(());
+}
+
+/// Drop the entry, which logs out the session it belongs to
+/// on its next request.
+async fn forget(redis: &SingleRedisPool, id: &str) -> Result<(), ApplicationError> {
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let user_id: Option<u32> = connection
+        .hget(key(id), "user_id")
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let mut pipe = redis::pipe();
+    pipe.del(key(id));
+    if let Some(user_id) = user_id {
+        pipe.srem(user_key(user_id), id);
+    }
+
+    pipe.query_async::<_, ()>(&mut *connection)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    return Ok(());
+}
+
+/// The sessions of the user, the most recently seen first.
+pub async fn list(
+    redis: &SingleRedisPool,
+    limits: &SessionLimits,
+    user_id: u32,
+) -> Result<Vec<UserSession>, ApplicationError> {
+    let mut connection = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let ids: Vec<String> = connection
+        .smembers(user_key(user_id))
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let mut sessions = vec![];
+    for id in ids {
+        let entry: HashMap<String, String> = connection
+            .hgetall(key(&id))
+            .await
+            .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+        let timestamp = |name: &str| {
+            entry
+                .get(name)
+                .and_then(|v| v.parse::<i64>().ok())
+                .and_then(|v| OffsetDateTime::from_unix_timestamp(v).ok())
+        };
+
+        // The entry has been dropped by Redis, or is only kept
+        // to tell its session why it was logged out.
+        let (created_at, last_seen_at) = match (timestamp("created_at"), timestamp("last_seen_at"))
+        {
+            (Some(created_at), Some(last_seen_at))
+                if !limits.has_expired(
+                    created_at.unix_timestamp(),
+                    last_seen_at.unix_timestamp(),
+                    now,
+                ) =>
+            {
+                (created_at, last_seen_at)
+            }
+            _ => {
+                connection
+                    .srem::<_, _, ()>(user_key(user_id), &id)
+                    .await
+                    .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+                continue;
+            }
+        };
+
+        sessions.push(UserSession {
+            device: device(entry.get("user_agent").map(String::as_str).unwrap_or("")),
+            ip: entry.get("ip").filter(|ip| !ip.is_empty()).cloned(),
+            id,
+            created_at,
+            last_seen_at,
+        });
+    }
+
+    sessions.sort_by(|a, b| b.last_seen_at.cmp(&a.last_seen_at));
+
+    return Ok(sessions);
+}
+
+/// Revoke one of the sessions of the user.
+pub async fn revoke(
+    redis: &SingleRedisPool,
+    user_id: u32,
+    id: &str,
+) -> Result<bool, ApplicationError> {
+    let belongs_to_user: bool = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+        .sismember(user_key(user_id), id)
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    if belongs_to_user {
+        forget(redis, id).await?;
+    }
+
+    return Ok(belongs_to_user);
+}
+
+/// Revoke the sessions of the user but the given one, e.g.
+/// the one changing the password.
+pub async fn revoke_others(
+    redis: &SingleRedisPool,
+    user_id: u32,
+    except: Option<&str>,
+) -> Result<(), ApplicationError> {
+    let ids: Vec<String> = redis
+        .aquire()
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?
+        .smembers(user_key(user_id))
+        .await
+        .map_err(|e| ApplicationError::ServerError(e.to_string()))?;
+
+    for id in ids {
+        if Some(id.as_str()) != except {
+            forget(redis, &id).await?;
+        }
+    }
+
+    return Ok(());
+}
+
+/// A short description of the device, e.g. "Firefox on
+/// Linux", read from the user agent.
+pub fn device(user_agent: &str) -> String {
+    // The order matters: Edge and Opera also claim to be
+    // Chrome, which claims to be Safari.
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+    ]
+    .iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| *name);
+
+    let system = [
+        ("Windows", "Windows"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Android", "Android"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ]
+    .iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| *name);
+
+    return match (browser, system) {
+        (Some(browser), Some(system)) => format!("{} on {}", browser, system),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => "Unknown device".to_string(),
+    };
+}
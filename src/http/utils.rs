@@ -1,4 +1,7 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
 
 pub fn deserialize_empty_string_as_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
@@ -11,3 +14,35 @@ where
         _ => Ok(None),
     };
 }
+
+/// Generate a url-safe random token from the given
+/// amount of bytes taken from the OS random source.
+pub fn random_token(bytes: usize) -> String {
+    let mut buffer = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buffer);
+
+    return URL_SAFE_NO_PAD.encode(buffer);
+}
+
+/// Compare two strings in a time that does not depend
+/// on where they differ, to be used on secrets.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    return a
+        .bytes()
+        .zip(b.bytes())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0;
+}
+
+/// The hex encoded SHA-256 of the value. Tokens handed out to
+/// users are only stored as this hash.
+pub fn sha256_hex(value: &str) -> String {
+    return Sha256::digest(value.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+}
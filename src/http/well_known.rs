@@ -0,0 +1,54 @@
+use super::{
+    oauth::{GRANT_TYPES, SCOPES},
+    AppContext,
+};
+use axum::{extract::State, response::IntoResponse, routing::get, Json, Router};
+use serde_json::json;
+
+pub fn router() -> Router<AppContext> {
+    return Router::new()
+        .route("/.well-known/jwks.json", get(jwks))
+        .route(
+            "/.well-known/openid-configuration",
+            get(openid_configuration),
+        );
+}
+
+/// The public keys the tokens we issue can be verified with.
+async fn jwks(State(AppContext { jwt, .. }): State<AppContext>) -> impl IntoResponse {
+    return (
+        [("Cache-Control", "public, max-age=3600")],
+        Json(jwt.jwks()),
+    );
+}
+
+/// The OpenID Connect discovery document, from which the
+/// client libraries configure themselves.
+async fn openid_configuration(
+    State(AppContext { jwt, .. }): State<AppContext>,
+) -> impl IntoResponse {
+    let issuer = jwt.issuer();
+    let endpoint = |path: &str| format!("{}{}", issuer, path);
+
+    return (
+        [("Cache-Control", "public, max-age=3600")],
+        Json(json!({
+            "issuer": issuer,
+            "authorization_endpoint": endpoint("/oauth/authorize"),
+            "token_endpoint": endpoint("/oauth/token"),
+            "userinfo_endpoint": endpoint("/oauth/userinfo"),
+            "jwks_uri": endpoint("/.well-known/jwks.json"),
+            "end_session_endpoint": endpoint("/oauth/logout"),
+            "revocation_endpoint": endpoint("/oauth/revoke"),
+            "introspection_endpoint": endpoint("/oauth/introspect"),
+            "scopes_supported": SCOPES.map(|(scope, _)| scope),
+            "response_types_supported": ["code"],
+            "grant_types_supported": GRANT_TYPES,
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": jwt.algorithms(),
+            "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+            "code_challenge_methods_supported": ["S256", "plain"],
+            "claims_supported": ["sub", "iss", "aud", "exp", "iat", "nonce", "preferred_username", "email", "email_verified"],
+        })),
+    );
+}
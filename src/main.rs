@@ -1,8 +1,14 @@
-use hands_on_maud::http;
+use clap::Parser;
+use hands_on_maud::{
+    cli::{self, Cli, Command},
+    http,
+};
 use sqlx::{migrate, mysql::MySqlPoolOptions};
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
     // Setup Mysql pool connections
     // for the applcation.
     let db = MySqlPoolOptions::new()
@@ -19,5 +25,16 @@ async fn main() {
     // it is safe.
     migrate!().run(&db).await.unwrap();
 
-    http::server(db).await;
+    // Without a command, we will start the
+    // web server. Other commands run once
+    // and exit.
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => http::server(db).await,
+        command => {
+            if let Err(e) = cli::run(&db, command).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 }
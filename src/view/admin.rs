@@ -0,0 +1,148 @@
+use super::format::datetime;
+use super::input::csrf_field;
+use super::layout::layout;
+use crate::http::{AuditRecord, Role, UserRoles};
+use maud::{html, Markup};
+
+/// The values of the filter form, as they were submitted.
+pub struct AuditFilterForm<'a> {
+    pub event: &'a str,
+    pub outcome: &'a str,
+    pub user: &'a str,
+    pub ip: &'a str,
+    pub since: &'a str,
+    pub until: &'a str,
+}
+
+pub fn audit_page(
+    records: &[AuditRecord],
+    form: &AuditFilterForm,
+    older: Option<&str>,
+    export: &str,
+) -> Markup {
+    return layout(
+        "Audit log",
+        html! {
+            main class="card shadow-md bg-white w-[64rem] max-w-full my-10" {
+                div class="card-body" {
+                    h1 class="card-title text-2xl" { "Audit log" }
+
+                    form class="flex flex-wrap items-end gap-2" method="get" action="/admin/audit" {
+                        input type="text" name="event" placeholder="Event" value=(form.event) class="input input-bordered input-sm bg-white";
+                        select name="outcome" class="select select-bordered select-sm bg-white" {
+                            option value="" selected[form.outcome.is_empty()] { "Any outcome" }
+                            option value="success" selected[form.outcome == "success"] { "Success" }
+                            option value="failure" selected[form.outcome == "failure"] { "Failure" }
+                        }
+                        input type="text" name="user" placeholder="Username" value=(form.user) class="input input-bordered input-sm bg-white";
+                        input type="text" name="ip" placeholder="IP address" value=(form.ip) class="input input-bordered input-sm bg-white";
+                        input type="date" name="since" value=(form.since) class="input input-bordered input-sm bg-white";
+                        input type="date" name="until" value=(form.until) class="input input-bordered input-sm bg-white";
+                        button type="submit" class="btn btn-primary btn-sm text-white" { "Filter" }
+                        a href=(export) class="btn btn-ghost btn-sm" hx-boost="false" { "Export JSON Lines" }
+                    }
+
+                    @if records.is_empty() {
+                        p class="text-gray-500 my-2" { "No events match the filter." }
+                    } @else {
+                        table class="table table-sm" {
+                            thead {
+                                tr { th { "Time" } th { "Event" } th { "User" } th { "IP address" } th { "Details" } }
+                            }
+                            tbody {
+                                @for record in records {
+                                    tr {
+                                        td class="whitespace-nowrap" { (datetime(&record.created_at)) }
+                                        td {
+                                            code { (record.event) }
+                                            " "
+                                            @if record.outcome == "success" {
+                                                span class="badge badge-success badge-sm" { (record.outcome) }
+                                            } @else {
+                                                span class="badge badge-error badge-sm" { (record.outcome) }
+                                            }
+                                        }
+                                        td { (record.username.as_deref().unwrap_or("")) }
+                                        td { (record.ip.as_deref().unwrap_or("")) }
+                                        td class="text-gray-500 break-all" {
+                                            @if let Some(metadata) = &record.metadata {
+                                                code { (metadata) }
+                                            }
+                                            @if let Some(user_agent) = &record.user_agent {
+                                                br;
+                                                span class="text-xs" { (user_agent) }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    @if let Some(older) = older {
+                        div class="flex justify-end mt-4" {
+                            a href=(older) class="btn btn-ghost btn-sm" { "Older events" }
+                        }
+                    }
+                }
+            }
+        },
+        None,
+    );
+}
+
+pub fn users_page(users: &[UserRoles], roles: &[Role], search: &str, current_id: u32) -> Markup {
+    return layout(
+        "Users",
+        html! {
+            main class="card shadow-md bg-white w-[64rem] max-w-full my-10" {
+                div class="card-body" {
+                    h1 class="card-title text-2xl" { "Users" }
+
+                    form class="flex items-end gap-2" method="get" action="/admin/users" {
+                        input type="text" name="search" placeholder="Username" value=(search) class="input input-bordered input-sm bg-white";
+                        button type="submit" class="btn btn-primary btn-sm text-white" { "Search" }
+                    }
+
+                    @if users.is_empty() {
+                        p class="text-gray-500 my-2" { "No users match the search." }
+                    } @else {
+                        table class="table table-sm" {
+                            thead {
+                                tr { th { "Username" } th { "Email" } th { "Roles" } }
+                            }
+                            tbody {
+                                @for user in users {
+                                    tr {
+                                        td { (user.username) }
+                                        td { (user.email) }
+                                        td {
+                                            @if user.id == current_id {
+                                                (user.roles.join(", "))
+                                                " "
+                                                span class="badge badge-primary badge-sm" { "You" }
+                                            } @else {
+                                                form class="flex flex-wrap items-center gap-2" method="post" action={ "/admin/users/"(user.id)"/roles" } {
+                                                    (csrf_field())
+                                                    @for role in roles {
+                                                        label class="label cursor-pointer gap-1" title=(role.description) {
+                                                            input type="checkbox" class="checkbox checkbox-sm" name="roles" value=(role.id)
+                                                                checked[user.roles.iter().any(|name| *name == role.name)];
+                                                            span { (role.name) }
+                                                        }
+                                                    }
+                                                    button type="submit" class="btn btn-ghost btn-sm" { "Save" }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        None,
+    );
+}
@@ -0,0 +1,35 @@
+use crate::http::{csp_nonce, find_asset};
+use maud::{html, Markup};
+
+pub use crate::http::asset;
+
+/// Third-party scripts served from `public/js/vendor`. They
+/// are copied there from `node_modules` by the build script.
+const VENDOR_SCRIPTS: [&str; 3] = [
+    "js/vendor/htmx.min.js",
+    "js/vendor/morphdom-swap.js",
+    "js/vendor/morphdom-umd.min.js",
+];
+
+pub fn script(path: &str) -> Markup {
+    return html! {
+        script src=(asset(path))
+                integrity=[find_asset(path).map(|asset| asset.integrity)]
+                nonce=(csp_nonce()) {}
+    };
+}
+
+pub fn stylesheet(path: &str) -> Markup {
+    return html! {
+        link rel="stylesheet" href=(asset(path))
+                integrity=[find_asset(path).map(|asset| asset.integrity)];
+    };
+}
+
+pub fn vendor_scripts() -> Markup {
+    return html! {
+        @for path in VENDOR_SCRIPTS {
+            (script(path))
+        }
+    };
+}
@@ -1,5 +1,9 @@
+use super::assets::{stylesheet, vendor_scripts};
+use super::flash::flash_messages;
+use super::format::datetime;
 use super::input::OnChangeValidation;
-use super::input::{Input, InputKind};
+use super::input::{csrf_field, Input, InputKind};
+use crate::http::{csp_nonce, csrf_token, sso_providers, LoginAlert};
 use crate::LoginAttempRequest;
 use crate::{ErrorBag, RegisterRequest};
 use maud::{html, Markup, DOCTYPE};
@@ -12,14 +16,14 @@ pub fn layout(title: &str, body: Markup) -> Markup {
                 meta charset="utf-8";
                 meta name="viewport" content="width=device-width";
                 title { (title) }
-                link rel="stylesheet" href="/public/css/app.css";
-                script src="https://unpkg.com/htmx.org@1.9.6" {}
-                script src="https://unpkg.com/htmx.org/dist/ext/morphdom-swap.js" {}
-                script src="https://cdn.jsdelivr.net/npm/morphdom@2.6.1/dist/morphdom-umd.js" {}
+                meta name="htmx-config" content=(htmx_config());
+                (stylesheet("css/app.css"))
+                (vendor_scripts())
             }
-            body hx-ext="morphdom-swap" hx-boost="true" {
+            body hx-ext="morphdom-swap" hx-boost="true" hx-headers=(csrf_headers()) {
                 main class="h-[100dvh] bg-blue-50 overflow-auto" {
                     div class="card shadow-md bg-white w-96 m-auto top-20" {
+                        (flash_messages())
                         (body)
                     }
                 }
@@ -28,10 +32,33 @@ pub fn layout(title: &str, body: Markup) -> Markup {
     };
 }
 
+/// The htmx headers sending the CSRF token along with
+/// every request made from the page.
+pub fn csrf_headers() -> String {
+    return format!(r#"{{"X-CSRF-Token": "{}"}}"#, csrf_token());
+}
+
+/// htmx must not inject its indicator styles, which the CSP
+/// would block, and has to tag the scripts it evaluates
+/// with the nonce of the page.
+pub fn htmx_config() -> String {
+    return format!(
+        r#"{{"includeIndicatorStyles": false, "inlineScriptNonce": "{}"}}"#,
+        csp_nonce()
+    );
+}
+
 pub async fn register_page() -> Markup {
+    return register_page_with_errors(None, None);
+}
+
+pub fn register_page_with_errors(
+    request: Option<&RegisterRequest>,
+    errors: Option<&ErrorBag>,
+) -> Markup {
     return html! {
         (layout("Register", html! {
-            (register_form(None, None))
+            (register_form(request, errors))
         }))
     };
 }
@@ -63,8 +90,9 @@ pub fn register_form(request: Option<&RegisterRequest>, errors: Option<&ErrorBag
     }
 
     return html! {
-        form class="card-body" hx-post="/register" hx-swap="outerHTML" novalidate {
+        form class="card-body" action="/register" method="post" hx-post="/register" hx-swap="outerHTML" novalidate {
             h1 class="card-title text-center text-2xl" { "Register" }
+            (csrf_field())
             (username_input)
             (email_input)
             (password_input)
@@ -80,18 +108,39 @@ pub fn register_form(request: Option<&RegisterRequest>, errors: Option<&ErrorBag
     };
 }
 
-pub fn login_page(successfully_registered: bool) -> Markup {
+pub async fn login_page() -> Markup {
+    return login_page_with_errors(None, None);
+}
+
+pub fn login_page_with_errors(
+    request: Option<&LoginAttempRequest>,
+    errors: Option<&ErrorBag>,
+) -> Markup {
     return html! {
         (layout("Login", html! {
             div {
-                @if successfully_registered {
-                    div class="alert alert-success" {
-                        "Your account has been created!. Now try to login with the registered information."
+                (login_form(request, errors))
+            }
+            (provider_buttons())
+        }))
+    };
+}
+
+/// A button per identity provider users can sign in with.
+fn provider_buttons() -> Markup {
+    let providers = sso_providers();
+
+    return html! {
+        @if !providers.is_empty() {
+            div class="card-body pt-0 gap-2" {
+                div class="divider" { "or" }
+                @for (id, name) in providers {
+                    a href={ "/login/" (id) } class="btn btn-outline" hx-boost="false" {
+                        "Sign in with " (name)
                     }
                 }
-                (login_form(None, None))
             }
-        }))
+        }
     };
 }
 
@@ -122,8 +171,9 @@ pub fn login_form(request: Option<&LoginAttempRequest>, errors: Option<&ErrorBag
                 (e[0])
             }
         }
-        form class="card-body" hx-post="/login" hx-swap="innerHTML" hx-target="closest div" novalidate {
+        form class="card-body" action="/login" method="post" hx-post="/login" hx-swap="innerHTML" hx-target="closest div" novalidate {
             h1 class="card-title text-center text-2xl" { "Login" }
+            (csrf_field())
             (username_input)
             (password_input)
             div class="flex justify-end items-center gap-4 my-4" {
@@ -136,3 +186,91 @@ pub fn login_form(request: Option<&LoginAttempRequest>, errors: Option<&ErrorBag
         }
     };
 }
+
+/// Ask the logged-in user for their password again before a
+/// sensitive action.
+pub fn confirm_password_page(error: Option<&str>) -> Markup {
+    let errors = error.map(|error| vec![error.to_string()]);
+    let password_input = Input::new("Password", "password")
+        .kind(InputKind::Password)
+        .errors(errors.as_ref());
+
+    return layout(
+        "Confirm password",
+        html! {
+            form class="card-body" action="/confirm-password" method="post" novalidate {
+                h1 class="card-title text-center text-2xl" { "Confirm your password" }
+                p { "This is a sensitive action, please enter your password to continue." }
+                (csrf_field())
+                (password_input)
+                div class="flex justify-end items-center gap-4 mt-4" {
+                    a href="/settings/account" class="underline" { "Cancel" }
+                    button type="submit" class="btn btn-primary text-white" { "Confirm" }
+                }
+            }
+            @if !sso_providers().is_empty() {
+                p class="card-body pt-0 text-sm" {
+                    "Signed up with another provider? Log out and sign in with it again instead."
+                }
+            }
+        },
+    );
+}
+
+pub fn login_alert_page(token: &str, alert: &LoginAlert, errors: Option<&ErrorBag>) -> Markup {
+    let password_input = Input::new("New password", "password")
+        .kind(InputKind::Password)
+        .errors(errors.and_then(|e| e.get("password")));
+    let password_confirmation_input =
+        Input::new("New password confirmation", "password_confirmation")
+            .kind(InputKind::Password)
+            .errors(errors.and_then(|e| e.get("password_confirmation")));
+
+    return layout(
+        "Secure your account",
+        html! {
+            form class="card-body" action={ "/login-alerts/"(token) } method="post" novalidate {
+                h1 class="card-title text-center text-2xl" { "Secure your account" }
+                p {
+                    "Your account was logged in to from "
+                    strong { (alert.device) }
+                    @if let Some(ip_range) = &alert.ip_range {
+                        " (" (ip_range) ")"
+                    }
+                    " on " (datetime(&alert.first_seen_at)) "."
+                }
+                p {
+                    "If it was not you, choose a new password. You will be logged out everywhere "
+                    "and your API tokens will be revoked."
+                }
+                (csrf_field())
+                (password_input)
+                (password_confirmation_input)
+                div class="flex justify-end items-center gap-4 mt-4" {
+                    a href="/login" class="underline" { "It was me" }
+                    button type="submit" class="btn btn-primary text-white" { "Change password" }
+                }
+            }
+        },
+    );
+}
+
+/// Ask the owner of the new address to confirm the change,
+/// the link of the email only leads here.
+pub fn confirm_email_page(token: &str, email: &str) -> Markup {
+    return layout(
+        "Confirm your email address",
+        html! {
+            form class="card-body" action={ "/settings/account/email/confirm/"(token) } method="post" {
+                h1 class="card-title text-center text-2xl" { "Confirm your email address" }
+                p {
+                    "Use " strong { (email) } " as the email address of your account?"
+                }
+                (csrf_field())
+                div class="flex justify-end items-center gap-4 mt-4" {
+                    button type="submit" class="btn btn-primary text-white" { "Confirm" }
+                }
+            }
+        },
+    );
+}
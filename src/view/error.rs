@@ -0,0 +1,32 @@
+use super::authentication::layout;
+use maud::{html, Markup};
+
+pub fn page_expired() -> Markup {
+    return layout(
+        "Page Expired",
+        html! {
+            div class="card-body" {
+                h1 class="card-title text-center text-2xl" { "Page Expired" }
+                p { "Your form has expired or was sent from another site. Please reload the page and try again." }
+                div class="flex justify-end mt-4" {
+                    a href="/login" class="btn btn-primary text-white" { "Back to login" }
+                }
+            }
+        },
+    );
+}
+
+pub fn forbidden() -> Markup {
+    return layout(
+        "Forbidden",
+        html! {
+            div class="card-body" {
+                h1 class="card-title text-center text-2xl" { "Forbidden" }
+                p { "You are not allowed to see this page. Ask an administrator if you need access to it." }
+                div class="flex justify-end mt-4" {
+                    a href="/home" class="btn btn-primary text-white" { "Back home" }
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,41 @@
+use crate::http::{take_flashes, FlashLevel, FlashMessage};
+use maud::{html, Markup};
+
+fn alert_class(level: FlashLevel) -> &'static str {
+    return match level {
+        FlashLevel::Success => "alert alert-success",
+        FlashLevel::Info => "alert alert-info",
+        FlashLevel::Warning => "alert alert-warning",
+        FlashLevel::Error => "alert alert-error",
+    };
+}
+
+fn messages(messages: &[FlashMessage]) -> Markup {
+    return html! {
+        @for message in messages {
+            div class=(alert_class(message.level)) role="alert" {
+                (message.text)
+            }
+        }
+    };
+}
+
+/// The container of the flash messages, filled with the
+/// messages pending for the current request.
+pub fn flash_messages() -> Markup {
+    return html! {
+        div id="flashes" {
+            (messages(&take_flashes()))
+        }
+    };
+}
+
+/// The given messages as an out-of-band swap of the
+/// container, for htmx responses that are fragments.
+pub fn flash_messages_oob(pending: Vec<FlashMessage>) -> Markup {
+    return html! {
+        div id="flashes" hx-swap-oob="true" {
+            (messages(&pending))
+        }
+    };
+}
@@ -0,0 +1,11 @@
+use time::{macros::format_description, OffsetDateTime};
+
+pub fn datetime(value: &OffsetDateTime) -> String {
+    return value
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute] UTC"))
+        .unwrap_or_default();
+}
+
+pub fn optional_datetime(value: Option<&OffsetDateTime>, fallback: &str) -> String {
+    return value.map(datetime).unwrap_or(fallback.to_string());
+}
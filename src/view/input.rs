@@ -1,3 +1,4 @@
+use crate::http::{csrf_token, CSRF_FIELD_NAME};
 use maud::{html, Markup, PreEscaped, Render};
 use std::fmt::Display;
 
@@ -105,3 +106,11 @@ fn error(field_name: &str, errors: &Vec<String>) -> Markup {
         }
     };
 }
+
+/// Hidden field carrying the CSRF token of the session, for
+/// forms that are submitted without htmx.
+pub fn csrf_field() -> Markup {
+    return html! {
+        input type="hidden" name=(CSRF_FIELD_NAME) value=(csrf_token());
+    };
+}
@@ -1,11 +1,15 @@
+use super::assets::{stylesheet, vendor_scripts};
+use super::authentication::{csrf_headers, htmx_config};
+use super::flash::flash_messages;
 use maud::{html, Markup, PreEscaped, DOCTYPE};
 
-fn layout(title: &str, body: Markup, script: Option<Markup>) -> Markup {
+pub fn layout(title: &str, body: Markup, script: Option<Markup>) -> Markup {
     return html! {
         (DOCTYPE)
         html data-theme="light" {
             (header(title))
-            body class="grid place-items-center h-[100dvh] bg-blue-100" {
+            body class="grid place-items-center min-h-[100dvh] bg-blue-100" hx-ext="morphdom-swap" hx-boost="true" hx-headers=(csrf_headers()) {
+                (flash_messages())
                 (body)
                 (footer())
                 (if let Some(s) = script { s } else { PreEscaped("".to_string()) })
@@ -20,7 +24,9 @@ fn header(title: &str) -> Markup {
             meta charset="utf-8";
             meta name="viewport" content="width=device-width";
             title {(title)}
-            link rel="stylesheet" href="/public/css/app.css";
+            meta name="htmx-config" content=(htmx_config());
+            (stylesheet("css/app.css"))
+            (vendor_scripts())
         }
     };
 }
@@ -1,3 +1,11 @@
+pub mod admin;
+pub mod assets;
 pub mod authentication;
+pub mod error;
+pub mod flash;
+pub mod format;
 pub mod input;
 pub mod layout;
+pub mod oauth;
+pub mod settings;
+pub mod sso;
@@ -0,0 +1,105 @@
+use super::authentication::layout;
+use super::input::csrf_field;
+use maud::{html, Markup};
+
+/// Ask the user to let the application act on their behalf.
+/// The parameters of the authorization request are carried
+/// over as hidden fields. The form must not be boosted: the
+/// response sends the browser away to the application.
+pub fn consent_page(
+    client_name: &str,
+    username: &str,
+    scopes: &[(&str, &str)],
+    fields: &[(&str, &str)],
+) -> Markup {
+    return layout(
+        "Authorize application",
+        html! {
+            form class="card-body" action="/oauth/authorize" method="post" hx-boost="false" {
+                h1 class="card-title text-center text-2xl" { "Authorize " (client_name) }
+                p {
+                    strong { (client_name) } " wants to access your account "
+                    strong { (username) } "."
+                }
+                @if !scopes.is_empty() {
+                    p { "It will be able to:" }
+                    ul class="list-disc list-inside" {
+                        @for (scope, description) in scopes {
+                            li title=(scope) { (description) }
+                        }
+                    }
+                }
+                (csrf_field())
+                @for (name, value) in fields {
+                    input type="hidden" name=(name) value=(value);
+                }
+                div class="flex justify-end items-center gap-4 mt-4" {
+                    button type="submit" name="decision" value="deny" class="btn btn-ghost" { "Deny" }
+                    button type="submit" name="decision" value="approve" class="btn btn-primary text-white" { "Authorize" }
+                }
+            }
+        },
+    );
+}
+
+/// Ask the user to confirm logging out, on behalf of the
+/// application when it is known.
+pub fn logout_page(client_name: Option<&str>, fields: &[(&str, &str)]) -> Markup {
+    return layout(
+        "Log out",
+        html! {
+            form class="card-body" action="/oauth/logout" method="post" hx-boost="false" {
+                h1 class="card-title text-center text-2xl" { "Log out" }
+                @if let Some(client_name) = client_name {
+                    p { strong { (client_name) } " asks you to log out of your account." }
+                } @else {
+                    p { "An application asks you to log out of your account." }
+                }
+                (csrf_field())
+                @for (name, value) in fields {
+                    input type="hidden" name=(name) value=(value);
+                }
+                div class="flex justify-end items-center gap-4 mt-4" {
+                    a href="/home" class="btn btn-ghost" { "Stay logged in" }
+                    button type="submit" class="btn btn-primary text-white" { "Log out" }
+                }
+            }
+        },
+    );
+}
+
+/// Send the browser back to the application. A redirect
+/// answering the consent form would be blocked by the
+/// `form-action` directive of the CSP, a refresh is not.
+pub fn redirect_page(url: &str) -> Markup {
+    return layout(
+        "Redirecting",
+        html! {
+            meta http-equiv="refresh" content={ "0;url=" (url) };
+            div class="card-body" {
+                h1 class="card-title text-center text-2xl" { "Redirecting" }
+                p { "You are being sent back to the application." }
+                div class="flex justify-end mt-4" {
+                    a href=(url) hx-boost="false" class="btn btn-primary text-white" { "Continue" }
+                }
+            }
+        },
+    );
+}
+
+/// The authorization request can not be answered to the
+/// application, e.g. because its redirect URI is unknown.
+pub fn authorization_error_page(message: &str) -> Markup {
+    return layout(
+        "Authorization failed",
+        html! {
+            div class="card-body" {
+                h1 class="card-title text-center text-2xl" { "Authorization failed" }
+                p { (message) }
+                div class="flex justify-end mt-4" {
+                    a href="/home" class="btn btn-primary text-white" { "Back to home" }
+                }
+            }
+        },
+    );
+}
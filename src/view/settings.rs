@@ -0,0 +1,437 @@
+use super::format::{datetime, optional_datetime};
+use super::input::{csrf_field, Input, InputKind, OnChangeValidation};
+use super::layout::layout;
+use crate::http::{
+    DataExport, KnownDevice, PersonalAccessToken, SignIn, UserSession, PERSONAL_ACCESS_TOKEN_SCOPES,
+};
+use crate::ErrorBag;
+use maud::{html, Markup};
+
+/// The pages of the settings area, in the order of the tabs.
+const PAGES: [(&str, &str); 5] = [
+    ("/settings/account", "Account"),
+    ("/settings/sessions", "Sessions"),
+    ("/settings/activity", "Activity"),
+    ("/settings/tokens", "API tokens"),
+    ("/settings/export", "Your data"),
+];
+
+pub fn settings_layout(title: &str, current: &str, body: Markup) -> Markup {
+    return layout(
+        title,
+        html! {
+            main class="card shadow-md bg-white w-[48rem] max-w-full my-10" {
+                div class="card-body" {
+                    div class="flex justify-between items-center" {
+                        h1 class="card-title text-2xl" { "Settings" }
+                        form method="post" action="/logout" {
+                            (csrf_field())
+                            button type="submit" class="btn btn-ghost btn-sm" { "Logout" }
+                        }
+                    }
+                    nav class="tabs my-4" {
+                        @for (href, label) in PAGES {
+                            a href=(href) class={ "tab tab-bordered " (if href == current { "tab-active" } else { "" }) } {
+                                (label)
+                            }
+                        }
+                    }
+                    (body)
+                }
+            }
+        },
+        None,
+    );
+}
+
+/// The form of the account page that was submitted, with
+/// the value to fill it with again.
+pub enum AccountForm<'a> {
+    Username(&'a str),
+    Email(&'a str),
+    Password,
+    Delete(&'a str),
+}
+
+pub fn account_page(
+    username: &str,
+    email: &str,
+    pending_email: Option<&str>,
+    can_change_password: bool,
+    deletion_grace_days: i64,
+    form: Option<AccountForm>,
+    errors: Option<&ErrorBag>,
+) -> Markup {
+    let errors_of = |name: &str| errors.and_then(|e| e.get(name));
+
+    let username_input = Input::new("Username", "username")
+        .validate_on_change(OnChangeValidation::Username)
+        .value(match form {
+            Some(AccountForm::Username(value)) => value,
+            _ => username,
+        })
+        .errors(errors_of("username"));
+    let email_input = Input::new("Email", "email")
+        .kind(InputKind::Email)
+        .validate_on_change(OnChangeValidation::Email)
+        .value(match form {
+            Some(AccountForm::Email(value)) => value,
+            _ => email,
+        })
+        .errors(errors_of("email"));
+    let current_password_input = Input::new("Current password", "current_password")
+        .kind(InputKind::Password)
+        .errors(errors_of("current_password"));
+    let password_input = Input::new("New password", "password")
+        .kind(InputKind::Password)
+        .errors(errors_of("password"));
+    let password_confirmation_input =
+        Input::new("New password confirmation", "password_confirmation")
+            .kind(InputKind::Password)
+            .errors(errors_of("password_confirmation"));
+    let confirmation_input = Input::new("Type your username to confirm", "confirmation")
+        .value(match form {
+            Some(AccountForm::Delete(value)) => value,
+            _ => "",
+        })
+        .errors(errors_of("confirmation"));
+
+    return settings_layout(
+        "Account",
+        "/settings/account",
+        html! {
+            section {
+                h2 class="text-lg font-bold" { "Username" }
+                form method="post" action="/settings/account/username" novalidate {
+                    (csrf_field())
+                    (username_input)
+                    div class="flex justify-end mt-4" {
+                        button type="submit" class="btn btn-primary text-white" { "Change username" }
+                    }
+                }
+            }
+
+            section class="mt-6" {
+                h2 class="text-lg font-bold" { "Email" }
+                @if let Some(pending_email) = pending_email {
+                    p class="text-gray-500 my-2" {
+                        "Waiting for the confirmation of " strong { (pending_email) } "."
+                    }
+                }
+                form method="post" action="/settings/account/email" novalidate {
+                    (csrf_field())
+                    (email_input)
+                    div class="flex justify-end mt-4" {
+                        button type="submit" class="btn btn-primary text-white" { "Change email" }
+                    }
+                }
+            }
+
+            section class="mt-6" {
+                h2 class="text-lg font-bold" { "Password" }
+                @if can_change_password {
+                    form method="post" action="/settings/account/password" novalidate {
+                        (csrf_field())
+                        (current_password_input)
+                        (password_input)
+                        (password_confirmation_input)
+                        div class="flex justify-end mt-4" {
+                            button type="submit" class="btn btn-primary text-white" { "Change password" }
+                        }
+                    }
+                } @else {
+                    p class="text-gray-500 my-2" {
+                        "Your password is managed by the directory of your organization."
+                    }
+                }
+            }
+
+            section class="mt-6" {
+                h2 class="text-lg font-bold text-red-600" { "Delete account" }
+                p class="text-gray-500 my-2" {
+                    "You will be logged out everywhere and your tokens will be revoked. "
+                    "Your account and its data are removed for good after "
+                    (deletion_grace_days) " days, logging in before then restores it."
+                }
+                form method="post" action="/settings/account/delete" novalidate {
+                    (csrf_field())
+                    (confirmation_input)
+                    div class="flex justify-end mt-4" {
+                        button type="submit" class="btn btn-error" { "Delete account" }
+                    }
+                }
+            }
+        },
+    );
+}
+
+pub fn sessions_page(sessions: &[UserSession], current_id: Option<&str>) -> Markup {
+    return settings_layout(
+        "Sessions",
+        "/settings/sessions",
+        html! {
+            section {
+                h2 class="text-lg font-bold" { "Where you're logged in" }
+                table class="table" {
+                    thead {
+                        tr { th { "Device" } th { "IP address" } th { "Logged in" } th { "Last seen" } th {} }
+                    }
+                    tbody {
+                        @for session in sessions {
+                            tr {
+                                td { (session.device) }
+                                td { (session.ip.as_deref().unwrap_or("Unknown")) }
+                                td { (datetime(&session.created_at)) }
+                                td { (datetime(&session.last_seen_at)) }
+                                td {
+                                    @if Some(session.id.as_str()) == current_id {
+                                        span class="badge badge-primary" { "This device" }
+                                    } @else {
+                                        form method="post" action={ "/settings/sessions/"(session.id)"/delete" } {
+                                            (csrf_field())
+                                            button type="submit" class="btn btn-error btn-sm" { "Log out" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            @if sessions.iter().any(|session| Some(session.id.as_str()) != current_id) {
+                form class="flex justify-end mt-4" method="post" action="/settings/other-sessions/delete" {
+                    (csrf_field())
+                    button type="submit" class="btn btn-error" { "Log out everywhere else" }
+                }
+            }
+        },
+    );
+}
+
+pub fn activity_page(sign_ins: &[SignIn], devices: &[KnownDevice]) -> Markup {
+    return settings_layout(
+        "Activity",
+        "/settings/activity",
+        html! {
+            section {
+                h2 class="text-lg font-bold" { "Recent logins" }
+                @if sign_ins.is_empty() {
+                    p class="text-gray-500 my-2" { "There are no logins to show yet." }
+                } @else {
+                    table class="table" {
+                        thead {
+                            tr { th { "When" } th { "Device" } th { "IP address" } th { "Method" } th {} }
+                        }
+                        tbody {
+                            @for sign_in in sign_ins {
+                                tr {
+                                    td { (datetime(&sign_in.created_at)) }
+                                    td { (sign_in.device) }
+                                    td { (sign_in.ip.as_deref().unwrap_or("Unknown")) }
+                                    td { (sign_in.method.as_deref().unwrap_or("Password")) }
+                                    td {
+                                        @if sign_in.succeeded {
+                                            span class="badge badge-success" { "Succeeded" }
+                                        } @else {
+                                            span class="badge badge-error" { "Failed" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            section class="mt-6" {
+                h2 class="text-lg font-bold" { "Known devices" }
+                p class="text-gray-500 my-2" {
+                    "We email you when your account is logged in to from a device or network "
+                    "that is not on this list."
+                }
+                table class="table" {
+                    thead {
+                        tr { th { "Device" } th { "Network" } th { "First seen" } th { "Last seen" } }
+                    }
+                    tbody {
+                        @for device in devices {
+                            tr {
+                                td { (device.device) }
+                                td { (device.ip_range.as_deref().unwrap_or("Unknown")) }
+                                td { (datetime(&device.first_seen_at)) }
+                                td { (datetime(&device.last_seen_at)) }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    );
+}
+
+pub struct NewTokenForm<'a> {
+    pub name: &'a str,
+    pub scopes: &'a [String],
+    pub expires_in_days: &'a str,
+}
+
+const EXPIRATIONS: [(&str, &str); 5] = [
+    ("7", "7 days"),
+    ("30", "30 days"),
+    ("90", "90 days"),
+    ("365", "1 year"),
+    ("", "No expiration"),
+];
+
+pub fn tokens_page(
+    tokens: &[PersonalAccessToken],
+    form: Option<NewTokenForm>,
+    errors: Option<&ErrorBag>,
+    created_token: Option<&str>,
+) -> Markup {
+    let name_input = Input::new("Name", "name")
+        .value(form.as_ref().map(|f| f.name).unwrap_or(""))
+        .errors(errors.and_then(|e| e.get("name")));
+    let selected_scopes = form.as_ref().map(|f| f.scopes).unwrap_or(&[]);
+    let scope_errors = errors.and_then(|e| e.get("scopes"));
+    let selected_expiration = form.as_ref().map(|f| f.expires_in_days).unwrap_or("30");
+    let expiry_errors = errors.and_then(|e| e.get("expires_in_days"));
+
+    return settings_layout(
+        "API tokens",
+        "/settings/tokens",
+        html! {
+            @if let Some(token) = created_token {
+                div class="alert alert-success flex-col items-start" {
+                    span { "Make sure to copy your new token now. You won't be able to see it again." }
+                    code class="break-all select-all" { (token) }
+                }
+            }
+
+            section {
+                h2 class="text-lg font-bold" { "Your tokens" }
+                @if tokens.is_empty() {
+                    p class="text-gray-500 my-2" { "You don't have any tokens yet." }
+                } @else {
+                    table class="table" {
+                        thead {
+                            tr { th { "Name" } th { "Scopes" } th { "Expires" } th { "Last used" } th {} }
+                        }
+                        tbody {
+                            @for token in tokens {
+                                tr {
+                                    td { (token.name) }
+                                    td { (token.scopes.join(", ")) }
+                                    td { (optional_datetime(token.expires_at.as_ref(), "Never")) }
+                                    td {
+                                        (optional_datetime(token.last_used_at.as_ref(), "Never"))
+                                        @if let Some(ip) = &token.last_used_ip {
+                                            br;
+                                            span class="text-gray-500" { (ip) }
+                                        }
+                                    }
+                                    td {
+                                        form method="post" action={ "/settings/tokens/"(token.id)"/delete" } {
+                                            (csrf_field())
+                                            button type="submit" class="btn btn-error btn-sm" { "Revoke" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            section class="mt-6" {
+                h2 class="text-lg font-bold" { "New token" }
+                form method="post" action="/settings/tokens" novalidate {
+                    (csrf_field())
+                    (name_input)
+                    div class="form-control" {
+                        span class="label" { "Scopes:" }
+                        @for (scope, description) in PERSONAL_ACCESS_TOKEN_SCOPES {
+                            label class="label cursor-pointer justify-start gap-2" {
+                                input type="checkbox" class="checkbox" name="scopes" value=(scope)
+                                    checked[selected_scopes.iter().any(|s| s == scope)];
+                                code { (scope) }
+                                span class="text-gray-500" { (description) }
+                            }
+                        }
+                        @if let Some(errors) = scope_errors {
+                            @for error in errors {
+                                span class="label text-red-500" { (error) }
+                            }
+                        }
+                    }
+                    div class="form-control" {
+                        label class="label" for="expires_in_days" { "Expiration:" }
+                        select id="expires_in_days" name="expires_in_days" class="select select-bordered bg-white" {
+                            @for (value, label) in EXPIRATIONS {
+                                option value=(value) selected[value == selected_expiration] { (label) }
+                            }
+                        }
+                        @if let Some(errors) = expiry_errors {
+                            @for error in errors {
+                                span class="label text-red-500" { (error) }
+                            }
+                        }
+                    }
+                    div class="flex justify-end mt-4" {
+                        button type="submit" class="btn btn-primary text-white" {
+                            span class="loading loading-spinner loading-sm htmx-indicator" {}
+                            "Generate token"
+                        }
+                    }
+                }
+            }
+        },
+    );
+}
+
+pub fn export_page(export: Option<&DataExport>) -> Markup {
+    return settings_layout(
+        "Your data",
+        "/settings/export",
+        html! {
+            section {
+                h2 class="text-lg font-bold" { "Download your data" }
+                p class="text-gray-500 my-2" {
+                    "Get a copy of everything we store about you: your account, sessions, "
+                    "tokens, linked accounts and security events, as JSON files in a ZIP archive."
+                }
+
+                @match export {
+                    Some(DataExport::Building) => {
+                        div class="alert alert-info" {
+                            "Your archive is being prepared, you will receive an email once it is ready."
+                        }
+                    }
+                    Some(DataExport::Ready { path, expires_at }) => {
+                        div class="alert alert-success" {
+                            span {
+                                "Your archive is ready. "
+                                a href=(path) class="underline" hx-boost="false" { "Download it" }
+                                " before " (datetime(expires_at)) "."
+                            }
+                        }
+                    }
+                    Some(DataExport::Failed) => {
+                        div class="alert alert-error" {
+                            "Your archive could not be prepared, please try again."
+                        }
+                    }
+                    None => {}
+                }
+
+                @if !matches!(export, Some(DataExport::Building)) {
+                    form class="flex justify-end mt-4" method="post" action="/settings/export" {
+                        (csrf_field())
+                        button type="submit" class="btn btn-primary text-white" { "Request an archive" }
+                    }
+                }
+            }
+        },
+    );
+}
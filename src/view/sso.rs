@@ -0,0 +1,49 @@
+use super::authentication::layout;
+use super::input::{csrf_field, Input, InputKind};
+use maud::{html, Markup};
+
+/// Ask a user signing in with a provider for the password
+/// of the account already registered with their email.
+pub fn link_account_page(provider_name: &str, email: &str, error: Option<&str>) -> Markup {
+    let errors = error.map(|error| vec![error.to_string()]);
+    let password_input = Input::new("Password", "password")
+        .kind(InputKind::Password)
+        .errors(errors.as_ref());
+
+    return layout(
+        "Link account",
+        html! {
+            form class="card-body" action="/link-account" method="post" novalidate {
+                h1 class="card-title text-center text-2xl" { "Link your account" }
+                p {
+                    "An account is already registered with "
+                    strong { (email) } ". Enter its password to sign in with "
+                    (provider_name) " from now on."
+                }
+                (csrf_field())
+                (password_input)
+                div class="flex justify-end items-center gap-4 mt-4" {
+                    a href="/login" class="underline" { "Cancel" }
+                    button type="submit" class="btn btn-primary text-white" { "Link account" }
+                }
+            }
+        },
+    );
+}
+
+/// The providers of a tenant its users can sign in with.
+pub fn saml_tenant_page(tenant: &str, providers: &[(String, String)]) -> Markup {
+    return layout(
+        "Sign in",
+        html! {
+            div class="card-body gap-2" {
+                h1 class="card-title text-center text-2xl" { "Sign in" }
+                @for (slug, name) in providers {
+                    a href={ "/saml/" (tenant) "/" (slug) "/login" } class="btn btn-outline" hx-boost="false" {
+                        "Sign in with " (name)
+                    }
+                }
+            }
+        },
+    );
+}